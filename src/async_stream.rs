@@ -0,0 +1,236 @@
+use crate::machine::EnigmaMachine;
+use crate::stream::encrypt_into;
+use std::io;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// Async counterpart of `EnigmaReader` for tokio's `AsyncRead`.
+///
+/// Encryption happens inline in `poll_read` on the bytes just read, so it never blocks the
+/// executor, and the rotor state carries over between polls.
+pub struct AsyncEnigmaReader<R> {
+    inner: R,
+    machine: EnigmaMachine,
+    scratch: Vec<u8>,
+    pending: Vec<u8>,
+    consumed: usize,
+    pending_error: Option<io::Error>,
+}
+
+impl<R: AsyncRead + Unpin> AsyncEnigmaReader<R> {
+    pub fn new(inner: R, machine: EnigmaMachine) -> Self {
+        AsyncEnigmaReader {
+            inner,
+            machine,
+            scratch: vec![0; 4096],
+            pending: Vec::new(),
+            consumed: 0,
+            pending_error: None,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn machine(&self) -> &EnigmaMachine {
+        &self.machine
+    }
+
+    /// Returns the underlying reader and the machine in its current rotor state.
+    pub fn into_parts(self) -> (R, EnigmaMachine) {
+        (self.inner, self.machine)
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for AsyncEnigmaReader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.consumed < this.pending.len() {
+                let available = &this.pending[this.consumed..];
+                let len = available.len().min(buf.remaining());
+                buf.put_slice(&available[..len]);
+                this.consumed += len;
+                return Poll::Ready(Ok(()));
+            }
+            if let Some(err) = this.pending_error.take() {
+                return Poll::Ready(Err(err));
+            }
+
+            let mut scratch = ReadBuf::new(&mut this.scratch);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut scratch))?;
+            let len = scratch.filled().len();
+            if len == 0 {
+                return Poll::Ready(Ok(()));
+            }
+            this.pending.clear();
+            this.consumed = 0;
+            if let Err((_, err)) =
+                encrypt_into(&mut this.machine, &this.scratch[..len], &mut this.pending)
+            {
+                this.pending_error = Some(err);
+            }
+        }
+    }
+}
+
+/// Async counterpart of `EnigmaWriter` for tokio's `AsyncWrite`.
+///
+/// Accepted bytes are encrypted once into an internal buffer and drained into the inner writer
+/// on later writes, `flush` or `shutdown`, so a `Pending` inner writer never causes bytes to be
+/// encrypted twice.
+pub struct AsyncEnigmaWriter<W> {
+    inner: W,
+    machine: EnigmaMachine,
+    buffer: Vec<u8>,
+}
+
+impl<W: AsyncWrite + Unpin> AsyncEnigmaWriter<W> {
+    pub fn new(inner: W, machine: EnigmaMachine) -> Self {
+        AsyncEnigmaWriter {
+            inner,
+            machine,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn machine(&self) -> &EnigmaMachine {
+        &self.machine
+    }
+
+    /// Returns the underlying writer and the machine in its current rotor state.
+    ///
+    /// Call `flush` or `shutdown` first, otherwise buffered ciphertext is dropped.
+    pub fn into_parts(self) -> (W, EnigmaMachine) {
+        (self.inner, self.machine)
+    }
+
+    fn poll_drain(&mut self, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        while !self.buffer.is_empty() {
+            match Pin::new(&mut self.inner).poll_write(cx, &self.buffer) {
+                Poll::Ready(Ok(0)) => {
+                    return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+                }
+                Poll::Ready(Ok(written)) => {
+                    self.buffer.drain(..written);
+                }
+                Poll::Ready(Err(err)) => return Poll::Ready(Err(err)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<W: AsyncWrite + Unpin> AsyncWrite for AsyncEnigmaWriter<W> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        if let Poll::Ready(Err(err)) = this.poll_drain(cx) {
+            return Poll::Ready(Err(err));
+        }
+        if !this.buffer.is_empty() {
+            return Poll::Pending;
+        }
+
+        match encrypt_into(&mut this.machine, buf, &mut this.buffer) {
+            Ok(()) => Poll::Ready(Ok(buf.len())),
+            Err((0, err)) => {
+                this.buffer.clear();
+                Poll::Ready(Err(err))
+            }
+            Err((valid, _)) => Poll::Ready(Ok(valid)),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        match this.poll_drain(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.inner).poll_flush(cx),
+            other => other,
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        match this.poll_drain(cx) {
+            Poll::Ready(Ok(())) => Pin::new(&mut this.inner).poll_shutdown(cx),
+            other => other,
+        }
+    }
+}
+
+#[cfg(test)]
+mod async_stream_tests {
+    use super::*;
+    use crate::plugboard::Plugboard;
+    use crate::reflector::reflectors;
+    use crate::rotor::rotors;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    fn setup_enigma_machine() -> EnigmaMachine {
+        EnigmaMachine::new(
+            vec![
+                rotors::type_i('A', 'A'),
+                rotors::type_ii('B', 'A'),
+                rotors::type_iii('C', 'A'),
+            ],
+            reflectors::ukw_b(),
+            Plugboard::new(vec![]).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_async_reader_matches_encrypt_message() {
+        let message = "WETTERVORHERSAGEBISKAYA".repeat(20);
+        let expected = setup_enigma_machine().encrypt_message(&message).unwrap();
+
+        let mut reader = AsyncEnigmaReader::new(message.as_bytes(), setup_enigma_machine());
+        let mut encrypted = String::new();
+        reader.read_to_string(&mut encrypted).await.unwrap();
+
+        assert_eq!(encrypted, expected);
+    }
+
+    #[tokio::test]
+    async fn test_async_writer_round_trips_over_duplex() {
+        let message = "KEINEBESONDERENEREIGNISSE".repeat(50);
+        let (client, server) = tokio::io::duplex(16);
+
+        let writing = tokio::spawn(async move {
+            let mut writer = AsyncEnigmaWriter::new(client, setup_enigma_machine());
+            writer.write_all(message.as_bytes()).await.unwrap();
+            writer.shutdown().await.unwrap();
+            message
+        });
+
+        let mut reader = AsyncEnigmaReader::new(server, setup_enigma_machine());
+        let mut decrypted = String::new();
+        reader.read_to_string(&mut decrypted).await.unwrap();
+
+        assert_eq!(decrypted, writing.await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_async_reader_reports_invalid_byte() {
+        let mut reader = AsyncEnigmaReader::new("ABC9".as_bytes(), setup_enigma_machine());
+        let mut buf = Vec::new();
+
+        let err = reader.read_to_end(&mut buf).await.unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(buf.len(), 3);
+    }
+}
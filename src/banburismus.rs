@@ -0,0 +1,563 @@
+//! Banburismus.
+//!
+//! Naval message keys are the Verfahrenkenngruppe keyed at the day's Grundstellung, one letter
+//! per key press, so two messages whose Verfahrenkenngruppen share their first two letters share
+//! the first two letters of their message keys too. Their right wheels start an unknown number of
+//! steps apart; slid that far against each other, the two texts are in depth and repeat each
+//! other about twice as often as chance.
+//!
+//! Each pair is scored at every offset in decibans, ten times the log10 of the odds the offset
+//! puts the texts in depth. A repeat after a repeat weighs more than a lone one, because repeated
+//! bigrams are rarer by chance than in language; the weights come from an `NgramModel`. Pairs that
+//! clear the threshold go into the scritchmus: chains of third indicator letters placed the
+//! measured number of steps apart on the right wheel.
+//!
+//! A pair can only be in depth if the right wheel does not turn the middle wheel over between
+//! the two message keys. Sliding each chain round a wheel's notches leaves the placements that
+//! break no depth; a wheel with none for some chain cannot be the right wheel, which narrows the
+//! wheel orders. Since the chains are only known up to that slide, wheels with one notch all fit
+//! alike: what the depths rule out is the two-notch wheels VI-VIII, once they span more steps
+//! than lie between the two notches. The middle wheel, found historically by the same means on
+//! pairs that share only their first letter, is left to the Bombe.
+
+use crate::error::EnigmaError;
+use crate::format::ungroup;
+use crate::model::Model;
+use crate::naval::{NavalMessage, NavalTables};
+use crate::ngram::NgramModel;
+use crate::rotor::rotors;
+use rayon::prelude::*;
+use std::fmt;
+
+const LETTERS: usize = 26;
+
+/// Odds of 50 to 1 that a pair is in depth, in decibans.
+pub const THRESHOLD: f64 = 17.0;
+
+/// One message of the day: its Verfahrenkenngruppe and ciphertext.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Intercept {
+    pub verfahrenkenngruppe: String,
+    pub ciphertext: String,
+}
+
+impl Intercept {
+    pub fn new(verfahrenkenngruppe: &str, ciphertext: &str) -> Result<Self, EnigmaError> {
+        if verfahrenkenngruppe.len() < 2
+            || !verfahrenkenngruppe.bytes().all(|b| b.is_ascii_uppercase())
+        {
+            return Err(EnigmaError::InvalidIndicator(format!(
+                "{} is not a Verfahrenkenngruppe",
+                verfahrenkenngruppe
+            )));
+        }
+        let ciphertext = ungroup(ciphertext);
+        if let Some(ch) = ciphertext.chars().find(|ch| !ch.is_ascii_uppercase()) {
+            return Err(EnigmaError::InvalidCharacter(ch));
+        }
+        Ok(Intercept {
+            verfahrenkenngruppe: verfahrenkenngruppe.to_string(),
+            ciphertext,
+        })
+    }
+
+    /// Reads the Verfahrenkenngruppe out of a naval message's indicator groups.
+    pub fn from_naval(tables: &NavalTables, message: &NavalMessage) -> Result<Self, EnigmaError> {
+        let kenngruppen = tables.decode_indicator(&message.indicator)?;
+        Self::new(&kenngruppen.verfahrenkenngruppe, &message.ciphertext)
+    }
+
+    /// Every letter of the Verfahrenkenngruppe but the last, which keys the right wheel.
+    fn prefix(&self) -> &str {
+        &self.verfahrenkenngruppe[..self.verfahrenkenngruppe.len() - 1]
+    }
+
+    fn right_letter(&self) -> u8 {
+        self.verfahrenkenngruppe.as_bytes()[self.verfahrenkenngruppe.len() - 1] - b'A'
+    }
+}
+
+/// A pair of intercepts scored at the offset that best puts them in depth: the right wheel of
+/// `later` starts `offset` steps after that of `earlier`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Depth {
+    pub earlier: String,
+    pub later: String,
+    pub offset: usize,
+    pub repeats: usize,
+    pub overlap: usize,
+    pub decibans: f64,
+}
+
+impl fmt::Display for Depth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} +{:<2} {} repeats in {} letters {:+.1} db",
+            self.earlier, self.later, self.offset, self.repeats, self.overlap, self.decibans
+        )
+    }
+}
+
+/// Decibans for a repeat and a miss, at the start of the overlap and after either.
+#[derive(Debug, Clone, Copy)]
+struct Weights {
+    first: (f64, f64),
+    after_repeat: (f64, f64),
+    after_miss: (f64, f64),
+}
+
+impl Weights {
+    fn new(model: &NgramModel) -> Self {
+        let chance = 1.0 / LETTERS as f64;
+        let weigh = |in_depth: f64| {
+            let in_depth = in_depth.clamp(1e-6, 1.0 - 1e-6);
+            (
+                10.0 * (in_depth / chance).log10(),
+                10.0 * ((1.0 - in_depth) / (1.0 - chance)).log10(),
+            )
+        };
+        let single = model.kappa(1);
+        let double = model.kappa(2);
+        Weights {
+            first: weigh(single),
+            after_repeat: weigh(double / single),
+            after_miss: weigh((single - double) / (1.0 - single)),
+        }
+    }
+}
+
+/// Scores a day of intercepts for depth.
+pub struct Banburismus {
+    intercepts: Vec<Intercept>,
+    weights: Weights,
+    threshold: f64,
+}
+
+impl Banburismus {
+    /// Weighs repeats by the coincidence rates of `model`.
+    pub fn new(intercepts: Vec<Intercept>, model: &NgramModel) -> Self {
+        Banburismus {
+            intercepts,
+            weights: Weights::new(model),
+            threshold: THRESHOLD,
+        }
+    }
+
+    pub fn intercepts(&self) -> &[Intercept] {
+        &self.intercepts
+    }
+
+    pub fn threshold(&self) -> f64 {
+        self.threshold
+    }
+
+    /// Decibans a pair must reach to count as in depth.
+    pub fn set_threshold(&mut self, threshold: f64) {
+        self.threshold = threshold;
+    }
+
+    /// Pairs of intercepts whose right wheels alone may start apart.
+    pub fn pairs(&self) -> Vec<(usize, usize)> {
+        let mut pairs = Vec::new();
+        for (i, first) in self.intercepts.iter().enumerate() {
+            for (j, second) in self.intercepts.iter().enumerate().skip(i + 1) {
+                if first.verfahrenkenngruppe.len() == second.verfahrenkenngruppe.len()
+                    && first.prefix() == second.prefix()
+                    && first.right_letter() != second.right_letter()
+                {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
+    }
+
+    /// Scores `later` slid `offset` letters against `earlier`.
+    pub fn score(&self, earlier: &Intercept, later: &Intercept, offset: usize) -> Depth {
+        let mut repeats = 0;
+        let mut overlap = 0;
+        let mut decibans = 0.0;
+        let mut previous: Option<bool> = None;
+        let aligned = earlier
+            .ciphertext
+            .bytes()
+            .skip(offset)
+            .zip(later.ciphertext.bytes());
+        for (first, second) in aligned {
+            let repeat = first == second;
+            let (hit, miss) = match previous {
+                None => self.weights.first,
+                Some(true) => self.weights.after_repeat,
+                Some(false) => self.weights.after_miss,
+            };
+            decibans += if repeat { hit } else { miss };
+            repeats += repeat as usize;
+            overlap += 1;
+            previous = Some(repeat);
+        }
+        Depth {
+            earlier: earlier.verfahrenkenngruppe.clone(),
+            later: later.verfahrenkenngruppe.clone(),
+            offset,
+            repeats,
+            overlap,
+            decibans,
+        }
+    }
+
+    /// The best offset of every pair that reaches the threshold, best first.
+    pub fn depths(&self) -> Vec<Depth> {
+        let mut depths: Vec<Depth> = self
+            .pairs()
+            .par_iter()
+            .filter_map(|&(i, j)| {
+                let (first, second) = (&self.intercepts[i], &self.intercepts[j]);
+                (1..LETTERS)
+                    .flat_map(|offset| {
+                        [
+                            self.score(first, second, offset),
+                            self.score(second, first, offset),
+                        ]
+                    })
+                    .filter(|depth| depth.overlap > 0)
+                    .max_by(|a, b| a.decibans.total_cmp(&b.decibans))
+            })
+            .filter(|depth| depth.decibans >= self.threshold)
+            .collect();
+        depths.sort_by(|a, b| b.decibans.total_cmp(&a.decibans));
+        depths
+    }
+}
+
+/// Right wheel letters of the Verfahrenkenngruppen, placed on the wheel by the depths.
+#[derive(Debug, Clone)]
+pub struct Scritchmus {
+    /// Chain and step of each letter placed so far.
+    placed: [Option<(usize, usize)>; LETTERS],
+    chains: usize,
+    /// Depths that fit the chains, as the earlier letter and offset.
+    spans: Vec<(u8, usize)>,
+    /// Depths that contradict a better one.
+    pub contradictions: Vec<Depth>,
+}
+
+impl Scritchmus {
+    /// Places letters depth by depth, best first, setting aside depths that contradict the
+    /// chains built so far.
+    pub fn new(depths: &[Depth]) -> Self {
+        let mut scritchmus = Scritchmus {
+            placed: [None; LETTERS],
+            chains: 0,
+            spans: Vec::new(),
+            contradictions: Vec::new(),
+        };
+        for depth in depths {
+            let earlier = right_letter(&depth.earlier);
+            let later = right_letter(&depth.later);
+            if scritchmus.place(earlier, later, depth.offset) {
+                scritchmus.spans.push((earlier, depth.offset));
+            } else {
+                scritchmus.contradictions.push(depth.clone());
+            }
+        }
+        scritchmus
+    }
+
+    /// Puts `later` `offset` steps after `earlier`, merging chains; false if they disagree.
+    fn place(&mut self, earlier: u8, later: u8, offset: usize) -> bool {
+        let (earlier, later) = (earlier as usize, later as usize);
+        match (self.placed[earlier], self.placed[later]) {
+            (None, None) => {
+                self.placed[earlier] = Some((self.chains, 0));
+                self.placed[later] = Some((self.chains, offset));
+                self.chains += 1;
+                true
+            }
+            (Some((chain, step)), None) => {
+                self.placed[later] = Some((chain, (step + offset) % LETTERS));
+                true
+            }
+            (None, Some((chain, step))) => {
+                self.placed[earlier] = Some((chain, (step + LETTERS - offset) % LETTERS));
+                true
+            }
+            (Some((chain, step)), Some((other, other_step))) if chain == other => {
+                (step + offset) % LETTERS == other_step
+            }
+            (Some((chain, step)), Some((other, other_step))) => {
+                let shift = (step + offset + LETTERS - other_step) % LETTERS;
+                let taken: Vec<usize> = self
+                    .placed
+                    .iter()
+                    .filter_map(|&placed| placed.filter(|&(c, _)| c == chain).map(|(_, s)| s))
+                    .collect();
+                let collides = self.placed.iter().any(|&placed| {
+                    placed.is_some_and(|(c, s)| {
+                        c == other && taken.contains(&((s + shift) % LETTERS))
+                    })
+                });
+                if collides {
+                    return false;
+                }
+                for placed in self.placed.iter_mut().flatten() {
+                    if placed.0 == other {
+                        *placed = (chain, (placed.1 + shift) % LETTERS);
+                    }
+                }
+                true
+            }
+        }
+    }
+
+    /// The chains, each as the letters on the right wheel step by step from its first, with
+    /// dots for steps not yet known.
+    pub fn chains(&self) -> Vec<String> {
+        let mut chains: Vec<String> = (0..self.chains)
+            .filter_map(|chain| {
+                let mut steps = ['.'; LETTERS];
+                for (letter, placed) in self.placed.iter().enumerate() {
+                    if let Some((c, step)) = placed {
+                        if *c == chain {
+                            steps[*step] = (b'A' + letter as u8) as char;
+                        }
+                    }
+                }
+                let start = steps.iter().position(|&ch| ch != '.')?;
+                steps.rotate_left(start);
+                Some(
+                    steps
+                        .iter()
+                        .collect::<String>()
+                        .trim_end_matches('.')
+                        .to_string(),
+                )
+            })
+            .collect();
+        chains.sort_by_key(|chain| std::cmp::Reverse(chain.len()));
+        chains
+    }
+
+    /// Ways to lay the chains on `wheel` (a name as for `rotors::from_name`) in the right
+    /// slot without a turnover inside any depth; none rules the wheel out.
+    pub fn placements(&self, wheel: &str) -> Result<usize, EnigmaError> {
+        let turnovers = turnovers(wheel)?;
+        let mut placements = 1;
+        for chain in (0..self.chains).filter(|&chain| self.is_live(chain)) {
+            let spans: Vec<(usize, usize)> = self
+                .spans
+                .iter()
+                .filter_map(|&(letter, offset)| match self.placed[letter as usize] {
+                    Some((c, step)) if c == chain => Some((step, offset)),
+                    _ => None,
+                })
+                .collect();
+            placements *= (0..LETTERS)
+                .filter(|shift| {
+                    spans.iter().all(|&(step, offset)| {
+                        (1..=offset).all(|k| !turnovers[(step + shift + k) % LETTERS])
+                    })
+                })
+                .count();
+        }
+        Ok(placements)
+    }
+
+    /// Placements for every wheel `model` can put in the right slot.
+    pub fn right_wheels(&self, model: Model) -> Vec<(&'static str, usize)> {
+        model
+            .wheels()
+            .iter()
+            .map(|&wheel| (wheel, self.placements(wheel).unwrap_or(0)))
+            .collect()
+    }
+
+    /// Whether any letter still sits on `chain`, which merging may have emptied.
+    fn is_live(&self, chain: usize) -> bool {
+        self.placed
+            .iter()
+            .any(|&placed| placed.is_some_and(|(c, _)| c == chain))
+    }
+}
+
+/// Wheel orders of `model` with one of `right_wheels` in the right slot.
+pub fn wheel_orders(model: Model, right_wheels: &[&str]) -> Vec<Vec<&'static str>> {
+    model
+        .wheel_orders()
+        .into_iter()
+        .filter(|order| {
+            order
+                .last()
+                .is_some_and(|wheel| right_wheels.contains(wheel))
+        })
+        .collect()
+}
+
+/// Whether the wheel turns the next one over on arriving at each step from A.
+fn turnovers(wheel: &str) -> Result<[bool; LETTERS], EnigmaError> {
+    let mut rotor = rotors::from_name(wheel, 'A', 'A')
+        .ok_or_else(|| EnigmaError::InvalidSearch(format!("unknown wheel {}", wheel)))?;
+    let mut turnovers = [false; LETTERS];
+    for step in 1..=LETTERS {
+        turnovers[step % LETTERS] = rotor.turn();
+    }
+    Ok(turnovers)
+}
+
+fn right_letter(verfahrenkenngruppe: &str) -> u8 {
+    verfahrenkenngruppe.as_bytes()[verfahrenkenngruppe.len() - 1] - b'A'
+}
+
+#[cfg(test)]
+mod banburismus_tests {
+    use super::*;
+    use crate::machine::EnigmaMachine;
+    use crate::naval::Kenngruppen;
+    use crate::ngram::{normalize, Language};
+    use crate::plugboard::Plugboard;
+    use crate::reflector::reflectors;
+    use crate::rotor::Rotor;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const GRUNDSTELLUNG: &str = "QDV";
+
+    fn daily_key() -> EnigmaMachine {
+        let plugboard = Plugboard::new(vec![('A', 'M'), ('F', 'I'), ('N', 'V'), ('P', 'S')]);
+        EnigmaMachine::new(
+            vec![
+                rotors::type_vi('A', 'C'),
+                rotors::type_ii('A', 'K'),
+                rotors::type_iv('A', 'T'),
+            ],
+            reflectors::ukw_b(),
+            plugboard.unwrap(),
+        )
+    }
+
+    /// Messages of 600 letters under Verfahrenkenngruppen VF? and KQ?, each with its message key.
+    /// The texts run round the corpus from starts too far apart to line up within a wheel turn.
+    fn intercepts() -> Vec<(Intercept, String)> {
+        let tables = NavalTables::generate(&mut StdRng::seed_from_u64(1941), 10);
+        let text = normalize(include_str!("../data/german.txt"), Some('X'));
+        let mut machine = daily_key();
+        ["VF", "KQ"]
+            .iter()
+            .flat_map(|prefix| {
+                "ABCDEFGH"
+                    .chars()
+                    .map(move |last| format!("{}{}", prefix, last))
+            })
+            .enumerate()
+            .map(|(i, verfahrenkenngruppe)| {
+                let kenngruppen = Kenngruppen {
+                    schluesselkenngruppe: "AAA".to_string(),
+                    verfahrenkenngruppe: verfahrenkenngruppe.clone(),
+                };
+                let plaintext: String = text.chars().cycle().skip(i * 325).take(600).collect();
+                let message = tables
+                    .encrypt_with(
+                        &mut machine,
+                        GRUNDSTELLUNG,
+                        &kenngruppen,
+                        ('X', 'Y'),
+                        &plaintext,
+                    )
+                    .unwrap();
+                let key = tables
+                    .message_key(&mut machine, GRUNDSTELLUNG, &verfahrenkenngruppe)
+                    .unwrap();
+                (Intercept::from_naval(&tables, &message).unwrap(), key)
+            })
+            .collect()
+    }
+
+    /// Steps the right wheel takes from one message key to the other.
+    fn steps(wheel: fn(char, char) -> Rotor, from: &str, to: &str) -> usize {
+        let mut rotor = wheel(from.chars().last().unwrap(), 'A');
+        let to = to.chars().last().unwrap();
+        (1..=LETTERS)
+            .find(|_| {
+                rotor.turn();
+                rotor.position() == to
+            })
+            .unwrap()
+    }
+
+    #[test]
+    fn test_intercept() {
+        let intercept = Intercept::new("VFK", "ABCDE FGHIJ").unwrap();
+        assert_eq!(intercept.ciphertext, "ABCDEFGHIJ");
+        assert_eq!(intercept.prefix(), "VF");
+        assert!(Intercept::new("V", "ABC").is_err());
+        assert!(Intercept::new("VFK", "AB1").is_err());
+    }
+
+    #[test]
+    fn test_score() {
+        let banburismus = Banburismus::new(Vec::new(), Language::German.model());
+        let text = Intercept::new("VFK", "KEINEXBESONDERENXEREIGNISSE").unwrap();
+        let shifted = Intercept::new("VFL", "XXXKEINEXBESONDERENXEREIGNISSE").unwrap();
+        let depth = banburismus.score(&shifted, &text, 3);
+        assert_eq!((depth.repeats, depth.overlap), (27, 27));
+        assert!(depth.decibans > 100.0);
+        assert!(banburismus.score(&shifted, &text, 4).decibans < 0.0);
+        assert_eq!(
+            depth.to_string(),
+            format!(
+                "VFL VFK +3  27 repeats in 27 letters {:+.1} db",
+                depth.decibans
+            )
+        );
+    }
+
+    #[test]
+    fn test_depths_and_right_wheel() {
+        let intercepts = intercepts();
+        let keys: Vec<(String, String)> = intercepts
+            .iter()
+            .map(|(intercept, key)| (intercept.verfahrenkenngruppe.clone(), key.clone()))
+            .collect();
+        let key = |verfahrenkenngruppe: &str| {
+            keys.iter()
+                .find(|(v, _)| v == verfahrenkenngruppe)
+                .map(|(_, key)| key.clone())
+                .unwrap()
+        };
+        let banburismus = Banburismus::new(
+            intercepts
+                .into_iter()
+                .map(|(intercept, _)| intercept)
+                .collect(),
+            Language::German.model(),
+        );
+        assert_eq!(banburismus.pairs().len(), 2 * 28);
+
+        let depths = banburismus.depths();
+        assert!(depths.len() >= 10);
+        for depth in &depths {
+            assert_eq!(
+                depth.offset,
+                steps(rotors::type_iv, &key(&depth.earlier), &key(&depth.later)),
+                "{}",
+                depth
+            );
+        }
+
+        let scritchmus = Scritchmus::new(&depths);
+        assert!(scritchmus.contradictions.is_empty());
+        assert!(!scritchmus.chains().is_empty());
+        let right_wheels = scritchmus.right_wheels(Model::M3);
+        let survivors: Vec<&str> = right_wheels
+            .iter()
+            .filter(|&&(_, placements)| placements > 0)
+            .map(|&(wheel, _)| wheel)
+            .collect();
+        // One-notch wheels fit alike; the depths span too far for the two-notch ones
+        assert_eq!(survivors, ["i", "ii", "iii", "iv", "v"]);
+        assert!(wheel_orders(Model::M3, &survivors)
+            .iter()
+            .all(|order| survivors.contains(&order[2])));
+        assert!(scritchmus.placements("x").is_err());
+    }
+}
@@ -0,0 +1,531 @@
+//! Turing–Welchman Bombe.
+//!
+//! A crib (guessed plaintext) lined up against the ciphertext gives a *menu*: a graph on the
+//! letters with one edge per crib letter, labelled by the key press that joins plaintext and
+//! ciphertext letter. Loops in the menu are what make it strong.
+//!
+//! For every rotor position the Bombe wires one drum stack (scrambler) per menu edge between
+//! the 26-wire registers of its two letters, and the diagonal board joins wire `x` of register
+//! `y` to wire `y` of register `x`, since plugboard connections are reciprocal. Energising a
+//! hypothesis "the test letter is steckered to `x`" lights everything it implies. Where that
+//! never lights two wires of one register, the hypothesis is consistent and the Bombe stops;
+//! the lit wires are the plugboard connections it deduces.
+//!
+//! Because the reflector has no fixed points, no letter ever encrypts to itself. `crib_drag`
+//! uses that to rule out every placement of a crib where a crib letter sits over the same
+//! ciphertext letter, and ranks the rest by how strong a menu they make.
+//!
+//! The drum stacks here are this crate's own `Rotor`s and `Reflector`, stepped exactly as the
+//! machine steps them, so turnovers inside the crib need no special handling. Ring settings are
+//! fixed per run; the search covers every start position of every wheel order.
+
+use crate::error::EnigmaError;
+use crate::machine;
+use crate::model::Model;
+use crate::reflector::{reflectors, Reflector};
+use crate::rotor::{rotors, Rotor};
+use crate::scrambler::Scrambler;
+use rayon::prelude::*;
+use std::fmt;
+
+const LETTERS: usize = 26;
+const WIRES: usize = LETTERS * LETTERS;
+
+/// One menu edge: at key press `offset + 1` the plaintext letter `plain` came out as `cipher`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MenuEdge {
+    pub plain: char,
+    pub cipher: char,
+    pub offset: usize,
+}
+
+/// The letter graph of a crib placed at `offset` in a ciphertext.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Menu {
+    pub crib: String,
+    pub offset: usize,
+    pub edges: Vec<MenuEdge>,
+}
+
+impl Menu {
+    /// Lines `crib` up against `ciphertext` starting at letter `offset`, rejecting placements
+    /// where a letter would encrypt to itself.
+    pub fn new(crib: &str, ciphertext: &str, offset: usize) -> Result<Self, EnigmaError> {
+        let crib: String = crib.chars().filter(|ch| !ch.is_whitespace()).collect();
+        let ciphertext: Vec<char> = ciphertext
+            .chars()
+            .filter(|ch| !ch.is_whitespace())
+            .collect();
+        if let Some(invalid) = crib
+            .chars()
+            .chain(ciphertext.iter().copied())
+            .find(|ch| !ch.is_ascii_uppercase())
+        {
+            return Err(EnigmaError::InvalidCharacter(invalid));
+        }
+        if crib.is_empty() || offset + crib.len() > ciphertext.len() {
+            return Err(EnigmaError::InvalidCrib(format!(
+                "a {} letter crib does not fit at offset {} of {} letters",
+                crib.len(),
+                offset,
+                ciphertext.len()
+            )));
+        }
+
+        let mut edges = Vec::new();
+        for (index, plain) in crib.chars().enumerate() {
+            let cipher = ciphertext[offset + index];
+            if plain == cipher {
+                return Err(EnigmaError::InvalidCrib(format!(
+                    "'{}' would encrypt to itself at offset {}",
+                    plain,
+                    offset + index
+                )));
+            }
+            edges.push(MenuEdge {
+                plain,
+                cipher,
+                offset: offset + index,
+            });
+        }
+        Ok(Menu {
+            crib,
+            offset,
+            edges,
+        })
+    }
+
+    /// Number of distinct letters on the menu.
+    pub fn letters(&self) -> usize {
+        self.letter_mask().count_ones() as usize
+    }
+
+    /// Number of independent loops: edges beyond those a spanning forest needs.
+    pub fn loops(&self) -> usize {
+        let mut parent: Vec<usize> = (0..LETTERS).collect();
+        let mut joins = 0;
+        for edge in &self.edges {
+            let (a, b) = (index(edge.plain), index(edge.cipher));
+            let (a, b) = (find(&mut parent, a), find(&mut parent, b));
+            if a != b {
+                parent[a] = b;
+                joins += 1;
+            }
+        }
+        self.edges.len() - joins
+    }
+
+    /// The letter the Bombe tests: the one with most edges.
+    pub fn test_letter(&self) -> char {
+        let mut degree = [0; LETTERS];
+        for edge in &self.edges {
+            degree[index(edge.plain)] += 1;
+            degree[index(edge.cipher)] += 1;
+        }
+        let best = (0..LETTERS)
+            .max_by_key(|&i| (degree[i], LETTERS - i))
+            .unwrap_or(0);
+        letter(best)
+    }
+
+    /// Turing's estimate of the stops one wheel order gives: each loop closes on itself for
+    /// only one position in 26, so every loop cuts the `26^rotors` positions by that factor.
+    pub fn expected_stops(&self, rotors: usize) -> f64 {
+        (LETTERS as f64).powi(rotors as i32 - self.loops() as i32)
+    }
+
+    fn letter_mask(&self) -> u32 {
+        self.edges.iter().fold(0, |mask, edge| {
+            mask | 1 << index(edge.plain) | 1 << index(edge.cipher)
+        })
+    }
+}
+
+impl fmt::Display for Menu {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Menu for {} at offset {}: {} letters, {} loops, test letter {}",
+            self.crib,
+            self.offset,
+            self.letters(),
+            self.loops(),
+            self.test_letter()
+        )?;
+        for edge in &self.edges {
+            writeln!(f, "{:>4}  {}-{}", edge.offset + 1, edge.plain, edge.cipher)?;
+        }
+        Ok(())
+    }
+}
+
+/// Slides `crib` along `ciphertext` and returns the menu of every placement where no letter
+/// would encrypt to itself, the strongest (fewest expected stops) first.
+pub fn crib_drag(crib: &str, ciphertext: &str) -> Result<Vec<Menu>, EnigmaError> {
+    let letters = ciphertext.chars().filter(|ch| !ch.is_whitespace()).count();
+    let crib_len = crib.chars().filter(|ch| !ch.is_whitespace()).count();
+    if crib_len == 0 || crib_len > letters {
+        return Err(EnigmaError::InvalidCrib(format!(
+            "a {} letter crib does not fit in {} letters",
+            crib_len, letters
+        )));
+    }
+
+    let mut menus = Vec::new();
+    for offset in 0..=letters - crib_len {
+        match Menu::new(crib, ciphertext, offset) {
+            Ok(menu) => menus.push(menu),
+            Err(EnigmaError::InvalidCrib(_)) => {}
+            Err(err) => return Err(err),
+        }
+    }
+    menus.sort_by_key(|menu| (std::cmp::Reverse(menu.loops()), menu.offset));
+    Ok(menus)
+}
+
+/// A rotor setting the Bombe stopped at, with the plugboard connections it deduced.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Stop {
+    /// Rotor models, leftmost first.
+    pub wheels: Vec<String>,
+    pub reflector: String,
+    pub rings: String,
+    /// Start positions of the message, as passed to `EnigmaMachine::set_positions`.
+    pub positions: String,
+    /// Deduced connections; a letter paired with itself is unplugged.
+    pub steckers: Vec<(char, char)>,
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let steckers: Vec<String> = self
+            .steckers
+            .iter()
+            .map(|&(a, b)| format!("{}{}", a, b))
+            .collect();
+        write!(
+            f,
+            "{} {} rings {} positions {} steckers {}",
+            self.wheels.join(" "),
+            self.reflector,
+            self.rings,
+            self.positions,
+            steckers.join(" ")
+        )
+    }
+}
+
+pub struct Bombe {
+    menu: Menu,
+}
+
+impl Bombe {
+    pub fn new(menu: Menu) -> Self {
+        Bombe { menu }
+    }
+
+    pub fn menu(&self) -> &Menu {
+        &self.menu
+    }
+
+    /// Runs every start position of one wheel order. The rotors' ring settings are kept and
+    /// their positions ignored.
+    pub fn run(&self, rotors: &[Rotor], reflector: &Reflector) -> Vec<Stop> {
+        let mut stack = rotors.to_vec();
+        let mut scrambler = Scrambler::new();
+        let mut diagonal_board = [0; WIRES];
+        for (wire, parent) in diagonal_board.iter_mut().enumerate() {
+            let (register, line) = (wire / LETTERS, wire % LETTERS);
+            *parent = (line * LETTERS + register).min(wire) as u16;
+        }
+        let test = index(self.menu.test_letter());
+        let mut permutations = vec![[0; LETTERS]; self.menu.edges.len()];
+        let mut stops = Vec::new();
+
+        for start in 0..LETTERS.pow(stack.len() as u32) {
+            let positions = positions(start, stack.len());
+            for (rotor, position) in stack.iter_mut().zip(positions.chars()) {
+                rotor.set_position(position);
+            }
+            let mut pressed = 0;
+            for (edge, permutation) in self.menu.edges.iter().zip(permutations.iter_mut()) {
+                while pressed <= edge.offset {
+                    machine::step(&mut stack);
+                    pressed += 1;
+                }
+                for (input, output) in permutation.iter_mut().enumerate() {
+                    *output = scrambler
+                        .scramble(&stack, reflector, input as u8)
+                        .unwrap_or(input as u8);
+                }
+            }
+
+            let mut parent = diagonal_board;
+            for (edge, permutation) in self.menu.edges.iter().zip(&permutations) {
+                let (plain, cipher) = (index(edge.plain), index(edge.cipher));
+                for (line, &scrambled) in permutation.iter().enumerate() {
+                    union(
+                        &mut parent,
+                        plain * LETTERS + line,
+                        cipher * LETTERS + scrambled as usize,
+                    );
+                }
+            }
+
+            for hypothesis in consistent_hypotheses(&mut parent, test) {
+                stops.push(Stop {
+                    wheels: rotors
+                        .iter()
+                        .map(|rotor| rotor.model().to_string())
+                        .collect(),
+                    reflector: reflector.model().to_string(),
+                    rings: rotors.iter().map(Rotor::ring).collect(),
+                    positions: positions.clone(),
+                    steckers: hypothesis,
+                });
+            }
+        }
+        stops
+    }
+
+    /// Runs every wheel order of `model` in parallel, with `rings` on every order.
+    pub fn search(
+        &self,
+        model: Model,
+        rings: &str,
+        reflector: &str,
+    ) -> Result<Vec<Stop>, EnigmaError> {
+        let orders: Vec<Vec<&str>> = model.wheel_orders();
+        self.search_orders(&orders, rings, reflector)
+    }
+
+    /// Runs the given wheel orders (wheel names as for `rotors::from_name`) in parallel.
+    pub fn search_orders(
+        &self,
+        orders: &[Vec<&str>],
+        rings: &str,
+        reflector: &str,
+    ) -> Result<Vec<Stop>, EnigmaError> {
+        let reflector = reflectors::by_name(reflector)
+            .ok_or_else(|| EnigmaError::InvalidCrib(format!("unknown reflector {}", reflector)))?;
+        let stacks = orders
+            .iter()
+            .map(|order| wheel_stack(order, rings))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(stacks
+            .par_iter()
+            .flat_map_iter(|stack| self.run(stack, &reflector))
+            .collect())
+    }
+}
+
+/// Builds the rotors for a wheel order, each at position `A` with its ring from `rings`.
+pub fn wheel_stack(order: &[&str], rings: &str) -> Result<Vec<Rotor>, EnigmaError> {
+    if rings.chars().count() != order.len() {
+        return Err(EnigmaError::PositionCount {
+            expected: order.len(),
+            found: rings.chars().count(),
+        });
+    }
+    order
+        .iter()
+        .zip(rings.chars())
+        .map(|(name, ring)| {
+            if !ring.is_ascii_uppercase() {
+                return Err(EnigmaError::InvalidPosition(ring));
+            }
+            rotors::from_name(name, 'A', ring)
+                .ok_or_else(|| EnigmaError::InvalidCrib(format!("unknown wheel {}", name)))
+        })
+        .collect()
+}
+
+/// Every hypothesis for the test register whose lit wires never put two wires of one
+/// register live, as the plugboard connections it implies.
+fn consistent_hypotheses(parent: &mut [u16; WIRES], test: usize) -> Vec<Vec<(char, char)>> {
+    let roots: Vec<usize> = (0..LETTERS)
+        .map(|line| find16(parent, test * LETTERS + line))
+        .collect();
+    let mut stops = Vec::new();
+    for &root in &roots {
+        if roots.iter().filter(|&&other| other == root).count() != 1 {
+            continue;
+        }
+        let mut lit = [0u8; LETTERS];
+        let mut steckers = Vec::new();
+        let mut consistent = true;
+        for wire in 0..WIRES {
+            if find16(parent, wire) != root {
+                continue;
+            }
+            let (register, line) = (wire / LETTERS, wire % LETTERS);
+            lit[register] += 1;
+            if lit[register] > 1 {
+                consistent = false;
+                break;
+            }
+            if register <= line {
+                steckers.push((letter(register), letter(line)));
+            }
+        }
+        if consistent {
+            stops.push(steckers);
+        }
+    }
+    stops
+}
+
+fn positions(start: usize, rotors: usize) -> String {
+    (0..rotors)
+        .rev()
+        .map(|place| letter(start / LETTERS.pow(place as u32) % LETTERS))
+        .collect()
+}
+
+fn union(parent: &mut [u16; WIRES], a: usize, b: usize) {
+    let (a, b) = (find16(parent, a), find16(parent, b));
+    if a != b {
+        parent[a.max(b)] = a.min(b) as u16;
+    }
+}
+
+fn find16(parent: &mut [u16; WIRES], mut wire: usize) -> usize {
+    while parent[wire] as usize != wire {
+        let grandparent = parent[parent[wire] as usize];
+        parent[wire] = grandparent;
+        wire = grandparent as usize;
+    }
+    wire
+}
+
+fn find(parent: &mut [usize], mut node: usize) -> usize {
+    while parent[node] != node {
+        parent[node] = parent[parent[node]];
+        node = parent[node];
+    }
+    node
+}
+
+fn index(ch: char) -> usize {
+    (ch as u8 - b'A') as usize
+}
+
+fn letter(index: usize) -> char {
+    (b'A' + index as u8) as char
+}
+
+#[cfg(test)]
+mod bombe_tests {
+    use super::*;
+    use crate::machine::EnigmaMachine;
+    use crate::plugboard::Plugboard;
+
+    const CRIB: &str = "WETTERVORHERSAGEBISKAYA";
+
+    fn intercept() -> String {
+        let wheels = vec![
+            rotors::type_ii('A', 'A'),
+            rotors::type_v('A', 'A'),
+            rotors::type_iii('A', 'A'),
+        ];
+        intercept_on(wheels, "QEV")
+    }
+
+    fn intercept_on(wheels: Vec<Rotor>, positions: &str) -> String {
+        let mut machine = EnigmaMachine::new(
+            wheels,
+            reflectors::ukw_b(),
+            Plugboard::new(vec![
+                ('A', 'T'),
+                ('B', 'L'),
+                ('E', 'Q'),
+                ('K', 'R'),
+                ('S', 'X'),
+            ])
+            .unwrap(),
+        );
+        machine.set_positions(positions).unwrap();
+        machine.encrypt_message(&format!("ANX{}", CRIB)).unwrap()
+    }
+
+    #[test]
+    fn test_menu() {
+        let menu = Menu::new("WETTER", "QQQXYZABCQQQ", 3).unwrap();
+        assert_eq!(
+            menu.edges[0],
+            MenuEdge {
+                plain: 'W',
+                cipher: 'X',
+                offset: 3
+            }
+        );
+        assert_eq!(menu.letters(), 10);
+        assert_eq!(menu.test_letter(), 'E');
+
+        let menu = Menu::new("ABCA", "BCAD", 0).unwrap();
+        assert_eq!(menu.loops(), 1);
+    }
+
+    #[test]
+    fn test_menu_rejects_crash_and_overrun() {
+        assert!(matches!(
+            Menu::new("WETTER", "XEXXXX", 0),
+            Err(EnigmaError::InvalidCrib(_))
+        ));
+        assert!(Menu::new("WETTER", "ABCDEFG", 2).is_err());
+    }
+
+    #[test]
+    fn test_crib_drag() {
+        let ciphertext = intercept();
+        let menus = crib_drag(CRIB, &ciphertext).unwrap();
+        assert!(menus.iter().any(|menu| menu.offset == 3));
+        assert!(menus.len() < ciphertext.len() - CRIB.len() + 1);
+        for menu in &menus {
+            for edge in &menu.edges {
+                assert_ne!(edge.plain, edge.cipher);
+            }
+        }
+        assert!(menus
+            .windows(2)
+            .all(|pair| pair[0].loops() >= pair[1].loops()));
+
+        assert!(crib_drag("TOOLONG", "ABC").is_err());
+        assert_eq!(crib_drag("AAA", "AAAAA").unwrap(), Vec::new());
+    }
+
+    /// Runs the Bombe over one wheel order and checks it stops at `positions` with the plugs
+    /// of `intercept_on`.
+    fn assert_stops_at(order: &[&str], positions: &str, rings: &str) {
+        let wheels = wheel_stack(order, rings).unwrap();
+        let bombe =
+            Bombe::new(Menu::new(CRIB, &intercept_on(wheels.clone(), positions), 3).unwrap());
+        let stops = bombe.run(&wheels, &reflectors::ukw_b());
+
+        let stop = stops
+            .iter()
+            .find(|stop| stop.positions == positions)
+            .expect("no stop at the true key");
+        let models: Vec<_> = wheels
+            .iter()
+            .map(|wheel| wheel.model().to_string())
+            .collect();
+        assert_eq!(stop.wheels, models);
+        for pair in [('A', 'T'), ('E', 'Q'), ('K', 'R'), ('S', 'X')] {
+            assert!(stop.steckers.contains(&pair), "{:?} not deduced", pair);
+        }
+        assert!(stops.len() < 50, "{} stops", stops.len());
+    }
+
+    #[test]
+    fn test_bombe_stops_at_the_key() {
+        // Two wheels keep the run to 26^2 start positions
+        assert_stops_at(&["v", "iii"], "EV", "AA");
+    }
+
+    #[test]
+    #[ignore = "runs 26^3 start positions; run with --ignored"]
+    fn test_bombe_stops_at_the_key_three_wheels() {
+        assert_stops_at(&["ii", "v", "iii"], "QEV", "AAA");
+    }
+}
@@ -0,0 +1,614 @@
+//! Ciphertext-only attack, after Gillogly (1995) and Weierud and Sullivan (2005).
+//!
+//! Without a crib the only handle on the key is how much a trial decryption looks like
+//! language. The search runs in three phases:
+//!
+//! 1. Every wheel order, reflector and start position is tried without a plugboard and scored
+//!    by index of coincidence. Letters that are not plugged still come out right, which lifts
+//!    the IoC of the true setting above that of random text.
+//! 2. The best candidates get the rings of their slower wheels searched, again by IoC.
+//! 3. The plugboard is hill-climbed from empty: any swap of two letters that improves the score
+//!    is kept, scoring first by IoC, then by bigram, trigram and quadgram log-probabilities.
+//!
+//! This machine adds the ring to the wiring offset while the window letter alone decides the
+//! stepping, and a wheel steps through its window letters in wiring order. A wrong ring on a
+//! wheel that moves during the message therefore garbles every letter after its first step, so
+//! unlike on the real machine the rings of the two fastest wheels cannot be left to a later
+//! hill climb. Phase 1 searches them with the start positions, but only decrypts once per
+//! offset of the middle wheel: the letters of each run between two of its steps are counted
+//! per offset, and each of the 26^2 window letters and rings of the middle wheel is scored by
+//! adding up the counts of its offsets. That costs about as much as 26^4 trial decryptions per
+//! wheel order and reflector for three wheels. Phase 1 moves the slower wheels on the middle
+//! wheel's first carry only, and they keep ring `A` until phase 2, so a message that steps the
+//! slow wheel early scores low. Known rings (from a captured key sheet, say) cut phase 1 to the
+//! 26^3 start positions.
+//!
+//! Phase 1 decrypts through lookup tables built from the `Rotor`s and stepped the way
+//! `EnigmaMachine` steps them; phases 2 and 3 drive an `EnigmaMachine`. The n-gram statistics
+//! are German unless another `NgramModel` is set.
+
+use crate::bombe::Stop;
+use crate::error::EnigmaError;
+use crate::machine::EnigmaMachine;
+use crate::model::Model;
+use crate::ngram::{counts_ioc, ioc, Language, NgramModel};
+use crate::plugboard::Plugboard;
+use crate::reflector::Reflector;
+use crate::rotor::rotors;
+use crate::tables::{Runs, Stack, Stacks};
+use rayon::prelude::*;
+
+const LETTERS: usize = 26;
+
+/// Candidates from phase 1 that go on to ring search and hill climbing.
+pub const CANDIDATES: usize = 10;
+
+/// The fastest wheels, whose rings phase 1 sweeps.
+const SWEPT_RINGS: usize = 2;
+
+/// What a crack recovered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Solution {
+    /// The key, in the form the Bombe reports its stops; only plugged pairs are listed.
+    pub key: Stop,
+    pub plaintext: String,
+    /// Mean quadgram log-probability per letter of the plaintext.
+    pub score: f64,
+}
+
+pub struct Cracker<'a> {
+    ciphertext: Vec<u8>,
+    candidates: usize,
+    model: &'a NgramModel,
+}
+
+impl<'a> Cracker<'a> {
+    /// Takes the ciphertext with any grouping; everything but whitespace must be `A-Z`.
+    pub fn new(ciphertext: &str) -> Result<Self, EnigmaError> {
+        let ciphertext = ciphertext
+            .chars()
+            .filter(|ch| !ch.is_whitespace())
+            .map(|ch| {
+                ch.is_ascii_uppercase()
+                    .then(|| index(ch))
+                    .ok_or(EnigmaError::InvalidCharacter(ch))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Cracker {
+            ciphertext,
+            candidates: CANDIDATES,
+            model: Language::German.model(),
+        })
+    }
+
+    pub fn candidates(&self) -> usize {
+        self.candidates
+    }
+
+    /// Sets how many phase 1 candidates go on to ring search and hill climbing.
+    pub fn set_candidates(&mut self, candidates: usize) {
+        self.candidates = candidates.max(1);
+    }
+
+    /// Sets the statistics the plugboard is hill-climbed against.
+    pub fn set_model(&mut self, model: &'a NgramModel) {
+        self.model = model;
+    }
+
+    /// Searches every wheel order and reflector of `model`, with `rings` if they are known.
+    pub fn crack(&self, model: Model, rings: Option<&str>) -> Result<Solution, EnigmaError> {
+        self.crack_orders(&model.wheel_orders(), model.reflectors(), rings)
+    }
+
+    /// Searches the given wheel orders and reflectors (names as for `rotors::from_name` and
+    /// `reflectors::by_name`) in parallel.
+    pub fn crack_orders(
+        &self,
+        orders: &[Vec<&str>],
+        reflector_names: &[&str],
+        rings: Option<&str>,
+    ) -> Result<Solution, EnigmaError> {
+        if self.ciphertext.is_empty() {
+            return Err(EnigmaError::InvalidSearch("no ciphertext".to_string()));
+        }
+        let stacks = Stacks::new(orders, reflector_names)?;
+        let wheel_count = stacks.wheel_count();
+        let ring_settings = match rings {
+            Some(rings) => vec![parse_rings(rings, wheel_count)?],
+            None if wheel_count < 2 => {
+                return Err(EnigmaError::InvalidSearch(
+                    "searching the rings takes at least two wheels".to_string(),
+                ))
+            }
+            None => fast_rings(wheel_count),
+        };
+        let jobs = stacks.jobs(&ring_settings);
+
+        // Phase 1: wheel order, reflector, start positions and the fastest rings by IoC
+        let candidates = jobs
+            .par_iter()
+            .fold(Vec::new, |mut best, &(order, reflector, ring_setting)| {
+                let stack = &stacks.tables[order][reflector];
+                let sweep = match rings {
+                    Some(_) => self.sweep_positions(stack, ring_setting),
+                    None => self.sweep_runs(stack, ring_setting),
+                };
+                for candidate in sweep {
+                    keep_best(
+                        &mut best,
+                        Candidate {
+                            order,
+                            reflector,
+                            ..candidate
+                        },
+                        self.candidates,
+                    );
+                }
+                best
+            })
+            .reduce(Vec::new, |mut best, other| {
+                for candidate in other {
+                    keep_best(&mut best, candidate, self.candidates);
+                }
+                best
+            });
+
+        // Phases 2 and 3 for each candidate
+        candidates
+            .par_iter()
+            .map(|candidate| {
+                let mut candidate = candidate.clone();
+                let settle = if rings.is_none() {
+                    wheel_count.saturating_sub(SWEPT_RINGS)
+                } else {
+                    0
+                };
+                let names = &orders[candidate.order];
+                let reflector = &stacks.reflectors[candidate.reflector];
+                self.settle_rings(&mut candidate, names, reflector, settle);
+                self.climb_plugboard(&candidate, names, reflector)
+            })
+            .max_by(|a, b| a.score.total_cmp(&b.score))
+            .ok_or_else(|| EnigmaError::InvalidSearch("nothing to search".to_string()))
+    }
+
+    /// The best start positions of one wheel order, reflector and ring setting.
+    fn sweep_positions(&self, stack: &Stack, rings: &[u8]) -> Vec<Candidate> {
+        let wheels = stack.wheel_count();
+        let mut best = Vec::new();
+        let mut start = vec![0; wheels];
+        let mut positions = vec![0; wheels];
+        let mut plaintext = Vec::with_capacity(self.ciphertext.len());
+        for setting in 0..LETTERS.pow(wheels as u32) {
+            let mut rest = setting;
+            for position in start.iter_mut().rev() {
+                *position = (rest % LETTERS) as u8;
+                rest /= LETTERS;
+            }
+            positions.copy_from_slice(&start);
+            stack.decrypt(&self.ciphertext, rings, &mut positions, &mut plaintext);
+            let score = ioc(&plaintext);
+            if admits(&best, score, self.candidates) {
+                keep_best(
+                    &mut best,
+                    Candidate {
+                        order: 0,
+                        reflector: 0,
+                        rings: rings.to_vec(),
+                        positions: start.clone(),
+                        score,
+                    },
+                    self.candidates,
+                );
+            }
+        }
+        best
+    }
+
+    /// The best start positions and middle wheel rings of one wheel order, reflector and ring
+    /// of the fast wheel, scored from the letter counts of `Stack::count_runs`.
+    fn sweep_runs(&self, stack: &Stack, rings: &[u8]) -> Vec<Candidate> {
+        let wheels = stack.wheel_count();
+        let middle = wheels - 2;
+        let mut best = Vec::new();
+        let mut start = vec![0; wheels];
+        let mut runs = Runs::default();
+        // Every window letter but the middle wheel's, which the counts cover
+        for setting in 0..LETTERS.pow(wheels as u32 - 1) {
+            let mut rest = setting;
+            for (wheel, position) in start.iter_mut().enumerate().rev() {
+                if wheel != middle {
+                    *position = (rest % LETTERS) as u8;
+                    rest /= LETTERS;
+                }
+            }
+            stack.count_runs(&self.ciphertext, rings, &start, &mut runs);
+            for position in 0..LETTERS as u8 {
+                for ring in 0..LETTERS as u8 {
+                    let score = counts_ioc(&stack.run_totals(&runs, position, ring));
+                    if admits(&best, score, self.candidates) {
+                        let mut candidate = Candidate {
+                            order: 0,
+                            reflector: 0,
+                            rings: rings.to_vec(),
+                            positions: start.clone(),
+                            score,
+                        };
+                        candidate.rings[middle] = ring;
+                        candidate.positions[middle] = position;
+                        keep_best(&mut best, candidate, self.candidates);
+                    }
+                }
+            }
+        }
+        best
+    }
+
+    /// Phase 2: tries every ring on the first `wheels` wheels, turning each back so its wiring
+    /// offset stays put; only the points where the wheel steps can tell the rings apart.
+    fn settle_rings(
+        &self,
+        candidate: &mut Candidate,
+        names: &[&str],
+        reflector: &Reflector,
+        wheels: usize,
+    ) {
+        let mut plaintext = Vec::with_capacity(self.ciphertext.len());
+        for wheel in 0..wheels {
+            let offset = (candidate.positions[wheel] + candidate.rings[wheel]) % LETTERS as u8;
+            for ring in 0..LETTERS as u8 {
+                let mut trial = candidate.clone();
+                trial.rings[wheel] = ring;
+                trial.positions[wheel] = (offset + LETTERS as u8 - ring) % LETTERS as u8;
+                let mut machine = build_machine(names, reflector, &trial.rings);
+                decrypt_with(
+                    &mut machine,
+                    &letters(&trial.positions),
+                    &self.ciphertext,
+                    &mut plaintext,
+                );
+                trial.score = ioc(&plaintext);
+                if trial.score > candidate.score {
+                    *candidate = trial;
+                }
+            }
+        }
+    }
+
+    /// Phase 3: hill-climbs the plugboard from empty, one scoring stage after the other.
+    fn climb_plugboard(
+        &self,
+        candidate: &Candidate,
+        names: &[&str],
+        reflector: &Reflector,
+    ) -> Solution {
+        let mut machine = build_machine(names, reflector, &candidate.rings);
+        let start = letters(&candidate.positions);
+        let ngrams = self.model;
+
+        let mut plugs = UNPLUGGED;
+        let mut plaintext = Vec::with_capacity(self.ciphertext.len());
+        // IoC first, then n-grams of growing length
+        for stage in [None, Some(2), Some(3), Some(4)] {
+            let mut evaluate = |plugs: &[u8; LETTERS]| {
+                machine.set_plugboard(plugboard(plugs));
+                decrypt_with(&mut machine, &start, &self.ciphertext, &mut plaintext);
+                match stage {
+                    Some(n) => ngrams.score(n, &plaintext),
+                    None => ioc(&plaintext),
+                }
+            };
+            let mut best = evaluate(&plugs);
+            let mut improved = true;
+            while improved {
+                improved = false;
+                for a in 0..LETTERS {
+                    for b in a + 1..LETTERS {
+                        for trial in swaps(&plugs, a, b) {
+                            let trial_score = evaluate(&trial);
+                            if trial_score > best {
+                                best = trial_score;
+                                plugs = trial;
+                                improved = true;
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        machine.set_plugboard(plugboard(&plugs));
+        decrypt_with(&mut machine, &start, &self.ciphertext, &mut plaintext);
+        Solution {
+            key: Stop {
+                wheels: names
+                    .iter()
+                    .filter_map(|name| rotors::from_name(name, 'A', 'A'))
+                    .map(|rotor| rotor.model().to_string())
+                    .collect(),
+                reflector: reflector.model().to_string(),
+                rings: letters(&candidate.rings),
+                positions: start,
+                steckers: (0..LETTERS)
+                    .filter(|&a| (plugs[a] as usize) > a)
+                    .map(|a| (letter(a as u8), letter(plugs[a])))
+                    .collect(),
+            },
+            score: ngrams.score_per_ngram(4, &plaintext),
+            plaintext: plaintext.iter().map(|&letter| letter as char).collect(),
+        }
+    }
+}
+
+/// A trial setting and its score.
+#[derive(Debug, Clone)]
+struct Candidate {
+    order: usize,
+    reflector: usize,
+    rings: Vec<u8>,
+    positions: Vec<u8>,
+    score: f64,
+}
+
+/// Inserts `candidate` into `best`, kept sorted best first and at most `limit` long.
+fn keep_best(best: &mut Vec<Candidate>, candidate: Candidate, limit: usize) {
+    let at = best.partition_point(|kept| kept.score >= candidate.score);
+    if at < limit {
+        best.insert(at, candidate);
+        best.truncate(limit);
+    }
+}
+
+/// True if a candidate scoring `score` would make it into `best`.
+fn admits(best: &[Candidate], score: f64, limit: usize) -> bool {
+    best.len() < limit || best.last().is_some_and(|worst| score > worst.score)
+}
+
+const UNPLUGGED: [u8; LETTERS] = {
+    let mut plugs = [0; LETTERS];
+    let mut letter = 0;
+    while letter < LETTERS {
+        plugs[letter] = letter as u8;
+        letter += 1;
+    }
+    plugs
+};
+
+/// The plugboards one move away that involve letters `a` and `b`: unplugging them if they are
+/// plugged together, otherwise plugging them together with their old partners freed, or with
+/// the old partners plugged to each other.
+fn swaps(plugs: &[u8; LETTERS], a: usize, b: usize) -> Vec<[u8; LETTERS]> {
+    let (partner_a, partner_b) = (plugs[a] as usize, plugs[b] as usize);
+    let mut trial = *plugs;
+    if partner_a == b {
+        trial[a] = a as u8;
+        trial[b] = b as u8;
+        return vec![trial];
+    }
+    trial[partner_a] = partner_a as u8;
+    trial[partner_b] = partner_b as u8;
+    trial[a] = b as u8;
+    trial[b] = a as u8;
+    if partner_a == a || partner_b == b {
+        return vec![trial];
+    }
+    let mut exchanged = trial;
+    exchanged[partner_a] = partner_b as u8;
+    exchanged[partner_b] = partner_a as u8;
+    vec![trial, exchanged]
+}
+
+fn plugboard(plugs: &[u8; LETTERS]) -> Plugboard {
+    let pairs = (0..LETTERS)
+        .filter(|&a| (plugs[a] as usize) > a)
+        .map(|a| (letter(a as u8), letter(plugs[a])))
+        .collect();
+    Plugboard::new(pairs).expect("an involution is a valid plugboard")
+}
+
+fn build_machine(names: &[&str], reflector: &Reflector, rings: &[u8]) -> EnigmaMachine {
+    let rotors = names
+        .iter()
+        .zip(rings)
+        .filter_map(|(name, &ring)| rotors::from_name(name, 'A', letter(ring)))
+        .collect();
+    EnigmaMachine::new(rotors, reflector.clone(), plugboard(&UNPLUGGED))
+}
+
+fn decrypt_with(
+    machine: &mut EnigmaMachine,
+    start: &str,
+    ciphertext: &[u8],
+    plaintext: &mut Vec<u8>,
+) {
+    machine
+        .set_positions(start)
+        .expect("a candidate has a window letter per wheel");
+    plaintext.clear();
+    plaintext.extend(
+        ciphertext
+            .iter()
+            .filter_map(|&cipher| machine.encrypt_char(letter(cipher)))
+            .map(|letter| letter as u8),
+    );
+}
+
+/// Ring `A` on every wheel but the fastest, which gets each ring in turn.
+fn fast_rings(wheels: usize) -> Vec<Vec<u8>> {
+    (0..LETTERS as u8)
+        .map(|ring| {
+            let mut rings = vec![0; wheels];
+            rings[wheels - 1] = ring;
+            rings
+        })
+        .collect()
+}
+
+/// Ring `A` on the slower wheels and every ring on the fastest two.
+pub(crate) fn swept_rings(wheels: usize) -> Vec<Vec<u8>> {
+    let swept = wheels.min(SWEPT_RINGS) as u32;
+    (0..LETTERS.pow(swept))
+        .map(|setting| {
+            let mut rings = vec![0; wheels];
+            let mut rest = setting;
+            for ring in rings.iter_mut().rev().take(swept as usize) {
+                *ring = (rest % LETTERS) as u8;
+                rest /= LETTERS;
+            }
+            rings
+        })
+        .collect()
+}
+
+pub(crate) fn parse_rings(rings: &str, wheels: usize) -> Result<Vec<u8>, EnigmaError> {
+    let found = rings.chars().count();
+    if found != wheels {
+        return Err(EnigmaError::PositionCount {
+            expected: wheels,
+            found,
+        });
+    }
+    rings
+        .chars()
+        .map(|ring| {
+            ring.is_ascii_uppercase()
+                .then(|| index(ring))
+                .ok_or(EnigmaError::InvalidPosition(ring))
+        })
+        .collect()
+}
+
+fn letters(indices: &[u8]) -> String {
+    indices.iter().map(|&index| letter(index)).collect()
+}
+
+fn index(ch: char) -> u8 {
+    ch as u8 - b'A'
+}
+
+fn letter(index: u8) -> char {
+    (b'A' + index) as char
+}
+
+#[cfg(test)]
+mod crack_tests {
+    use super::*;
+    use crate::reflector::reflectors;
+
+    const PLAINTEXT: &str = "VONXFLOTTILLEXWESTXANXUXZWEIXNEUNXSCHWERERXSTURMXAUSXWESTXERWARTETXWINDXSTAERKEXNEUNXSICHTWEITEXGERINGXEINLAUFENXNACHXLORIENTXBISXAUFXWEITERESXVERSCHOBENXTREFFPUNKTXMITXVERSORGERXWIRDXNEUXFESTGELEGTXKEINXFUNKVERKEHR";
+
+    fn machine(rings: &str, positions: &str, plugs: Vec<(char, char)>) -> EnigmaMachine {
+        let rotors = ["ii", "iv", "i"]
+            .iter()
+            .zip(rings.chars().zip(positions.chars()))
+            .filter_map(|(name, (ring, position))| rotors::from_name(name, position, ring))
+            .collect();
+        EnigmaMachine::new(rotors, reflectors::ukw_b(), Plugboard::new(plugs).unwrap())
+    }
+
+    #[test]
+    fn test_swaps() {
+        let mut plugs = UNPLUGGED;
+        plugs.swap(0, 1);
+        assert_eq!(swaps(&plugs, 0, 1), vec![UNPLUGGED]);
+        let moved = swaps(&plugs, 0, 2);
+        assert_eq!(moved.len(), 1);
+        assert_eq!((moved[0][0], moved[0][1], moved[0][2]), (2, 1, 0));
+    }
+
+    #[test]
+    fn test_crack_with_known_rings() {
+        let plugs = vec![('A', 'R'), ('G', 'K'), ('O', 'X'), ('B', 'M'), ('L', 'W')];
+        let mut machine = machine("BUL", "QEV", plugs.clone());
+        let ciphertext = machine.encrypt_message(PLAINTEXT).unwrap();
+
+        let mut cracker = Cracker::new(&ciphertext).unwrap();
+        cracker.set_candidates(1);
+        let solution = cracker
+            .crack_orders(&[vec!["ii", "iv", "i"]], &["ukw_b"], Some("BUL"))
+            .unwrap();
+        assert_eq!(solution.plaintext, PLAINTEXT);
+        assert_eq!(solution.key.positions, "QEV");
+        assert_eq!(solution.key.steckers, plugs_sorted(plugs));
+    }
+
+    #[test]
+    fn test_crack_without_rings() {
+        // Two wheels keep the search to 26^3 settings
+        let plugs = vec![('B', 'Y'), ('C', 'P'), ('J', 'V')];
+        let rotors = vec![rotors::type_iv('A', 'M'), rotors::type_i('C', 'T')];
+        let mut machine = EnigmaMachine::new(
+            rotors,
+            reflectors::ukw_b(),
+            Plugboard::new(plugs.clone()).unwrap(),
+        );
+        let ciphertext = machine.encrypt_message(PLAINTEXT).unwrap();
+
+        let mut cracker = Cracker::new(&ciphertext).unwrap();
+        cracker.set_candidates(1);
+        let solution = cracker
+            .crack_orders(&[vec!["iv", "i"]], &["ukw_b"], None)
+            .unwrap();
+        assert_eq!(solution.plaintext, PLAINTEXT);
+        assert_eq!(solution.key.rings, "MT");
+        assert_eq!(solution.key.positions, "AC");
+        assert_eq!(solution.key.steckers, plugs_sorted(plugs));
+    }
+
+    #[test]
+    #[ignore = "searches 26^4 settings; run with --ignored"]
+    fn test_crack_without_rings_three_wheels() {
+        let plugs = vec![('B', 'Y'), ('C', 'P'), ('J', 'V')];
+        let mut machine = machine("FMT", "KAC", plugs.clone());
+        let ciphertext = machine.encrypt_message(PLAINTEXT).unwrap();
+
+        let mut cracker = Cracker::new(&ciphertext).unwrap();
+        cracker.set_candidates(1);
+        let solution = cracker
+            .crack_orders(&[vec!["ii", "iv", "i"]], &["ukw_b"], None)
+            .unwrap();
+        assert_eq!(solution.plaintext, PLAINTEXT);
+        // The slow wheel never steps here, so its ring folds into its window letter
+        assert_eq!(&solution.key.rings[1..], "MT");
+        assert_eq!(&solution.key.positions[1..], "AC");
+        assert_eq!(solution.key.steckers, plugs_sorted(plugs));
+    }
+
+    #[test]
+    fn test_crack_english() {
+        let plaintext = "ALLSHIPSINTHEWESTERNAPPROACHESAREWARNEDTHATAHEAVYGALEISEXPECTEDTONIGHTMEETINGTHETANKERISPOSTPONEDUNTILTHEWEATHERCLEARSKEEPRADIOSILENCEANDREPORTONLYENEMYCONTACTS";
+        let mut machine = machine("MCK", "ADU", vec![('E', 'Z'), ('T', 'Q'), ('S', 'N')]);
+        let ciphertext = machine.encrypt_message(plaintext).unwrap();
+
+        let mut cracker = Cracker::new(&ciphertext).unwrap();
+        cracker.set_candidates(1);
+        cracker.set_model(Language::English.model());
+        let solution = cracker
+            .crack_orders(&[vec!["ii", "iv", "i"]], &["ukw_b"], Some("MCK"))
+            .unwrap();
+        assert_eq!(solution.plaintext, plaintext);
+    }
+
+    fn plugs_sorted(mut plugs: Vec<(char, char)>) -> Vec<(char, char)> {
+        plugs.sort();
+        plugs
+    }
+
+    #[test]
+    fn test_invalid_searches() {
+        assert_eq!(
+            Cracker::new("AB1").err(),
+            Some(EnigmaError::InvalidCharacter('1'))
+        );
+        let cracker = Cracker::new("ABCDE").unwrap();
+        assert!(cracker
+            .crack_orders(&[vec!["ii", "x", "i"]], &["ukw_b"], None)
+            .is_err());
+        assert!(cracker
+            .crack_orders(&[vec!["ii", "iv", "i"]], &["ukw_x"], None)
+            .is_err());
+        assert!(cracker
+            .crack_orders(&[vec!["ii", "iv", "i"]], &["ukw_b"], Some("AB"))
+            .is_err());
+    }
+}
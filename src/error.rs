@@ -0,0 +1,67 @@
+use std::fmt;
+
+/// Errors raised while pushing text through an `EnigmaMachine`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnigmaError {
+    /// The character has no key on the machine.
+    InvalidCharacter(char),
+    /// A rotor setting named a different number of rotors than the machine has.
+    PositionCount { expected: usize, found: usize },
+    /// A rotor can only be turned to a letter `A-Z`.
+    InvalidPosition(char),
+    /// A ring setting named a different number of rotors than the machine has.
+    RingCount { expected: usize, found: usize },
+    /// A ring can only be set to a letter `A-Z`.
+    InvalidRing(char),
+    /// A message indicator could not be decoded.
+    InvalidIndicator(String),
+    /// A radio message preamble could not be parsed.
+    InvalidHeader(String),
+    /// The letter count in a preamble disagrees with the message body.
+    LetterCount { expected: usize, found: usize },
+    /// A part of a message split into parts never arrived.
+    MissingPart { part: u32, parts: u32 },
+    /// A key sheet entry does not describe a machine that can be built.
+    InvalidKeySheet(String),
+    /// A crib cannot be placed against the ciphertext, or its search is misconfigured.
+    InvalidCrib(String),
+    /// A key search names wheels, reflectors or settings the machine does not have.
+    InvalidSearch(String),
+}
+
+impl fmt::Display for EnigmaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EnigmaError::InvalidCharacter(ch) => {
+                write!(f, "Failed to encrypt character: '{}'", ch.escape_default())
+            }
+            EnigmaError::PositionCount { expected, found } => {
+                write!(f, "Expected {} rotor positions, found {}", expected, found)
+            }
+            EnigmaError::InvalidPosition(ch) => {
+                write!(f, "Invalid rotor position: '{}'", ch.escape_default())
+            }
+            EnigmaError::RingCount { expected, found } => {
+                write!(f, "Expected {} ring settings, found {}", expected, found)
+            }
+            EnigmaError::InvalidRing(ch) => {
+                write!(f, "Invalid ring setting: '{}'", ch.escape_default())
+            }
+            EnigmaError::InvalidIndicator(reason) => write!(f, "Invalid indicator: {}", reason),
+            EnigmaError::InvalidHeader(reason) => write!(f, "Invalid message header: {}", reason),
+            EnigmaError::LetterCount { expected, found } => write!(
+                f,
+                "Header announces {} letters, but the body has {}",
+                expected, found
+            ),
+            EnigmaError::MissingPart { part, parts } => {
+                write!(f, "Part {} of {} is missing", part, parts)
+            }
+            EnigmaError::InvalidKeySheet(reason) => write!(f, "Invalid key sheet: {}", reason),
+            EnigmaError::InvalidCrib(reason) => write!(f, "Invalid crib: {}", reason),
+            EnigmaError::InvalidSearch(reason) => write!(f, "Invalid search: {}", reason),
+        }
+    }
+}
+
+impl std::error::Error for EnigmaError {}
@@ -0,0 +1,182 @@
+use std::io::{self, Write};
+
+/// Layout of ciphertext on the page: letters in fixed-size groups, a number of groups per line.
+///
+/// A `group_size` of 0 is raw mode: text goes out untouched. A `groups_per_line` of 0 puts every
+/// group on one line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupFormat {
+    pub group_size: usize,
+    pub groups_per_line: usize,
+}
+
+impl GroupFormat {
+    /// Five-letter groups, as used by the Heer and Luftwaffe.
+    pub const FIVE: GroupFormat = GroupFormat {
+        group_size: 5,
+        groups_per_line: 10,
+    };
+
+    /// Four-letter groups, as used by the Kriegsmarine.
+    pub const FOUR: GroupFormat = GroupFormat {
+        group_size: 4,
+        groups_per_line: 10,
+    };
+
+    pub fn new(group_size: usize, groups_per_line: usize) -> Self {
+        GroupFormat {
+            group_size,
+            groups_per_line,
+        }
+    }
+
+    pub fn raw() -> Self {
+        GroupFormat::new(0, 0)
+    }
+
+    pub fn is_raw(&self) -> bool {
+        self.group_size == 0
+    }
+
+    /// Lays out `text` in one go. Grouping drops any whitespace already in it; raw mode keeps it.
+    pub fn format(&self, text: &str) -> String {
+        let mut writer = GroupWriter::new(Vec::new(), *self);
+        // Writing to a Vec cannot fail
+        writer.write_all(text.as_bytes()).unwrap();
+        String::from_utf8_lossy(&writer.into_inner()).into_owned()
+    }
+}
+
+impl Default for GroupFormat {
+    fn default() -> Self {
+        GroupFormat::raw()
+    }
+}
+
+/// Removes the whitespace grouping adds, so grouped ciphertext can be fed back to the machine.
+pub fn ungroup(text: &str) -> String {
+    text.chars().filter(|ch| !ch.is_whitespace()).collect()
+}
+
+/// Wraps any `Write` and lays out everything written through it per a `GroupFormat`.
+///
+/// Group and line positions carry over between writes, so chunk boundaries of the caller never
+/// show up in the output.
+pub struct GroupWriter<W: Write> {
+    inner: W,
+    format: GroupFormat,
+    in_group: usize,
+    groups_on_line: usize,
+    written: bool,
+}
+
+impl<W: Write> GroupWriter<W> {
+    pub fn new(inner: W, format: GroupFormat) -> Self {
+        GroupWriter {
+            inner,
+            format,
+            in_group: 0,
+            groups_on_line: 0,
+            written: false,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+
+    /// Ends the last line with a newline, if anything was written, and returns the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        if self.written {
+            self.inner.write_all(b"\n")?;
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+}
+
+impl<W: Write> Write for GroupWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.format.is_raw() {
+            self.written |= !buf.is_empty();
+            self.inner.write_all(buf)?;
+            return Ok(buf.len());
+        }
+
+        let mut laid_out = Vec::with_capacity(buf.len() + buf.len() / self.format.group_size);
+        for &byte in buf.iter().filter(|byte| !byte.is_ascii_whitespace()) {
+            if self.in_group == self.format.group_size {
+                self.in_group = 0;
+                self.groups_on_line += 1;
+                if self.groups_on_line == self.format.groups_per_line {
+                    self.groups_on_line = 0;
+                    laid_out.push(b'\n');
+                } else {
+                    laid_out.push(b' ');
+                }
+            }
+            laid_out.push(byte);
+            self.in_group += 1;
+            self.written = true;
+        }
+        self.inner.write_all(&laid_out)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod format_tests {
+    use super::*;
+
+    #[test]
+    fn test_five_letter_groups() {
+        let format = GroupFormat::new(5, 2);
+        assert_eq!(
+            format.format("ABCDEFGHIJKLMNOPQRSTUVW"),
+            "ABCDE FGHIJ\nKLMNO PQRST\nUVW"
+        );
+    }
+
+    #[test]
+    fn test_zero_groups_per_line_keeps_one_line() {
+        assert_eq!(
+            GroupFormat::new(5, 0).format("ABCDEFGHIJKLMNOPQRSTUVW"),
+            "ABCDE FGHIJ KLMNO PQRST UVW"
+        );
+    }
+
+    #[test]
+    fn test_groups_carry_over_between_writes() {
+        let mut writer = GroupWriter::new(Vec::new(), GroupFormat::FOUR);
+        for chunk in ["ABC", "DEFG\n", "HI"] {
+            writer.write_all(chunk.as_bytes()).unwrap();
+        }
+        let output = writer.finish().unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "ABCD EFGH I\n");
+    }
+
+    #[test]
+    fn test_raw_is_untouched() {
+        assert_eq!(GroupFormat::raw().format("AB CD\nEF"), "AB CD\nEF");
+    }
+
+    #[test]
+    fn test_ungroup_round_trips() {
+        let text = "WETTERVORHERSAGEBISKAYA";
+        assert_eq!(ungroup(&GroupFormat::new(5, 3).format(text)), text);
+    }
+
+    #[test]
+    fn test_finish_without_output() {
+        let writer = GroupWriter::new(Vec::new(), GroupFormat::FIVE);
+        assert!(writer.finish().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,122 @@
+use crate::error::EnigmaError;
+use crate::machine::EnigmaMachine;
+use std::collections::VecDeque;
+
+/// Lazily en/decrypts a stream of characters.
+///
+/// Characters without a key are handled by the machine's `NonAlphaPolicy`, so one input may
+/// yield several items (substitution) or none (skip). Under `NonAlphaPolicy::Error` a bad
+/// character only fails its own item: the iterator keeps going and callers decide what to do.
+///
+/// # Example
+///
+/// ```rust
+/// use enigma_shark::{reflectors, rotors, EnigmaIteratorExt, EnigmaMachine, Plugboard};
+///
+/// let mut machine = EnigmaMachine::new(
+///     vec![rotors::type_i('A', 'A'), rotors::type_ii('B', 'A'), rotors::type_iii('C', 'A')],
+///     reflectors::ukw_b(),
+///     Plugboard::new(vec![]).unwrap(),
+/// );
+/// let encrypted: String = "HELLO WORLD"
+///     .chars()
+///     .enigma(&mut machine)
+///     .filter_map(Result::ok)
+///     .collect();
+/// assert_eq!(encrypted.len(), 10);
+/// ```
+pub struct EncryptIter<'a, I> {
+    chars: I,
+    machine: &'a mut EnigmaMachine,
+    pending: VecDeque<Result<char, EnigmaError>>,
+}
+
+impl<'a, I: Iterator<Item = char>> EncryptIter<'a, I> {
+    pub(crate) fn new(chars: I, machine: &'a mut EnigmaMachine) -> Self {
+        EncryptIter {
+            chars,
+            machine,
+            pending: VecDeque::new(),
+        }
+    }
+}
+
+impl<I: Iterator<Item = char>> Iterator for EncryptIter<'_, I> {
+    type Item = Result<char, EnigmaError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pending.is_empty() {
+            let ch = self.chars.next()?;
+            let pending = &mut self.pending;
+            if let Err(err) = self
+                .machine
+                .process_char(ch, |out| pending.push_back(Ok(out)))
+            {
+                pending.push_back(Err(err));
+            }
+        }
+        self.pending.pop_front()
+    }
+}
+
+/// Adds `.enigma(&mut machine)` to any iterator of characters.
+pub trait EnigmaIteratorExt: Iterator<Item = char> + Sized {
+    fn enigma(self, machine: &mut EnigmaMachine) -> EncryptIter<'_, Self> {
+        EncryptIter::new(self, machine)
+    }
+}
+
+impl<I: Iterator<Item = char>> EnigmaIteratorExt for I {}
+
+#[cfg(test)]
+mod iter_tests {
+    use super::*;
+    use crate::plugboard::Plugboard;
+    use crate::reflector::reflectors;
+    use crate::rotor::rotors;
+
+    fn setup_enigma_machine() -> EnigmaMachine {
+        EnigmaMachine::new(
+            vec![
+                rotors::type_i('A', 'A'),
+                rotors::type_ii('B', 'A'),
+                rotors::type_iii('C', 'A'),
+            ],
+            reflectors::ukw_b(),
+            Plugboard::new(vec![]).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_encrypt_iter_matches_encrypt_message() {
+        let message = "BLETCHLEYPARK";
+        let expected = setup_enigma_machine().encrypt_message(message).unwrap();
+
+        let mut machine = setup_enigma_machine();
+        let encrypted: Result<String, EnigmaError> =
+            machine.encrypt_iter(message.chars()).collect();
+
+        assert_eq!(encrypted.unwrap(), expected);
+    }
+
+    #[test]
+    fn test_bad_characters_fail_per_item() {
+        let mut machine = setup_enigma_machine();
+        let results: Vec<_> = "AB9C".chars().enigma(&mut machine).collect();
+
+        assert_eq!(results.len(), 4);
+        assert!(results[0].is_ok());
+        assert!(results[1].is_ok());
+        assert_eq!(results[2], Err(EnigmaError::InvalidCharacter('9')));
+        assert!(results[3].is_ok());
+    }
+
+    #[test]
+    fn test_iterator_is_lazy() {
+        let mut machine = setup_enigma_machine();
+        let mut iter = std::iter::repeat('A').enigma(&mut machine);
+
+        assert!(iter.next().unwrap().is_ok());
+        assert_eq!(iter.take(3).count(), 3);
+    }
+}
@@ -0,0 +1,526 @@
+//! Monthly key sheets (Schlüsselblätter).
+//!
+//! A sheet gives one daily key per day of the month: wheel order, ring settings, plugboard
+//! connections and the Kenngruppen that identify the key net, plus the UKW-D wiring where the
+//! rewirable reflector was in use. Generation follows the rules the key office held to:
+//!
+//! - exactly ten plugboard cables, never joining neighbouring letters such as `A`-`B`
+//! - no stepping wheel sits in the same slot two days running; the M4's Greek wheel is exempt
+//! - the day's Kenngruppen are all different
+//!
+//! Printed sheets ran from the last day of the month down to the first, so used days could be
+//! cut off and destroyed; `KeySheet::table` keeps that order.
+
+use crate::error::EnigmaError;
+use crate::machine::EnigmaMachine;
+use crate::model::Model;
+use crate::plugboard::Plugboard;
+use crate::procedure::random_key;
+use crate::reflector::reflectors;
+use crate::rotor::rotors;
+use rand::seq::SliceRandom;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Plugboard cables issued per day.
+pub const PLUG_PAIRS: usize = 10;
+/// Kenngruppen issued per day.
+pub const KENNGRUPPEN: usize = 4;
+
+/// A calendar month, written `YYYY-MM`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Month {
+    pub year: u32,
+    pub month: u32,
+}
+
+impl Month {
+    pub fn days(&self) -> u32 {
+        match self.month {
+            2 if self.year.is_multiple_of(4)
+                && (!self.year.is_multiple_of(100) || self.year.is_multiple_of(400)) =>
+            {
+                29
+            }
+            2 => 28,
+            4 | 6 | 9 | 11 => 30,
+            _ => 31,
+        }
+    }
+}
+
+impl FromStr for Month {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("Invalid month: {} (expected YYYY-MM)", s);
+        let (year, month) = s.split_once('-').ok_or_else(invalid)?;
+        let year = year.parse().map_err(|_| invalid())?;
+        let month = month.parse().map_err(|_| invalid())?;
+        if !(1..=12).contains(&month) {
+            return Err(invalid());
+        }
+        Ok(Month { year, month })
+    }
+}
+
+impl TryFrom<String> for Month {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+impl From<Month> for String {
+    fn from(month: Month) -> Self {
+        month.to_string()
+    }
+}
+
+impl fmt::Display for Month {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}", self.year, self.month)
+    }
+}
+
+/// The settings for one day.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DailyKey {
+    pub day: u32,
+    /// Wheel names from left to right, as accepted by `rotors::from_name`.
+    pub wheels: Vec<String>,
+    /// Ring setting of each wheel, as letters.
+    pub rings: String,
+    /// Reflector name as accepted by `reflectors::from_name`, or `ukw_d`.
+    pub reflector: String,
+    /// The twelve UKW-D connections besides the fixed `J`-`Y`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ukw_d: Option<Vec<String>>,
+    /// Plugboard connections as letter pairs, e.g. `AT`.
+    pub plugboard: Vec<String>,
+    pub kenngruppen: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeySheet {
+    pub model: Model,
+    pub month: Month,
+    pub days: Vec<DailyKey>,
+}
+
+impl DailyKey {
+    /// Draws the key for `day`, keeping every stepping wheel out of the slot it held on
+    /// `previous`.
+    pub fn generate<R: RngCore + ?Sized>(
+        rng: &mut R,
+        model: Model,
+        day: u32,
+        previous: Option<&DailyKey>,
+        ukw_d: bool,
+    ) -> Self {
+        let wheels = wheel_order(rng, model, previous.map(|key| key.wheels.as_slice()));
+        let ukw_d = (ukw_d && model.supports_ukw_d()).then(|| pair_names(&ukw_d_pairs(rng)));
+        DailyKey {
+            day,
+            rings: random_key(rng, wheels.len()),
+            wheels,
+            reflector: match ukw_d {
+                Some(_) => "ukw_d".to_string(),
+                None => model.reflectors()[0].to_string(),
+            },
+            ukw_d,
+            plugboard: pair_names(&plug_pairs(rng, PLUG_PAIRS)),
+            kenngruppen: kenngruppen(rng),
+        }
+    }
+
+    /// Sets the machine up at this day's key. Every rotor starts at `A`; the message key
+    /// procedure supplies the positions.
+    pub fn machine(&self) -> Result<EnigmaMachine, EnigmaError> {
+        let invalid =
+            |reason: String| EnigmaError::InvalidKeySheet(format!("day {}: {}", self.day, reason));
+
+        if self.rings.chars().count() != self.wheels.len() {
+            return Err(invalid(format!(
+                "{} wheels but {} ring settings",
+                self.wheels.len(),
+                self.rings.chars().count()
+            )));
+        }
+        let rotors = self
+            .wheels
+            .iter()
+            .zip(self.rings.chars())
+            .map(|(name, ring)| match rotors::from_name(name, 'A', ring) {
+                Some(rotor) if ring.is_ascii_uppercase() => Ok(rotor),
+                Some(_) => Err(invalid(format!("invalid ring setting '{}'", ring))),
+                None => Err(invalid(format!("unknown wheel '{}'", name))),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let reflector = match (self.reflector.as_str(), &self.ukw_d) {
+            ("ukw_d", Some(wiring)) => parse_pairs(wiring)
+                .and_then(|pairs| reflectors::ukw_d(&pairs))
+                .ok_or_else(|| invalid("invalid UKW-D wiring".to_string()))?,
+            ("ukw_d", None) => return Err(invalid("UKW-D without its wiring".to_string())),
+            (name, _) => reflectors::by_name(name)
+                .ok_or_else(|| invalid(format!("unknown reflector '{}'", name)))?,
+        };
+
+        let plugboard = parse_pairs(&self.plugboard)
+            .and_then(|pairs| Plugboard::new(pairs).ok())
+            .ok_or_else(|| invalid("invalid plugboard connections".to_string()))?;
+
+        Ok(EnigmaMachine::new(rotors, reflector, plugboard))
+    }
+}
+
+impl KeySheet {
+    /// Draws a daily key for every day of `month`, with a UKW-D wiring per day if `ukw_d` is
+    /// set and the model takes one. A seeded `rng` reproduces the same sheet.
+    pub fn generate<R: RngCore + ?Sized>(
+        rng: &mut R,
+        model: Model,
+        month: Month,
+        ukw_d: bool,
+    ) -> Self {
+        let mut days: Vec<DailyKey> = Vec::new();
+        for day in 1..=month.days() {
+            let key = DailyKey::generate(rng, model, day, days.last(), ukw_d);
+            days.push(key);
+        }
+        KeySheet { model, month, days }
+    }
+
+    pub fn day(&self, day: u32) -> Option<&DailyKey> {
+        self.days.iter().find(|key| key.day == day)
+    }
+
+    /// Lays the sheet out as a printable table, last day first.
+    pub fn table(&self) -> String {
+        let with_ukw_d = self.days.iter().any(|key| key.ukw_d.is_some());
+        let mut headings = vec!["Tag", "UKW", "Walzenlage", "Ringstellung"];
+        if with_ukw_d {
+            headings.push("UKW-D");
+        }
+        headings.extend(["Steckerverbindungen", "Kenngruppen"]);
+
+        let rows: Vec<Vec<String>> = self
+            .days
+            .iter()
+            .rev()
+            .map(|key| {
+                let mut row = vec![
+                    format!("{:>3}", key.day),
+                    reflector_label(&key.reflector),
+                    key.wheels
+                        .iter()
+                        .map(|name| format!("{:<5}", wheel_label(name)))
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                        .trim_end()
+                        .to_string(),
+                    key.rings
+                        .chars()
+                        .map(|ring| format!("{:02}", ring as u8 - b'A' + 1))
+                        .collect::<Vec<_>>()
+                        .join(" "),
+                ];
+                if with_ukw_d {
+                    row.push(key.ukw_d.as_deref().unwrap_or_default().join(" "));
+                }
+                row.push(key.plugboard.join(" "));
+                row.push(key.kenngruppen.join(" "));
+                row
+            })
+            .collect();
+
+        let widths: Vec<usize> = headings
+            .iter()
+            .enumerate()
+            .map(|(column, heading)| {
+                rows.iter()
+                    .map(|row| row[column].len())
+                    .chain([heading.len()])
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let line = |cells: Vec<String>| {
+            cells
+                .iter()
+                .zip(&widths)
+                .map(|(cell, &width)| format!("{:<width$}", cell))
+                .collect::<Vec<_>>()
+                .join(" | ")
+                .trim_end()
+                .to_string()
+        };
+
+        let mut table = vec![
+            format!("Enigma {} key sheet {}", self.model, self.month),
+            line(headings.iter().map(|heading| heading.to_string()).collect()),
+            widths
+                .iter()
+                .map(|&width| "-".repeat(width))
+                .collect::<Vec<_>>()
+                .join("-+-"),
+        ];
+        table.extend(rows.into_iter().map(line));
+        table.join("\n")
+    }
+}
+
+/// Draws `count` plugboard cables, none joining neighbouring letters.
+pub fn plug_pairs<R: RngCore + ?Sized>(rng: &mut R, count: usize) -> Vec<(char, char)> {
+    let count = count.min(13);
+    let mut letters: Vec<char> = ('A'..='Z').collect();
+    loop {
+        letters.shuffle(rng);
+        let mut pairs: Vec<(char, char)> = letters
+            .chunks(2)
+            .take(count)
+            .map(|pair| (pair[0].min(pair[1]), pair[0].max(pair[1])))
+            .collect();
+        if pairs.iter().all(|&(a, b)| b as u8 - a as u8 != 1) {
+            pairs.sort_unstable();
+            return pairs;
+        }
+    }
+}
+
+/// Draws a UKW-D wiring: twelve pairs over every letter but the fixed `J` and `Y`.
+pub fn ukw_d_pairs<R: RngCore + ?Sized>(rng: &mut R) -> Vec<(char, char)> {
+    let mut letters: Vec<char> = ('A'..='Z').filter(|&ch| ch != 'J' && ch != 'Y').collect();
+    letters.shuffle(rng);
+    let mut pairs: Vec<(char, char)> = letters
+        .chunks(2)
+        .map(|pair| (pair[0].min(pair[1]), pair[0].max(pair[1])))
+        .collect();
+    pairs.sort_unstable();
+    pairs
+}
+
+fn wheel_order<R: RngCore + ?Sized>(
+    rng: &mut R,
+    model: Model,
+    previous: Option<&[String]>,
+) -> Vec<String> {
+    // The Greek wheel never steps, so only the three slots after it fall under the rule.
+    let fixed = model.greek_wheels().len().min(1);
+    loop {
+        let greek = model.greek_wheels().choose(rng);
+        let order: Vec<String> = greek
+            .into_iter()
+            .chain(model.wheels().choose_multiple(rng, 3))
+            .map(|name| name.to_string())
+            .collect();
+        let repeats = previous.is_some_and(|previous| {
+            previous
+                .iter()
+                .zip(&order)
+                .skip(fixed)
+                .any(|(yesterday, today)| yesterday == today)
+        });
+        if !repeats {
+            return order;
+        }
+    }
+}
+
+fn kenngruppen<R: RngCore + ?Sized>(rng: &mut R) -> Vec<String> {
+    let mut groups: Vec<String> = Vec::with_capacity(KENNGRUPPEN);
+    while groups.len() < KENNGRUPPEN {
+        let group = random_key(rng, 3);
+        if !groups.contains(&group) {
+            groups.push(group);
+        }
+    }
+    groups
+}
+
+fn parse_pairs(names: &[String]) -> Option<Vec<(char, char)>> {
+    names
+        .iter()
+        .map(|name| {
+            let mut letters = name.chars();
+            match (letters.next(), letters.next(), letters.next()) {
+                (Some(a), Some(b), None) => Some((a, b)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+fn pair_names(pairs: &[(char, char)]) -> Vec<String> {
+    pairs
+        .iter()
+        .map(|&(a, b)| [a, b].iter().collect())
+        .collect()
+}
+
+fn wheel_label(name: &str) -> String {
+    match name {
+        "beta" => "Beta".to_string(),
+        "gamma" => "Gamma".to_string(),
+        _ => name.to_ascii_uppercase(),
+    }
+}
+
+fn reflector_label(name: &str) -> String {
+    match name.strip_prefix("ukw_") {
+        Some(short) => short.replace("_thin", " thin").to_ascii_uppercase(),
+        None => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod keysheet_tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    #[test]
+    fn test_month() {
+        let month: Month = "2026-10".parse().unwrap();
+        assert_eq!(month.days(), 31);
+        assert_eq!(month.to_string(), "2026-10");
+        assert_eq!("2024-02".parse::<Month>().unwrap().days(), 29);
+        assert_eq!("1900-02".parse::<Month>().unwrap().days(), 28);
+        assert!("2026-13".parse::<Month>().is_err());
+        assert!("October".parse::<Month>().is_err());
+    }
+
+    #[test]
+    fn test_generate_follows_the_rules() {
+        let month = "2026-02".parse().unwrap();
+        for model in [Model::EnigmaI, Model::M3, Model::M4] {
+            let sheet = KeySheet::generate(&mut StdRng::seed_from_u64(1), model, month, false);
+            let fixed = model.greek_wheels().len().min(1);
+            assert_eq!(sheet.days.len(), 28);
+
+            for key in &sheet.days {
+                assert_eq!(key.wheels.len(), model.rotor_count());
+                assert_eq!(key.rings.len(), model.rotor_count());
+                assert_eq!(key.plugboard.len(), PLUG_PAIRS);
+                assert_eq!(key.kenngruppen.len(), KENNGRUPPEN);
+                for (i, group) in key.kenngruppen.iter().enumerate() {
+                    assert!(!key.kenngruppen[i + 1..].contains(group));
+                }
+                for pair in &key.plugboard {
+                    let pair = pair.as_bytes();
+                    assert!(pair[0].abs_diff(pair[1]) > 1);
+                }
+            }
+            for days in sheet.days.windows(2) {
+                let stepping = days[0].wheels.iter().zip(&days[1].wheels).skip(fixed);
+                for (yesterday, today) in stepping {
+                    assert_ne!(yesterday, today);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_greek_wheel_may_stay_in_place() {
+        let month = "2026-10".parse().unwrap();
+        let sheet = KeySheet::generate(&mut StdRng::seed_from_u64(38), Model::M4, month, false);
+        assert!(sheet
+            .days
+            .windows(2)
+            .any(|days| days[0].wheels[0] == days[1].wheels[0]));
+    }
+
+    #[test]
+    fn test_seeded_generation_is_reproducible() {
+        let month = "2026-10".parse().unwrap();
+        let generate =
+            |seed| KeySheet::generate(&mut StdRng::seed_from_u64(seed), Model::M4, month, false);
+        assert_eq!(generate(17), generate(17));
+        assert_ne!(generate(17), generate(18));
+    }
+
+    #[test]
+    fn test_ukw_d_wiring() {
+        let month = "2026-10".parse().unwrap();
+        let sheet = KeySheet::generate(&mut StdRng::seed_from_u64(2), Model::EnigmaI, month, true);
+        let key = sheet.day(17).unwrap();
+        assert_eq!(key.reflector, "ukw_d");
+        let wiring = key.ukw_d.as_ref().unwrap();
+        assert_eq!(wiring.len(), 12);
+        assert!(!wiring.concat().contains(['J', 'Y']));
+
+        let sheet = KeySheet::generate(&mut StdRng::seed_from_u64(3), Model::M4, month, true);
+        assert!(sheet.days.iter().all(|key| key.ukw_d.is_none()));
+    }
+
+    #[test]
+    fn test_daily_machine() {
+        let month = "2026-10".parse().unwrap();
+        for (model, ukw_d) in [(Model::M3, true), (Model::M4, false)] {
+            let sheet = KeySheet::generate(&mut StdRng::seed_from_u64(4), model, month, ukw_d);
+            let key = sheet.day(17).unwrap();
+
+            let mut machine = key.machine().unwrap();
+            assert_eq!(machine.positions(), "A".repeat(model.rotor_count()));
+            let encrypted = machine.encrypt_message("WETTERBERICHT").unwrap();
+            let mut machine = key.machine().unwrap();
+            assert_eq!(
+                machine.encrypt_message(&encrypted).unwrap(),
+                "WETTERBERICHT"
+            );
+        }
+    }
+
+    #[test]
+    fn test_daily_machine_rejects_bad_entries() {
+        let month = "2026-10".parse().unwrap();
+        let sheet = KeySheet::generate(&mut StdRng::seed_from_u64(5), Model::M3, month, false);
+
+        let mut key = sheet.day(1).unwrap().clone();
+        key.wheels[0] = "ix".to_string();
+        assert!(matches!(
+            key.machine(),
+            Err(EnigmaError::InvalidKeySheet(_))
+        ));
+
+        let mut key = sheet.day(1).unwrap().clone();
+        key.plugboard.push(key.plugboard[0].clone());
+        assert!(matches!(
+            key.machine(),
+            Err(EnigmaError::InvalidKeySheet(_))
+        ));
+
+        let mut key = sheet.day(1).unwrap().clone();
+        key.reflector = "ukw_d".to_string();
+        assert!(matches!(
+            key.machine(),
+            Err(EnigmaError::InvalidKeySheet(_))
+        ));
+    }
+
+    #[test]
+    fn test_json_round_trip() {
+        let month = "2026-10".parse().unwrap();
+        let sheet = KeySheet::generate(&mut StdRng::seed_from_u64(6), Model::M3, month, false);
+        let json = serde_json::to_string(&sheet).unwrap();
+        assert!(json.contains(r#""model":"m3","month":"2026-10""#));
+        assert_eq!(serde_json::from_str::<KeySheet>(&json).unwrap(), sheet);
+    }
+
+    #[test]
+    fn test_table_runs_backwards() {
+        let month = "2026-10".parse().unwrap();
+        let sheet = KeySheet::generate(&mut StdRng::seed_from_u64(7), Model::M4, month, false);
+        let table = sheet.table();
+        let lines: Vec<&str> = table.lines().collect();
+        assert_eq!(lines[0], "Enigma m4 key sheet 2026-10");
+        assert_eq!(lines.len(), 3 + 31);
+        assert!(lines[3].starts_with(" 31 | B THIN"));
+        assert!(lines[33].starts_with("  1 | "));
+    }
+}
@@ -1,9 +1,42 @@
+#[cfg(feature = "tokio")]
+mod async_stream;
+pub mod banburismus;
+pub mod bombe;
+pub mod crack;
+mod error;
+mod format;
+mod iter;
+pub mod keysheet;
 mod machine;
+pub mod message;
+mod model;
+pub mod naval;
+pub mod ngram;
 mod plugboard;
+mod policy;
+mod preprocess;
+pub mod procedure;
+pub mod recover;
 mod reflector;
+pub mod rejewski;
 mod rotor;
+mod scrambler;
+mod stream;
+mod tables;
+mod trace;
+pub mod zygalski;
 
+#[cfg(feature = "tokio")]
+pub use async_stream::{AsyncEnigmaReader, AsyncEnigmaWriter};
+pub use error::EnigmaError;
+pub use format::{ungroup, GroupFormat, GroupWriter};
+pub use iter::{EncryptIter, EnigmaIteratorExt};
 pub use machine::EnigmaMachine;
-pub use plugboard::Plugboard;
+pub use model::Model;
+pub use plugboard::{plugboards, Plugboard};
+pub use policy::NonAlphaPolicy;
+pub use preprocess::Convention;
 pub use reflector::{reflectors, Reflector};
 pub use rotor::{rotors, Rotor};
+pub use stream::{EnigmaReader, EnigmaWriter};
+pub use trace::{Reflection, RotorPass, StepReason, Stepped, Trace, TraceFormat, Wire};
@@ -1,11 +1,19 @@
+use crate::error::EnigmaError;
+use crate::iter::EncryptIter;
 use crate::plugboard::Plugboard;
-use crate::reflector::{reflectors, Reflector};
-use crate::rotor::{rotors, Rotor};
+use crate::policy::NonAlphaPolicy;
+use crate::reflector::Reflector;
+use crate::rotor::Rotor;
+use crate::scrambler::Scrambler;
+use crate::trace::{Reflection, RotorPass, StepReason, Stepped, Trace, Wire};
 
 pub struct EnigmaMachine {
     rotors: Vec<Rotor>,
     reflector: Reflector,
     plugboard: Plugboard,
+    scrambler: Scrambler,
+    non_alpha: NonAlphaPolicy,
+    preserve_case: bool,
 }
 
 impl EnigmaMachine {
@@ -14,58 +22,312 @@ impl EnigmaMachine {
             rotors,
             reflector,
             plugboard,
+            scrambler: Scrambler::new(),
+            non_alpha: NonAlphaPolicy::default(),
+            preserve_case: false,
         }
     }
 
+    pub fn non_alpha_policy(&self) -> NonAlphaPolicy {
+        self.non_alpha
+    }
+
+    pub fn set_non_alpha_policy(&mut self, policy: NonAlphaPolicy) {
+        self.non_alpha = policy;
+    }
+
+    pub fn preserves_case(&self) -> bool {
+        self.preserve_case
+    }
+
+    /// When enabled, lowercase letters take the same path as their uppercase key and come out
+    /// lowercase, instead of being treated as characters without a key.
+    pub fn set_preserve_case(&mut self, preserve_case: bool) {
+        self.preserve_case = preserve_case;
+    }
+
+    /// Current rotor window letters, leftmost rotor first.
+    pub fn positions(&self) -> String {
+        self.rotors.iter().map(Rotor::position).collect()
+    }
+
+    /// Turns every rotor to the given window letters, leftmost rotor first.
+    pub fn set_positions(&mut self, positions: &str) -> Result<(), EnigmaError> {
+        let found = positions.chars().count();
+        if found != self.rotors.len() {
+            return Err(EnigmaError::PositionCount {
+                expected: self.rotors.len(),
+                found,
+            });
+        }
+        if let Some(invalid) = positions.chars().find(|ch| !ch.is_ascii_uppercase()) {
+            return Err(EnigmaError::InvalidPosition(invalid));
+        }
+        for (rotor, position) in self.rotors.iter_mut().zip(positions.chars()) {
+            rotor.set_position(position);
+        }
+        Ok(())
+    }
+
+    /// Current ring settings, leftmost rotor first.
+    pub fn rings(&self) -> String {
+        self.rotors.iter().map(Rotor::ring).collect()
+    }
+
+    /// Sets every rotor's ring to the given letters, leftmost rotor first, keeping the window
+    /// letters where they are.
+    pub fn set_rings(&mut self, rings: &str) -> Result<(), EnigmaError> {
+        let found = rings.chars().count();
+        if found != self.rotors.len() {
+            return Err(EnigmaError::RingCount {
+                expected: self.rotors.len(),
+                found,
+            });
+        }
+        if let Some(invalid) = rings.chars().find(|ch| !ch.is_ascii_uppercase()) {
+            return Err(EnigmaError::InvalidRing(invalid));
+        }
+        for (rotor, ring) in self.rotors.iter_mut().zip(rings.chars()) {
+            rotor.set_ring(ring);
+        }
+        self.scrambler.invalidate();
+        Ok(())
+    }
+
+    /// The rotors, leftmost first.
+    pub fn rotors(&self) -> &[Rotor] {
+        &self.rotors
+    }
+
+    pub fn reflector(&self) -> &Reflector {
+        &self.reflector
+    }
+
+    pub fn plugboard(&self) -> &Plugboard {
+        &self.plugboard
+    }
+
+    /// Swaps in a different plugboard, keeping the rotors where they are.
+    pub fn set_plugboard(&mut self, plugboard: Plugboard) {
+        self.plugboard = plugboard;
+    }
+
     fn encrypt(&mut self, input: char) -> Option<char> {
-        (input.is_ascii_alphabetic() && input.is_uppercase())
-            .then(|| input)
-            .and_then(|char_to_encrypt| {
-                self.rotors
-                    .iter_mut()
-                    .fold(Some(char_to_encrypt), |current_char, rotor| {
-                        current_char.and_then(|ch| rotor.pass_through_forward(ch))
-                    })
-            })
-            .and_then(|char_after_rotors| self.reflector.encrypt(char_after_rotors))
-            .and_then(|mid_char| {
-                self.rotors
-                    .iter_mut()
-                    .rev()
-                    .fold(Some(mid_char), |current_char, rotor| {
-                        current_char.and_then(|ch| rotor.pass_through_reverse(ch))
-                    })
+        // The plugboard sits on both sides of the scrambler, keeping the machine reciprocal
+        input
+            .is_ascii_uppercase()
+            .then(|| self.plugboard.pass_through(input) as u8 - b'A')
+            .and_then(|index| {
+                self.scrambler
+                    .scramble(&self.rotors, &self.reflector, index)
             })
-            .map(|after_rotors_reverse| self.plugboard.pass_through(after_rotors_reverse))
+            .map(|scrambled| self.plugboard.pass_through((b'A' + scrambled) as char))
     }
 
     fn advance_rotors(&mut self) {
-        // Starting from the rightmost rotor
-        let mut should_advance_next = true;
-        for rotor in self.rotors.iter_mut().rev() {
-            if should_advance_next {
-                should_advance_next = rotor.turn();
-            } else {
-                break;
+        step(&mut self.rotors);
+    }
+
+    /// Presses a single key: steps the rotors, then sends `input` through the machine.
+    ///
+    /// Returns `None` for anything outside `A-Z`, without stepping the rotors.
+    pub fn encrypt_char(&mut self, input: char) -> Option<char> {
+        if !input.is_ascii_uppercase() {
+            return None;
+        }
+        self.advance_rotors();
+        self.encrypt(input)
+    }
+
+    /// Presses a single key like `encrypt_char`, recording which rotors turned and why and
+    /// every stage the current takes from the key to the lamp.
+    ///
+    /// Returns `None` for anything outside `A-Z`, without stepping the rotors.
+    pub fn encrypt_traced(&mut self, input: char) -> Option<Trace> {
+        if !input.is_ascii_uppercase() {
+            return None;
+        }
+        let stepped = step_traced(&mut self.rotors);
+
+        let pass = |rotor: &Rotor, input: char, output: Option<char>| {
+            output.map(|output| RotorPass {
+                rotor: rotor.model().to_string(),
+                window: rotor.position(),
+                ring: rotor.ring(),
+                input,
+                output,
+            })
+        };
+        let plugboard_in = Wire {
+            input,
+            output: self.plugboard.pass_through(input),
+        };
+        let mut current = plugboard_in.output;
+        let mut forward = Vec::with_capacity(self.rotors.len());
+        for rotor in &self.rotors {
+            let step = pass(rotor, current, rotor.pass_through_forward(current))?;
+            current = step.output;
+            forward.push(step);
+        }
+        let reflector = Reflection {
+            reflector: self.reflector.model().to_string(),
+            input: current,
+            output: self.reflector.encrypt(current)?,
+        };
+        current = reflector.output;
+        let mut reverse = Vec::with_capacity(self.rotors.len());
+        for rotor in self.rotors.iter().rev() {
+            let step = pass(rotor, current, rotor.pass_through_reverse(current))?;
+            current = step.output;
+            reverse.push(step);
+        }
+        let plugboard_out = Wire {
+            input: current,
+            output: self.plugboard.pass_through(current),
+        };
+
+        Some(Trace {
+            key: input,
+            stepped,
+            windows: self.positions(),
+            plugboard_in,
+            forward,
+            reflector,
+            reverse,
+            lamp: plugboard_out.output,
+            plugboard_out,
+        })
+    }
+
+    /// Types `input` on the machine, applying the `NonAlphaPolicy` to characters without a key,
+    /// and hands every resulting character to `emit`.
+    pub fn process_char(
+        &mut self,
+        input: char,
+        mut emit: impl FnMut(char),
+    ) -> Result<(), EnigmaError> {
+        if let Some(encrypted) = self.encrypt_char(input) {
+            emit(encrypted);
+            return Ok(());
+        }
+        if self.preserve_case && input.is_ascii_lowercase() {
+            if let Some(encrypted) = self.encrypt_char(input.to_ascii_uppercase()) {
+                emit(encrypted.to_ascii_lowercase());
+                return Ok(());
+            }
+        }
+
+        match self.non_alpha {
+            NonAlphaPolicy::Error => return Err(EnigmaError::InvalidCharacter(input)),
+            NonAlphaPolicy::Skip => {}
+            NonAlphaPolicy::PassThrough => emit(input),
+            NonAlphaPolicy::Substitute => {
+                let replacement = NonAlphaPolicy::substitute(input)
+                    .ok_or(EnigmaError::InvalidCharacter(input))?;
+                for ch in replacement.chars() {
+                    emit(
+                        self.encrypt_char(ch)
+                            .ok_or(EnigmaError::InvalidCharacter(ch))?,
+                    );
+                }
             }
         }
+        Ok(())
+    }
+
+    /// Lazily en/decrypts `chars`, yielding one `Result` per output character.
+    pub fn encrypt_iter<I>(&mut self, chars: I) -> EncryptIter<'_, I::IntoIter>
+    where
+        I: IntoIterator<Item = char>,
+    {
+        EncryptIter::new(chars.into_iter(), self)
     }
 
     pub fn encrypt_message(&mut self, message: &str) -> Result<String, String> {
-        message
-            .chars()
-            .map(|ch| {
-                self.advance_rotors();
-                self.encrypt(ch)
-                    .ok_or_else(|| format!("Failed to encrypt character: '{}'", ch))
+        self.encrypt_iter(message.chars())
+            .collect::<Result<String, EnigmaError>>()
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// Steps a rotor stack the way a key press does: the rightmost rotor turns, carrying leftwards.
+pub(crate) fn step(rotors: &mut [Rotor]) {
+    step_with(rotors, |_, _, _| {});
+}
+
+/// Steps the rotors like `step`, recording each rotor that turned and why.
+fn step_traced(rotors: &mut [Rotor]) -> Vec<Stepped> {
+    let mut stepped = Vec::new();
+    step_with(rotors, |rotor, from, carried_by| {
+        if rotor.position() == from {
+            return;
+        }
+        let reason = match carried_by {
+            None => StepReason::KeyPress,
+            Some(carrier) => StepReason::Carry {
+                rotor: carrier.model().to_string(),
+                notch: carrier.position(),
+            },
+        };
+        stepped.push(Stepped {
+            rotor: rotor.model().to_string(),
+            from,
+            to: rotor.position(),
+            reason,
+        });
+    });
+    stepped
+}
+
+/// Walks the rotors from the rightmost, turning each one the rotor to its right carried.
+/// `turned` sees every rotor that was turned, the window letter it left, and the rotor whose
+/// notch carried it, if any.
+fn step_with(rotors: &mut [Rotor], mut turned: impl FnMut(&Rotor, char, Option<&Rotor>)) {
+    let mut carried_by = None;
+    for index in (0..rotors.len()).rev() {
+        let from = rotors[index].position();
+        let carries = rotors[index].turn();
+        turned(
+            &rotors[index],
+            from,
+            carried_by.map(|carrier| &rotors[carrier]),
+        );
+        if !carries {
+            break;
+        }
+        carried_by = Some(index);
+    }
+}
+
+#[cfg(test)]
+impl EnigmaMachine {
+    /// The original character-by-character walk through every rotor, kept as the reference the
+    /// composed scrambler is checked and measured against.
+    fn encrypt_sequential(&mut self, input: char) -> Option<char> {
+        (input.is_ascii_alphabetic() && input.is_uppercase())
+            .then(|| self.plugboard.pass_through(input))
+            .and_then(|char_to_encrypt| {
+                self.rotors
+                    .iter()
+                    .try_fold(char_to_encrypt, |ch, rotor| rotor.pass_through_forward(ch))
+            })
+            .and_then(|char_after_rotors| self.reflector.encrypt(char_after_rotors))
+            .and_then(|mid_char| {
+                self.rotors
+                    .iter()
+                    .rev()
+                    .try_fold(mid_char, |ch, rotor| rotor.pass_through_reverse(ch))
             })
-            .collect()
+            .map(|after_rotors_reverse| self.plugboard.pass_through(after_rotors_reverse))
     }
 }
 
 #[cfg(test)]
 mod machine_tests {
     use super::*;
+    use crate::reflector::reflectors;
+    use crate::rotor::rotors;
+    use std::time::Instant;
 
     fn setup_enigma_machine() -> EnigmaMachine {
         let rotor1 = rotors::type_i('A', 'A');
@@ -104,6 +366,79 @@ mod machine_tests {
         assert_eq!(message, decrypted_msg);
     }
 
+    #[test]
+    fn test_enigma_reversibility_with_plugboard() {
+        let setup = || {
+            EnigmaMachine::new(
+                vec![rotors::type_iii('K', 'B'), rotors::type_i('Q', 'A')],
+                reflectors::ukw_b(),
+                Plugboard::new(vec![('A', 'B'), ('K', 'Z'), ('E', 'Q')]).unwrap(),
+            )
+        };
+        let message = "KEINEBESONDERENEREIGNISSE";
+
+        let encrypted_msg = setup().encrypt_message(message).unwrap();
+        assert!(message
+            .chars()
+            .zip(encrypted_msg.chars())
+            .all(|(plain, cipher)| plain != cipher));
+        assert_eq!(setup().encrypt_message(&encrypted_msg).unwrap(), message);
+    }
+
+    #[test]
+    fn test_set_positions() {
+        let mut enigma = setup_enigma_machine();
+        assert_eq!(enigma.positions(), "ABCD");
+
+        let first = enigma.encrypt_message("ENIGMA").unwrap();
+        enigma.set_positions("ABCD").unwrap();
+        assert_eq!(enigma.encrypt_message("ENIGMA").unwrap(), first);
+
+        assert_eq!(
+            enigma.set_positions("ABC"),
+            Err(EnigmaError::PositionCount {
+                expected: 4,
+                found: 3
+            })
+        );
+        assert_eq!(
+            enigma.set_positions("AB1D"),
+            Err(EnigmaError::InvalidPosition('1'))
+        );
+    }
+
+    #[test]
+    fn test_set_rings() {
+        let mut enigma = setup_enigma_machine();
+        enigma.encrypt_message("WARMUP").unwrap();
+        enigma.set_positions("ABCD").unwrap();
+        enigma.set_rings("QEVZ").unwrap();
+        assert_eq!(enigma.rings(), "QEVZ");
+        assert_eq!(enigma.positions(), "ABCD");
+
+        let rotors = vec![
+            rotors::type_i('A', 'Q'),
+            rotors::type_ii('B', 'E'),
+            rotors::type_iii('C', 'V'),
+            rotors::type_iv('D', 'Z'),
+        ];
+        let mut expected =
+            EnigmaMachine::new(rotors, reflectors::ukw_b(), Plugboard::new(vec![]).unwrap());
+        assert_eq!(
+            enigma.encrypt_message("ENIGMA").unwrap(),
+            expected.encrypt_message("ENIGMA").unwrap()
+        );
+
+        assert_eq!(
+            enigma.set_rings("AAAAA"),
+            Err(EnigmaError::RingCount {
+                expected: 4,
+                found: 5
+            })
+        );
+        assert_eq!(enigma.set_rings("AaAA"), Err(EnigmaError::InvalidRing('a')));
+    }
+
     #[test]
     fn test_enigma_encryption_failure() {
         let message = "BANBURISMUS9";
@@ -114,4 +449,165 @@ mod machine_tests {
             Err(e) => assert_eq!(e, "Failed to encrypt character: '9'"),
         }
     }
+
+    #[test]
+    fn test_preserve_case() {
+        let mut enigma = setup_enigma_machine();
+        enigma.set_preserve_case(true);
+        let mixed = enigma.encrypt_message("Enigma").unwrap();
+
+        let upper = setup_enigma_machine().encrypt_message("ENIGMA").unwrap();
+        assert_eq!(mixed[..1], upper[..1]);
+        assert_eq!(mixed[1..], upper[1..].to_ascii_lowercase());
+
+        let mut enigma_reset = setup_enigma_machine();
+        enigma_reset.set_preserve_case(true);
+        assert_eq!(enigma_reset.encrypt_message(&mixed).unwrap(), "Enigma");
+    }
+
+    #[test]
+    fn test_lowercase_rejected_without_preserve_case() {
+        let mut enigma = setup_enigma_machine();
+        assert!(enigma.encrypt_message("enigma").is_err());
+    }
+
+    #[test]
+    fn test_non_alpha_skip() {
+        let mut enigma = setup_enigma_machine();
+        enigma.set_non_alpha_policy(NonAlphaPolicy::Skip);
+        let skipped = enigma.encrypt_message("HELLO, WORLD 9").unwrap();
+
+        let expected = setup_enigma_machine()
+            .encrypt_message("HELLOWORLD")
+            .unwrap();
+        assert_eq!(skipped, expected);
+    }
+
+    #[test]
+    fn test_non_alpha_pass_through_does_not_step() {
+        let mut enigma = setup_enigma_machine();
+        enigma.set_non_alpha_policy(NonAlphaPolicy::PassThrough);
+        let passed = enigma.encrypt_message("HELLO WORLD\n").unwrap();
+
+        let expected = setup_enigma_machine()
+            .encrypt_message("HELLOWORLD")
+            .unwrap();
+        assert_eq!(passed, format!("{} {}\n", &expected[..5], &expected[5..]));
+    }
+
+    #[test]
+    fn test_non_alpha_substitute() {
+        let mut enigma = setup_enigma_machine();
+        enigma.set_non_alpha_policy(NonAlphaPolicy::Substitute);
+        let substituted = enigma.encrypt_message("U 2").unwrap();
+
+        let expected = setup_enigma_machine().encrypt_message("UXZWO").unwrap();
+        assert_eq!(substituted, expected);
+
+        let mut enigma = setup_enigma_machine();
+        enigma.set_non_alpha_policy(NonAlphaPolicy::Substitute);
+        assert_eq!(
+            enigma.encrypt_message("U#"),
+            Err("Failed to encrypt character: '#'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_encrypt_traced_matches_encrypt_char() {
+        let plugboard = || Plugboard::new(vec![('A', 'Q'), ('E', 'Z'), ('K', 'M')]).unwrap();
+        let machine = || {
+            let rotors = vec![
+                rotors::type_ii('A', 'C'),
+                rotors::type_iv('D', 'A'),
+                rotors::type_v('X', 'F'),
+            ];
+            EnigmaMachine::new(rotors, reflectors::ukw_b(), plugboard())
+        };
+        let (mut traced, mut plain) = (machine(), machine());
+        let mut carries = 0;
+        for key in "THEQUICKBROWNFOXJUMPSOVERTHELAZYDOG"
+            .chars()
+            .cycle()
+            .take(700)
+        {
+            let trace = traced.encrypt_traced(key).unwrap();
+            assert_eq!(Some(trace.lamp), plain.encrypt_char(key));
+            assert_eq!(trace.windows, plain.positions());
+            assert_eq!(trace.plugboard_in.output, trace.forward[0].input);
+            assert_eq!(trace.reflector.input, trace.forward[2].output);
+            assert_eq!(trace.reverse[2].output, trace.plugboard_out.input);
+            assert_eq!(trace.stepped[0].reason, StepReason::KeyPress);
+            for pair in trace.stepped.windows(2) {
+                assert_eq!(
+                    pair[1].reason,
+                    StepReason::Carry {
+                        rotor: pair[0].rotor.clone(),
+                        notch: pair[0].to,
+                    }
+                );
+            }
+            carries += trace.stepped.len() - 1;
+        }
+        assert!(carries >= 26, "only {} carries", carries);
+        assert_eq!(traced.encrypt_traced('1'), None);
+        assert_eq!(traced.positions(), plain.positions());
+    }
+
+    #[test]
+    fn test_scrambler_matches_sequential_fold() {
+        let build = || {
+            EnigmaMachine::new(
+                vec![
+                    rotors::type_ii('X', 'D'),
+                    rotors::type_v('Y', 'A'),
+                    rotors::type_i('Z', 'Q'),
+                ],
+                reflectors::ukw_c(),
+                Plugboard::new(vec![('A', 'M'), ('Q', 'T'), ('E', 'Z')]).unwrap(),
+            )
+        };
+        let mut fast = build();
+        let mut reference = build();
+
+        // Long enough for the middle and slow rotors to carry several times
+        for ch in "WETTERVORHERSAGE".chars().cycle().take(26 * 26 * 3) {
+            fast.advance_rotors();
+            reference.advance_rotors();
+            assert_eq!(fast.encrypt(ch), reference.encrypt_sequential(ch));
+        }
+    }
+
+    #[test]
+    fn benchmark_scrambler_against_sequential_fold() {
+        let input: Vec<char> = std::iter::repeat_n('A', 128 * 1024).collect();
+
+        let mut enigma = setup_enigma_machine();
+        let start_time = Instant::now();
+        let scrambled: Vec<Option<char>> = input
+            .iter()
+            .map(|&ch| {
+                enigma.advance_rotors();
+                enigma.encrypt(ch)
+            })
+            .collect();
+        let scrambler_time = start_time.elapsed();
+
+        let mut enigma = setup_enigma_machine();
+        let start_time = Instant::now();
+        let folded: Vec<Option<char>> = input
+            .iter()
+            .map(|&ch| {
+                enigma.advance_rotors();
+                enigma.encrypt_sequential(ch)
+            })
+            .collect();
+        let sequential_time = start_time.elapsed();
+
+        assert_eq!(scrambled, folded);
+
+        println!(
+            "Time taken to encrypt 128KB: scrambler {:?}, sequential fold {:?}",
+            scrambler_time, sequential_time
+        );
+    }
 }
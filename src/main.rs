@@ -1,9 +1,28 @@
-use clap::Parser;
-use enigma_shark::{reflectors, rotors, EnigmaMachine, Plugboard};
+use clap::{Parser, Subcommand};
+use enigma_shark::banburismus::{self, Banburismus, Intercept, Scritchmus, THRESHOLD};
+use enigma_shark::bombe::{self, crib_drag, Bombe, Menu};
+use enigma_shark::crack::{Cracker, CANDIDATES};
+use enigma_shark::keysheet::{KeySheet, Month};
+use enigma_shark::message::{self, PART_LENGTH};
+use enigma_shark::naval::NavalTables;
+use enigma_shark::ngram::{Language, NgramModel};
+use enigma_shark::procedure::{random_key, Indicator, Procedure};
+use enigma_shark::recover::{Recoverer, LIMIT};
+use enigma_shark::rejewski::{Catalogue, Characteristic};
+use enigma_shark::zygalski;
+use enigma_shark::{
+    reflectors, rotors, ungroup, Convention, EnigmaError, EnigmaMachine, EnigmaReader, GroupFormat,
+    GroupWriter, Model, NonAlphaPolicy, Plugboard, TraceFormat,
+};
+use rand::rngs::StdRng;
+use rand::{RngCore, SeedableRng};
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::fs;
-use std::io::{Cursor, Read};
+use std::io::{self, Cursor, Read, Write};
+
+#[cfg(feature = "tui")]
+mod tui;
 
 #[derive(Deserialize, Debug)]
 struct RotorConfig {
@@ -13,98 +32,891 @@ struct RotorConfig {
     ring: char,
 }
 
-#[derive(Deserialize, Debug)]
-struct PlugboardMapping {
-    from: char,
-    to: char,
-}
-
 #[derive(Deserialize, Debug)]
 struct MachineConfig {
     rotors: Vec<RotorConfig>,
     reflector: String,
-    //plugboard: Vec<PlugboardMapping>,
     plugboard: HashMap<char, char>,
 }
 
 #[derive(Parser, Debug)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// Optional: Input message or file for encryption
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     input: Option<String>,
 
     /// Optional: Configuration file for machine settings
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     config: Option<String>,
+
+    /// Optional: Key sheet file from `keygen --json` to take the day's machine settings from
+    #[arg(long, global = true, conflicts_with = "config", requires = "day")]
+    keysheet: Option<String>,
+
+    /// Optional: Day of the month to use from the key sheet
+    #[arg(long, global = true, requires = "keysheet")]
+    day: Option<u32>,
+
+    /// Optional: Machine model: enigma-i, m3 or m4 (m3 for keygen; checked against a key sheet)
+    #[arg(long, global = true)]
+    model: Option<Model>,
+
+    /// Optional: Handling of characters outside A-Z: error, skip, pass-through or substitute
+    #[arg(long, default_value = "error", global = true)]
+    non_alpha: NonAlphaPolicy,
+
+    /// Optional: Encrypt lowercase letters to lowercase instead of upper-casing the input
+    #[arg(long, global = true)]
+    preserve_case: bool,
+
+    /// Optional: Historical plaintext convention: heer, luftwaffe or kriegsmarine
+    #[arg(long, global = true)]
+    convention: Option<Convention>,
+
+    /// Optional: Treat the input as ciphertext, formatting the output with the convention
+    #[arg(short, long, global = true)]
+    decrypt: bool,
+
+    /// Optional: Print the output in groups of N letters, e.g. 5 (Heer) or 4 (Kriegsmarine); 0 for raw
+    #[arg(long, default_value_t = 0, global = true)]
+    groups: usize,
+
+    /// Optional: Number of letter groups per line (0 keeps them all on one line)
+    #[arg(long, default_value_t = 10, global = true)]
+    groups_per_line: usize,
+
+    /// Optional: Seed for generated keys, sheets and tables, to make them reproducible
+    #[arg(long, global = true)]
+    seed: Option<u64>,
+
+    /// Optional: Print what each key press does inside the machine, as a table or json, instead of the output
+    #[arg(long)]
+    trace: Option<TraceFormat>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Encrypt a whole message under a fresh message key, printing its indicator and body
+    Send {
+        /// Message key procedure: pre-1940 (doubled indicator) or post-1940
+        #[arg(long, default_value = "post-1940")]
+        procedure: Procedure,
+
+        /// Optional: Message key to use instead of a random one (not with a radio header)
+        #[arg(long, conflicts_with = "to")]
+        message_key: Option<String>,
+
+        /// Optional: Grundstellung to use instead of a random one (post-1940, not with a radio header)
+        #[arg(long, conflicts_with = "to")]
+        grundstellung: Option<String>,
+
+        /// Optional: Kenngruppenbuch and bigram table file; selects the Kriegsmarine procedure
+        #[arg(long)]
+        naval_tables: Option<String>,
+
+        /// Optional: Callsign of the receiving station; adds a radio header and splits long
+        /// messages into parts, each under a fresh message key
+        #[arg(long, requires = "from", conflicts_with = "naval_tables")]
+        to: Option<String>,
+
+        /// Optional: Callsign of the sending station
+        #[arg(long, requires = "to")]
+        from: Option<String>,
+
+        /// Optional: Time of origin as HHMM for the radio header (defaults to now, UTC)
+        #[arg(long, requires = "to")]
+        time: Option<String>,
+
+        /// Optional: Most letters per part of a message with a radio header
+        #[arg(long, default_value_t = PART_LENGTH, requires = "to")]
+        part_length: usize,
+    },
+    /// Decrypt a message produced by `send`
+    Receive {
+        /// Message key procedure: pre-1940 (doubled indicator) or post-1940
+        #[arg(long, default_value = "post-1940")]
+        procedure: Procedure,
+
+        /// Optional: Kenngruppenbuch and bigram table file; selects the Kriegsmarine procedure
+        #[arg(long)]
+        naval_tables: Option<String>,
+
+        /// Optional: Read a full intercept with its radio header, in one or more parts; the
+        /// procedure follows the indicator
+        #[arg(long, conflicts_with = "naval_tables")]
+        header: bool,
+    },
+    /// Generate a monthly key sheet
+    Keygen {
+        /// Month to issue keys for, as YYYY-MM
+        #[arg(long)]
+        month: Month,
+
+        /// Optional: Issue a daily UKW-D wiring instead of a fixed reflector
+        #[arg(long)]
+        ukw_d: bool,
+
+        /// Optional: Print the sheet as JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Generate a random Kenngruppenbuch and bigram table as JSON
+    NavalTables {
+        /// Number of trigrams in the Kenngruppenbuch
+        #[arg(long, default_value_t = 100)]
+        kenngruppen: usize,
+    },
+    /// Run a Bombe over every rotor position for a crib against a ciphertext
+    Bombe {
+        /// Guessed plaintext, e.g. WETTERVORHERSAGE
+        #[arg(long)]
+        crib: String,
+
+        /// File holding the ciphertext
+        #[arg(long)]
+        ciphertext: String,
+
+        /// Optional: Letter of the ciphertext the crib starts at, counting from 0
+        #[arg(long, default_value_t = 0)]
+        offset: usize,
+
+        /// Optional: Only try this wheel order, e.g. ii,v,iii (default: every order of --model)
+        #[arg(long)]
+        wheels: Option<String>,
+
+        /// Optional: Ring settings to run with (default: all A)
+        #[arg(long)]
+        rings: Option<String>,
+
+        /// Optional: Reflector to run with
+        #[arg(long, default_value = "ukw_b")]
+        reflector: String,
+    },
+    /// Recover the key and plaintext of a ciphertext with no crib, by hill climbing
+    Crack {
+        /// File holding the ciphertext
+        ciphertext: String,
+
+        /// Optional: Only try this wheel order, e.g. ii,v,iii (default: every order of --model)
+        #[arg(long)]
+        wheels: Option<String>,
+
+        /// Optional: Only try this reflector (default: every reflector of --model)
+        #[arg(long)]
+        reflector: Option<String>,
+
+        /// Optional: Ring settings, if known; otherwise they are searched too
+        #[arg(long)]
+        rings: Option<String>,
+
+        /// Optional: Number of best settings to hill-climb a plugboard for
+        #[arg(long, default_value_t = CANDIDATES)]
+        candidates: usize,
+
+        /// Optional: Language of the plaintext (german or english)
+        #[arg(long, default_value = "german")]
+        language: Language,
+
+        /// Optional: N-gram model trained with train-ngrams, instead of --language
+        #[arg(long, conflicts_with = "language")]
+        ngrams: Option<String>,
+    },
+    /// List every key (wheel order, rings, positions, plugboard) that turns a plaintext into its
+    /// ciphertext
+    Recover {
+        /// File holding the known plaintext
+        #[arg(long)]
+        plaintext: String,
+
+        /// File holding the ciphertext
+        #[arg(long)]
+        ciphertext: String,
+
+        /// Optional: Only try this wheel order, e.g. ii,v,iii (default: every order of --model)
+        #[arg(long)]
+        wheels: Option<String>,
+
+        /// Optional: Only try this reflector (default: every reflector of --model)
+        #[arg(long)]
+        reflector: Option<String>,
+
+        /// Optional: Ring settings, if known; otherwise they are searched too
+        #[arg(long)]
+        rings: Option<String>,
+
+        /// Optional: Most keys to list before asking for a longer pair
+        #[arg(long, default_value_t = LIMIT)]
+        limit: usize,
+    },
+    /// Score a day of naval intercepts for depth in decibans and narrow the right wheel
+    Banburismus {
+        /// File holding the day's naval messages as sent, separated by blank lines
+        messages: String,
+
+        /// Kenngruppenbuch and bigram table file, to read the Verfahrenkenngruppen with
+        #[arg(long)]
+        naval_tables: String,
+
+        /// Optional: Decibans a pair must reach to count as in depth
+        #[arg(long, default_value_t = THRESHOLD)]
+        threshold: f64,
+
+        /// Optional: Language of the plaintext (german or english)
+        #[arg(long, default_value = "german")]
+        language: Language,
+    },
+    /// Find a day's Grundstellung from its doubled indicators with a cycle catalogue
+    Rejewski {
+        /// File holding the day's six-letter indicators, separated by whitespace
+        #[arg(long)]
+        indicators: String,
+
+        /// Optional: Only catalogue this wheel order, e.g. ii,v,iii (default: every order of --model)
+        #[arg(long)]
+        wheels: Option<String>,
+
+        /// Optional: Ring settings to catalogue (default: all A)
+        #[arg(long)]
+        rings: Option<String>,
+
+        /// Optional: Reflector to catalogue
+        #[arg(long, default_value = "ukw_b")]
+        reflector: String,
+    },
+    /// Stack a Zygalski sheet per female of the day's indicators and print the rings left lit
+    Zygalski {
+        /// File holding the day's indicators, a Grundstellung and a doubled key per line, e.g. KWT PQRPSX
+        #[arg(long)]
+        indicators: String,
+
+        /// Optional: Only search this wheel order, e.g. ii,v,iii (default: every order of --model)
+        #[arg(long)]
+        wheels: Option<String>,
+
+        /// Optional: Reflector to search
+        #[arg(long, default_value = "ukw_b")]
+        reflector: String,
+    },
+    /// Draw the Zygalski sheets of a wheel order as SVG files or a PDF
+    ZygalskiSheets {
+        /// Wheel order, e.g. ii,v,iii
+        #[arg(long)]
+        wheels: String,
+
+        /// Optional: Ring settings (default: all A)
+        #[arg(long)]
+        rings: Option<String>,
+
+        /// Optional: Reflector of the machine
+        #[arg(long, default_value = "ukw_b")]
+        reflector: String,
+
+        /// Optional: Which letter of the key and its repeat to punch for, 1 for the first and fourth
+        #[arg(long, default_value_t = 1)]
+        pair: usize,
+
+        /// Directory for one SVG per sheet, or the PDF file with --pdf
+        #[arg(long)]
+        out: String,
+
+        /// Optional: Write all sheets to one PDF instead
+        #[arg(long)]
+        pdf: bool,
+    },
+    /// Count the 1- to 4-letter sequences of a corpus and print them as an n-gram model (JSON)
+    TrainNgrams {
+        /// Text file to train on
+        corpus: String,
+
+        /// Optional: Letter to write between words, e.g. X for German military traffic
+        #[arg(long)]
+        separator: Option<char>,
+    },
+    /// Slide a crib along a ciphertext and print a Bombe menu for every placement that fits
+    CribDrag {
+        /// Guessed plaintext, e.g. KEINEBESONDERENEREIGNISSE
+        #[arg(long)]
+        crib: String,
+
+        /// File holding the ciphertext
+        #[arg(long)]
+        ciphertext: String,
+
+        /// Optional: Reflector of the machine, which must have no fixed points
+        #[arg(long, default_value = "ukw_b")]
+        reflector: String,
+    },
+    /// Type on a full-screen lampboard, changing window letters, rings and plugs live
+    #[cfg(feature = "tui")]
+    Tui,
 }
 
 fn main() {
     let args = Args::parse();
+    match &args.command {
+        Some(Command::Keygen { .. }) => keygen_command(&args),
+        Some(Command::NavalTables { .. }) => naval_tables_command(&args),
+        Some(Command::Bombe { .. }) => bombe_command(&args),
+        Some(Command::Rejewski { .. }) => rejewski_command(&args),
+        Some(Command::Banburismus { .. }) => banburismus_command(&args),
+        Some(Command::Zygalski { .. }) => zygalski_command(&args),
+        Some(Command::ZygalskiSheets { .. }) => zygalski_sheets_command(&args),
+        Some(Command::TrainNgrams { .. }) => train_ngrams_command(&args),
+        Some(Command::Recover { .. }) => recover_command(&args),
+        Some(Command::Crack { .. }) => crack_command(&args),
+        Some(Command::CribDrag { .. }) => crib_drag_command(&args),
+        #[cfg(feature = "tui")]
+        Some(Command::Tui) => tui_command(&args),
+        Some(Command::Send { .. } | Command::Receive { .. }) => message_command(&args),
+        None => encrypt_command(&args),
+    }
+}
+
+/// Reports bad input on stderr and exits.
+fn fail(message: impl std::fmt::Display) -> ! {
+    eprintln!("Error: {}", message);
+    std::process::exit(1);
+}
+
+/// Prints the report of a command, or exits with its error.
+fn print_report(result: Result<String, EnigmaError>, action: &str) {
+    match result {
+        Ok(report) => print!("{}", report),
+        Err(err) => {
+            eprintln!("{} failed with error: {}", action, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Reads a file named on the command line, or exits if it cannot be read.
+fn read_file(path: &str, what: &str) -> String {
+    fs::read_to_string(path).unwrap_or_else(|err| {
+        fail(format!(
+            "Failed to read the {} file {}: {}",
+            what, path, err
+        ))
+    })
+}
+
+fn keygen_command(args: &Args) {
+    let Some(Command::Keygen { month, ukw_d, json }) = args.command else {
+        unreachable!("keygen_command called without the keygen command");
+    };
+    let model = args.model.unwrap_or(Model::M3);
+    if ukw_d && !model.supports_ukw_d() {
+        fail(format!("The {} does not take a UKW-D.", model));
+    }
+    let sheet = KeySheet::generate(&mut key_rng(args.seed), model, month, ukw_d);
+    if json {
+        match serde_json::to_string_pretty(&sheet) {
+            Ok(json) => println!("{}", json),
+            Err(err) => panic!("Failed to serialize the key sheet: {}", err),
+        }
+    } else {
+        println!("{}", sheet.table());
+    }
+}
 
-    let mut enigma_machine = match args.config {
-        Some(config_file) => {
-            let config = fs::read_to_string(config_file)
-                .expect("Failed to read the machine configuration file");
-            match setup_enigma_from_config(Some(config)) {
-                Ok(machine) => machine,
-                Err(err) => panic!("Failed to set up the enigma machine: {}", err),
+fn naval_tables_command(args: &Args) {
+    let Some(Command::NavalTables { kenngruppen }) = args.command else {
+        unreachable!("naval_tables_command called without the naval-tables command");
+    };
+    let tables = NavalTables::generate(&mut key_rng(args.seed), kenngruppen);
+    match serde_json::to_string_pretty(&tables) {
+        Ok(json) => println!("{}", json),
+        Err(err) => panic!("Failed to serialize the naval tables: {}", err),
+    }
+}
+
+fn bombe_command(args: &Args) {
+    let Some(Command::Bombe {
+        crib,
+        ciphertext,
+        offset,
+        wheels,
+        rings,
+        reflector,
+    }) = &args.command
+    else {
+        unreachable!("bombe_command called without the bombe command");
+    };
+    let ciphertext = read_file(ciphertext, "ciphertext");
+    let model = args.model.unwrap_or(Model::M3);
+    let report = run_bombe(
+        crib,
+        &ciphertext,
+        *offset,
+        model,
+        wheels.as_deref(),
+        rings.as_deref(),
+        reflector,
+    );
+    print_report(report, "Bombe");
+}
+
+fn rejewski_command(args: &Args) {
+    let Some(Command::Rejewski {
+        indicators,
+        wheels,
+        rings,
+        reflector,
+    }) = &args.command
+    else {
+        unreachable!("rejewski_command called without the rejewski command");
+    };
+    let indicators = read_file(indicators, "indicators");
+    let model = args.model.unwrap_or(Model::EnigmaI);
+    let report = run_rejewski(
+        &indicators,
+        model,
+        wheels.as_deref(),
+        rings.as_deref(),
+        reflector,
+    );
+    print_report(report, "Catalogue lookup");
+}
+
+fn banburismus_command(args: &Args) {
+    let Some(Command::Banburismus {
+        messages,
+        naval_tables,
+        threshold,
+        language,
+    }) = &args.command
+    else {
+        unreachable!("banburismus_command called without the banburismus command");
+    };
+    let messages = read_file(messages, "messages");
+    let tables = load_naval_tables(naval_tables);
+    let model = args.model.unwrap_or(Model::M3);
+    let report = run_banburismus(&messages, &tables, model, *threshold, language.model());
+    print_report(report, "Banburismus");
+}
+
+fn zygalski_command(args: &Args) {
+    let Some(Command::Zygalski {
+        indicators,
+        wheels,
+        reflector,
+    }) = &args.command
+    else {
+        unreachable!("zygalski_command called without the zygalski command");
+    };
+    let indicators = read_file(indicators, "indicators");
+    let model = args.model.unwrap_or(Model::EnigmaI);
+    let report = run_zygalski(&indicators, model, wheels.as_deref(), reflector);
+    print_report(report, "Sheet search");
+}
+
+fn zygalski_sheets_command(args: &Args) {
+    let Some(Command::ZygalskiSheets {
+        wheels,
+        rings,
+        reflector,
+        pair,
+        out,
+        pdf,
+    }) = &args.command
+    else {
+        unreachable!("zygalski_sheets_command called without the zygalski-sheets command");
+    };
+    let report = write_zygalski_sheets(wheels, rings.as_deref(), reflector, *pair, out, *pdf);
+    print_report(report, "Drawing the sheets");
+}
+
+fn train_ngrams_command(args: &Args) {
+    let Some(Command::TrainNgrams { corpus, separator }) = &args.command else {
+        unreachable!("train_ngrams_command called without the train-ngrams command");
+    };
+    let corpus = read_file(corpus, "corpus");
+    match serde_json::to_string(&NgramModel::train(&corpus, *separator)) {
+        Ok(json) => println!("{}", json),
+        Err(err) => panic!("Failed to serialize the n-gram model: {}", err),
+    }
+}
+
+fn recover_command(args: &Args) {
+    let Some(Command::Recover {
+        plaintext,
+        ciphertext,
+        wheels,
+        reflector,
+        rings,
+        limit,
+    }) = &args.command
+    else {
+        unreachable!("recover_command called without the recover command");
+    };
+    let plaintext = read_file(plaintext, "plaintext");
+    let ciphertext = read_file(ciphertext, "ciphertext");
+    let model = args.model.unwrap_or(Model::M3);
+    let report = run_recover(
+        &plaintext,
+        &ciphertext,
+        model,
+        wheels.as_deref(),
+        reflector.as_deref(),
+        rings.as_deref(),
+        *limit,
+    );
+    print_report(report, "Recovery");
+}
+
+fn crack_command(args: &Args) {
+    let Some(Command::Crack {
+        ciphertext,
+        wheels,
+        reflector,
+        rings,
+        candidates,
+        language,
+        ngrams,
+    }) = &args.command
+    else {
+        unreachable!("crack_command called without the crack command");
+    };
+    let ciphertext = read_file(ciphertext, "ciphertext");
+    let model = args.model.unwrap_or(Model::M3);
+    let ngrams = match ngrams {
+        Some(path) => load_ngrams(path),
+        None => language.model().clone(),
+    };
+    let report = run_crack(
+        &ciphertext,
+        model,
+        wheels.as_deref(),
+        reflector.as_deref(),
+        rings.as_deref(),
+        *candidates,
+        &ngrams,
+    );
+    print_report(report, "Crack");
+}
+
+fn crib_drag_command(args: &Args) {
+    let Some(Command::CribDrag {
+        crib,
+        ciphertext,
+        reflector,
+    }) = &args.command
+    else {
+        unreachable!("crib_drag_command called without the crib-drag command");
+    };
+    let text = read_file(ciphertext, "ciphertext");
+    let model = args.model.unwrap_or(Model::M3);
+    print_report(
+        run_crib_drag(crib, &text, ciphertext, model, reflector),
+        "Crib drag",
+    );
+}
+
+#[cfg(feature = "tui")]
+fn tui_command(args: &Args) {
+    if let Err(err) = tui::run(setup_enigma_from_args(args)) {
+        eprintln!("Lampboard failed with error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+fn message_command(args: &Args) {
+    let mut enigma_machine = setup_enigma_from_args(args);
+    enigma_machine.set_non_alpha_policy(args.non_alpha);
+    enigma_machine.set_preserve_case(args.preserve_case);
+
+    let mut message = String::new();
+    if let Err(err) = input_reader(args).read_to_string(&mut message) {
+        fail(format!("Failed to read the input: {}", err));
+    }
+    let result = match args.command {
+        Some(Command::Send { .. }) => send_message(&message, &mut enigma_machine, args),
+        _ => receive_message(&message, &mut enigma_machine, args),
+    };
+    match result {
+        Ok(output) => println!("{}", output),
+        Err(err) => {
+            eprintln!("Message procedure failed with error: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn encrypt_command(args: &Args) {
+    let mut enigma_machine = setup_enigma_from_args(args);
+    enigma_machine.set_non_alpha_policy(args.non_alpha);
+    enigma_machine.set_preserve_case(args.preserve_case);
+    let mut reader = input_reader(args);
+
+    if let Some(trace) = args.trace {
+        let mut input = String::new();
+        if let Err(err) = reader.read_to_string(&mut input) {
+            fail(format!("Failed to read the input: {}", err));
+        }
+        match trace_with_enigma(&input, &mut enigma_machine, args, trace) {
+            Ok(report) => print!("{}", report),
+            Err(err) => {
+                eprintln!("Tracing failed with error: {}", err);
+                std::process::exit(1);
             }
         }
-        None => match setup_enigma_from_config(None) {
-            Ok(machine) => machine,
-            Err(err) => panic!("Failed to set up the enigma machine: {}", err),
-        },
+        return;
+    }
+
+    let format = GroupFormat::new(args.groups, args.groups_per_line);
+    let output = GroupWriter::new(io::stdout().lock(), format);
+    if let Err(err) = encrypt_stream(reader, enigma_machine, args, output) {
+        eprintln!("Encryption failed with error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+/// Builds the machine from `--keysheet`, `--config` or the default settings.
+fn setup_enigma_from_args(args: &Args) -> EnigmaMachine {
+    let machine = match (&args.keysheet, &args.config) {
+        (Some(keysheet_file), _) => {
+            let keysheet = read_file(keysheet_file, "key sheet");
+            let Some(day) = args.day else {
+                fail("--keysheet requires --day");
+            };
+            setup_enigma_from_keysheet(&keysheet, day, args.model)
+        }
+        (None, Some(config_file)) => {
+            let config = read_file(config_file, "machine configuration");
+            setup_enigma_from_config(Some(config))
+        }
+        (None, None) => setup_enigma_from_config(None),
     };
+    machine.unwrap_or_else(|err| fail(err))
+}
 
-    let mut reader: Box<dyn Read> = match args.input {
+/// The text to work on: `--input` at a terminal, stdin otherwise.
+fn input_reader(args: &Args) -> Box<dyn Read> {
+    match &args.input {
         Some(input_str) if atty::is(atty::Stream::Stdin) => {
-            Box::new(Cursor::new(input_str.into_bytes()))
+            Box::new(Cursor::new(input_str.clone().into_bytes()))
         }
         None if !atty::is(atty::Stream::Stdin) => Box::new(std::io::stdin()),
-        _ => {
-            eprintln!("Error: Please provide input through stdin or use the '--input' option.");
-            std::process::exit(1);
+        _ => fail("Please provide input through stdin or use the '--input' option."),
+    }
+}
+
+/// Sends `input` through the machine with an `EnigmaReader`, after `Keyed` has prepared it, and
+/// lays the result out through `output`.
+fn encrypt_stream<R: Read, W: Write>(
+    input: R,
+    enigma: EnigmaMachine,
+    args: &Args,
+    mut output: GroupWriter<W>,
+) -> io::Result<W> {
+    let preparation = Preparation::new(args, &enigma);
+    let mut reader = EnigmaReader::new(Keyed::new(input, preparation), enigma);
+    let copied = match (args.convention, args.decrypt) {
+        // Restoring the convention looks across word boundaries, so it waits for the whole text
+        (Some(convention), true) => {
+            let mut decrypted = Vec::new();
+            reader.read_to_end(&mut decrypted).and_then(|_| {
+                let formatted = convention.format(&String::from_utf8_lossy(&decrypted));
+                output.write_all(formatted.as_bytes())
+            })
         }
+        _ => io::copy(&mut reader, &mut output).map(drop),
     };
+    // End the line of whatever came out before an error, so the error starts on its own
+    let output = output.finish()?;
+    copied.map(|()| output)
+}
 
-    let mut buffer = [0; 4096];
-    while let Ok(len) = reader.read(&mut buffer) {
-        if len == 0 {
-            break;
+/// How the command line rewrites text before it is keyed.
+#[derive(Debug, Clone, Copy)]
+struct Preparation {
+    /// Plaintext convention to apply while encrypting.
+    convention: Option<Convention>,
+    /// Drop the spacing of letter groups while decrypting.
+    ungroup: bool,
+    uppercase: bool,
+}
+
+impl Preparation {
+    fn new(args: &Args, enigma: &EnigmaMachine) -> Self {
+        Preparation {
+            convention: args.convention.filter(|_| !args.decrypt),
+            // Ciphertext may arrive in letter groups; the spacing is layout, not text
+            ungroup: args.decrypt && enigma.non_alpha_policy() != NonAlphaPolicy::PassThrough,
+            uppercase: !enigma.preserves_case(),
         }
-        let input_chunk = String::from_utf8_lossy(&buffer[..len]);
-        println!(
-            "{}",
-            encrypt_with_enigma(input_chunk.to_string(), &mut enigma_machine)
-        );
+    }
+
+    fn apply(&self, text: &str) -> Result<String, EnigmaError> {
+        let text = match (self.convention, self.ungroup) {
+            (Some(convention), _) => convention.preprocess(text)?,
+            (None, true) => ungroup(text),
+            (None, false) => text.to_string(),
+        };
+        Ok(match self.uppercase {
+            true => text.to_uppercase(),
+            false => text,
+        })
     }
 }
 
+/// Reads text prepared for keying, with the whitespace at the very start and end of the stream
+/// dropped. A convention reads across words, abbreviations and names, so text under one is
+/// prepared whole; anything else goes through as it arrives, never cutting a character in two.
+struct Keyed<R: Read> {
+    inner: R,
+    preparation: Preparation,
+    scratch: Vec<u8>,
+    carry: Vec<u8>,
+    ready: Vec<u8>,
+    consumed: usize,
+    started: bool,
+    done: bool,
+}
+
+impl<R: Read> Keyed<R> {
+    fn new(inner: R, preparation: Preparation) -> Self {
+        Keyed {
+            inner,
+            preparation,
+            scratch: vec![0; 4096],
+            carry: Vec::new(),
+            ready: Vec::new(),
+            consumed: 0,
+            started: false,
+            done: false,
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let len = self.inner.read(&mut self.scratch)?;
+        self.carry.extend_from_slice(&self.scratch[..len]);
+        self.done = len == 0;
+
+        let split = match (self.done, self.preparation.convention) {
+            (true, _) => self.carry.len(),
+            (false, Some(_)) => 0,
+            // Hold back trailing whitespace, in case nothing follows it, and a split character
+            (false, None) => {
+                let whole = whole_chars(&self.carry);
+                self.carry[..whole]
+                    .iter()
+                    .rposition(|byte| !byte.is_ascii_whitespace())
+                    .map_or(0, |index| index + 1)
+            }
+        };
+        let chunk: Vec<u8> = self.carry.drain(..split).collect();
+        let mut text = String::from_utf8_lossy(&chunk).into_owned();
+        if !self.started {
+            text = text.trim_start().to_string();
+        }
+        if self.done {
+            text = text.trim_end().to_string();
+        }
+        self.started |= !text.is_empty();
+        self.ready = self
+            .preparation
+            .apply(&text)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?
+            .into_bytes();
+        self.consumed = 0;
+        Ok(())
+    }
+}
+
+/// The length of `bytes` without a UTF-8 sequence cut short at its end.
+fn whole_chars(bytes: &[u8]) -> usize {
+    let Some(back) = bytes
+        .iter()
+        .rev()
+        .take(4)
+        .position(|byte| byte & 0xC0 != 0x80)
+    else {
+        return bytes.len();
+    };
+    let lead = bytes.len() - 1 - back;
+    let width = match bytes[lead] {
+        0xF0.. => 4,
+        0xE0.. => 3,
+        0xC0.. => 2,
+        _ => 1,
+    };
+    match lead + width > bytes.len() {
+        true => lead,
+        false => bytes.len(),
+    }
+}
+
+impl<R: Read> Read for Keyed<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        while self.consumed == self.ready.len() {
+            if self.done {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let available = &self.ready[self.consumed..];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.consumed += len;
+        Ok(len)
+    }
+}
+
+/// Types `input` the way `encrypt_stream` would, rendering a trace of every key press instead
+/// of collecting the output. Characters that press no key are left out.
+fn trace_with_enigma(
+    input: &str,
+    enigma: &mut EnigmaMachine,
+    args: &Args,
+    format: TraceFormat,
+) -> Result<String, EnigmaError> {
+    let input = Preparation::new(args, enigma).apply(input)?;
+    let mut keys = String::new();
+    for ch in input.trim().chars() {
+        if ch.is_ascii_alphabetic() {
+            keys.push(ch.to_ascii_uppercase());
+            continue;
+        }
+        match enigma.non_alpha_policy() {
+            NonAlphaPolicy::Error => return Err(EnigmaError::InvalidCharacter(ch)),
+            NonAlphaPolicy::Skip | NonAlphaPolicy::PassThrough => {}
+            NonAlphaPolicy::Substitute => keys
+                .push_str(NonAlphaPolicy::substitute(ch).ok_or(EnigmaError::InvalidCharacter(ch))?),
+        }
+    }
+
+    let separator = match format {
+        TraceFormat::Table => "\n\n",
+        TraceFormat::Json => "\n",
+    };
+    let mut report = String::new();
+    for key in keys.chars() {
+        if let Some(trace) = enigma.encrypt_traced(key) {
+            report.push_str(&format.render(&trace));
+            report.push_str(separator);
+        }
+    }
+    Ok(report)
+}
+
 fn setup_enigma_from_config(machine_config: Option<String>) -> Result<EnigmaMachine, String> {
     if let Some(config) = machine_config {
-        let machine_settings: MachineConfig =
-            serde_json::from_str(&config).expect("Failed to parse machine configuration");
+        let machine_settings: MachineConfig = serde_json::from_str(&config)
+            .map_err(|err| format!("Failed to parse the machine configuration: {}", err))?;
 
         let mut rotor_list = Vec::new();
 
         for rotor_config in machine_settings.rotors {
-            match rotor_config.type_.as_str() {
-                "type_i" | "i" => {
-                    rotor_list.push(rotors::type_i(rotor_config.position, rotor_config.ring))
-                }
-                "type_ii" | "ii" => {
-                    rotor_list.push(rotors::type_ii(rotor_config.position, rotor_config.ring))
-                }
-                "type_iii" | "iii" => {
-                    rotor_list.push(rotors::type_iii(rotor_config.position, rotor_config.ring))
-                }
-                "type_iv" | "iv" => {
-                    rotor_list.push(rotors::type_iv(rotor_config.position, rotor_config.ring))
-                }
-                "type_v" | "v" => {
-                    rotor_list.push(rotors::type_v(rotor_config.position, rotor_config.ring))
-                }
-                _ => panic!("Unsupported rotor type!"),
+            match rotors::from_name(
+                &rotor_config.type_,
+                rotor_config.position,
+                rotor_config.ring,
+            ) {
+                Some(rotor) => rotor_list.push(rotor),
+                None => return Err(format!("Unsupported rotor type: {}", rotor_config.type_)),
             }
         }
 
@@ -117,7 +929,7 @@ fn setup_enigma_from_config(machine_config: Option<String>) -> Result<EnigmaMach
             .collect();
 
         let plugboard = Plugboard::new(plugboard_mappings)
-            .expect("Failed to initialize the plugboard with provided mappings");
+            .map_err(|err| format!("Invalid plugboard: {}", err))?;
 
         Ok(EnigmaMachine::new(rotor_list, reflector, plugboard))
     } else {
@@ -140,30 +952,511 @@ fn setup_enigma_from_config(machine_config: Option<String>) -> Result<EnigmaMach
     }
 }
 
-pub fn encrypt_with_enigma(input: String, enigma: &mut EnigmaMachine) -> String {
-    let processed_input = input.trim().to_uppercase();
-    match enigma.encrypt_message(&processed_input) {
-        Ok(encrypted_msg) => encrypted_msg,
-        Err(err) => {
-            eprintln!("Encryption failed with error: {}", err);
-            std::process::exit(1);
+fn setup_enigma_from_keysheet(
+    keysheet: &str,
+    day: u32,
+    model: Option<Model>,
+) -> Result<EnigmaMachine, String> {
+    let keysheet: KeySheet = serde_json::from_str(keysheet)
+        .map_err(|err| format!("Failed to parse the key sheet: {}", err))?;
+    if let Some(model) = model.filter(|&model| model != keysheet.model) {
+        return Err(format!(
+            "The key sheet is for the {}, not the {}",
+            keysheet.model, model
+        ));
+    }
+    let key = keysheet
+        .day(day)
+        .ok_or_else(|| format!("Day {} is not on the key sheet for {}", day, keysheet.month))?;
+    key.machine().map_err(|err| err.to_string())
+}
+
+fn send_message(
+    plaintext: &str,
+    enigma: &mut EnigmaMachine,
+    args: &Args,
+) -> Result<String, EnigmaError> {
+    let Some(Command::Send {
+        procedure,
+        message_key,
+        grundstellung,
+        naval_tables,
+        to,
+        from,
+        time,
+        part_length,
+    }) = &args.command
+    else {
+        unreachable!("send_message called without the send command");
+    };
+
+    let plaintext = match args.convention {
+        Some(convention) => convention.preprocess(plaintext)?,
+        None if enigma.preserves_case() => plaintext.trim().to_string(),
+        None => plaintext.trim().to_uppercase(),
+    };
+
+    let mut rng = key_rng(args.seed);
+    let daily_grundstellung = enigma.positions();
+    let format = GroupFormat::new(args.groups, args.groups_per_line);
+    if let Some(path) = naval_tables {
+        let tables = load_naval_tables(path);
+        let message = tables.encrypt(enigma, &daily_grundstellung, &mut rng, &plaintext)?;
+        return Ok(tables.format(&message, format));
+    }
+    if let (Some(to), Some(from)) = (to, from) {
+        let time = time.clone().unwrap_or_else(current_time);
+        let parts = message::encrypt_parts(
+            *procedure,
+            enigma,
+            &mut rng,
+            to,
+            from,
+            &time,
+            &plaintext,
+            *part_length,
+        )?;
+        let parts: Vec<String> = parts.iter().map(|part| part.format(format)).collect();
+        return Ok(parts.join("\n\n"));
+    }
+
+    let key_len = daily_grundstellung.len();
+    let grundstellung = match (procedure, grundstellung) {
+        (Procedure::DoubledIndicator, _) => daily_grundstellung,
+        (Procedure::SingleIndicator, Some(grundstellung)) => grundstellung.to_uppercase(),
+        (Procedure::SingleIndicator, None) => random_key(&mut rng, key_len),
+    };
+    let message_key = message_key
+        .as_deref()
+        .map(str::to_uppercase)
+        .unwrap_or_else(|| random_key(&mut rng, key_len));
+
+    let message = procedure.encrypt(enigma, &grundstellung, &message_key, &plaintext)?;
+    Ok(procedure.format(&message, format))
+}
+
+fn receive_message(
+    text: &str,
+    enigma: &mut EnigmaMachine,
+    args: &Args,
+) -> Result<String, EnigmaError> {
+    let Some(Command::Receive {
+        procedure,
+        naval_tables,
+        header,
+    }) = &args.command
+    else {
+        unreachable!("receive_message called without the receive command");
+    };
+
+    let plaintext = match naval_tables {
+        Some(path) => {
+            let tables = load_naval_tables(path);
+            let daily_grundstellung = enigma.positions();
+            let message = tables.parse(text)?;
+            tables.decrypt(enigma, &daily_grundstellung, &message)?
+        }
+        None if *header => message::reassemble(message::parse_all(text)?)?
+            .iter()
+            .map(|part| part.decrypt(enigma))
+            .collect::<Result<String, EnigmaError>>()?,
+        None => {
+            let message = procedure.parse(text, &enigma.positions())?;
+            procedure.decrypt(enigma, &message)?
+        }
+    };
+    Ok(match args.convention {
+        Some(convention) => convention.format(&plaintext),
+        None => plaintext,
+    })
+}
+
+/// The source of all generated key material: seeded when `--seed` is given, random otherwise.
+fn key_rng(seed: Option<u64>) -> Box<dyn RngCore> {
+    match seed {
+        Some(seed) => Box::new(StdRng::seed_from_u64(seed)),
+        None => Box::new(rand::thread_rng()),
+    }
+}
+
+/// The current UTC time as `HHMM`, for the time of origin in a radio header.
+fn current_time() -> String {
+    let seconds = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_secs());
+    format!("{:02}{:02}", seconds / 3600 % 24, seconds / 60 % 60)
+}
+
+fn run_bombe(
+    crib: &str,
+    ciphertext: &str,
+    offset: usize,
+    model: Model,
+    wheels: Option<&str>,
+    rings: Option<&str>,
+    reflector: &str,
+) -> Result<String, EnigmaError> {
+    let menu = Menu::new(&crib.to_uppercase(), &ungroup(ciphertext), offset)?;
+    let orders = match wheels {
+        Some(wheels) => vec![wheels.split(',').map(str::trim).collect()],
+        None => model.wheel_orders(),
+    };
+    let rings = rings
+        .map(str::to_string)
+        .unwrap_or_else(|| "A".repeat(orders[0].len()));
+    // Check the wheel names and rings up front rather than once per thread
+    bombe::wheel_stack(&orders[0], &rings)?;
+
+    let bombe = Bombe::new(menu);
+    let stops = bombe.search_orders(&orders, &rings, reflector)?;
+    let mut report = bombe.menu().to_string();
+    for stop in &stops {
+        report.push_str(&format!("{}\n", stop));
+    }
+    report.push_str(&format!(
+        "{} stops over {} wheel orders\n",
+        stops.len(),
+        orders.len()
+    ));
+    Ok(report)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn run_crack(
+    ciphertext: &str,
+    model: Model,
+    wheels: Option<&str>,
+    reflector: Option<&str>,
+    rings: Option<&str>,
+    candidates: usize,
+    ngrams: &NgramModel,
+) -> Result<String, EnigmaError> {
+    let mut cracker = Cracker::new(&ungroup(ciphertext))?;
+    cracker.set_candidates(candidates);
+    cracker.set_model(ngrams);
+    let orders = match wheels {
+        Some(wheels) => vec![wheels.split(',').map(str::trim).collect()],
+        None => model.wheel_orders(),
+    };
+    let reflectors = match reflector {
+        Some(reflector) => vec![reflector],
+        None => model.reflectors().to_vec(),
+    };
+    let solution = cracker.crack_orders(&orders, &reflectors, rings)?;
+    Ok(format!(
+        "{}\nScore {:.3} per letter\n{}\n",
+        solution.key, solution.score, solution.plaintext
+    ))
+}
+
+fn run_recover(
+    plaintext: &str,
+    ciphertext: &str,
+    model: Model,
+    wheels: Option<&str>,
+    reflector: Option<&str>,
+    rings: Option<&str>,
+    limit: usize,
+) -> Result<String, EnigmaError> {
+    let mut recoverer = Recoverer::new(plaintext, ciphertext)?;
+    recoverer.set_limit(limit);
+    let orders = match wheels {
+        Some(wheels) => vec![wheels.split(',').map(str::trim).collect()],
+        None => model.wheel_orders(),
+    };
+    let reflectors = match reflector {
+        Some(reflector) => vec![reflector],
+        None => model.reflectors().to_vec(),
+    };
+    let recoveries = recoverer.recover_orders(&orders, &reflectors, rings)?;
+
+    let mut report = match recoveries.len() {
+        1 => "1 key fits".to_string(),
+        keys => format!("{} keys fit", keys),
+    };
+    report.push_str(&format!(" over {} wheel orders\n", orders.len()));
+    for recovery in &recoveries {
+        report.push_str(&format!("{}\n", recovery));
+    }
+    Ok(report)
+}
+
+fn run_rejewski(
+    indicators: &str,
+    model: Model,
+    wheels: Option<&str>,
+    rings: Option<&str>,
+    reflector: &str,
+) -> Result<String, EnigmaError> {
+    let indicators: Vec<&str> = indicators.split_whitespace().collect();
+    let orders = match wheels {
+        Some(wheels) => vec![wheels.split(',').map(str::trim).collect()],
+        None => model.wheel_orders(),
+    };
+    let rings = rings
+        .map(str::to_string)
+        .unwrap_or_else(|| "A".repeat(orders[0].len()));
+    let characteristic = Characteristic::from_indicators(&indicators)?;
+    let catalogue = Catalogue::build(&orders, &rings, reflector)?;
+    let candidates = catalogue.lookup(&characteristic);
+
+    let mut report = format!("Characteristic {}\n", characteristic);
+    report.push_str(&format!(
+        "{} of {} catalogued settings share it ({} characteristics over {} wheel orders)\n",
+        candidates.len(),
+        catalogue.len(),
+        catalogue.characteristics(),
+        orders.len()
+    ));
+    for candidate in &candidates {
+        report.push_str(&format!("{}\n", candidate));
+    }
+    Ok(report)
+}
+
+fn run_banburismus(
+    messages: &str,
+    tables: &NavalTables,
+    model: Model,
+    threshold: f64,
+    ngrams: &NgramModel,
+) -> Result<String, EnigmaError> {
+    let mut blocks = vec![String::new()];
+    for line in messages.lines() {
+        match (
+            line.trim().is_empty(),
+            blocks.last().is_some_and(String::is_empty),
+        ) {
+            (true, true) => {}
+            (true, false) => blocks.push(String::new()),
+            (false, _) => {
+                let block = blocks.last_mut().unwrap();
+                block.push_str(line);
+                block.push('\n');
+            }
+        }
+    }
+    let intercepts = blocks
+        .iter()
+        .filter(|block| !block.is_empty())
+        .map(|block| Intercept::from_naval(tables, &tables.parse(block)?))
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut banburismus = Banburismus::new(intercepts, ngrams);
+    banburismus.set_threshold(threshold);
+    let depths = banburismus.depths();
+    let scritchmus = Scritchmus::new(&depths);
+
+    let mut report = format!(
+        "{} intercepts, {} pairs sharing all but the last letter of the Verfahrenkenngruppe\n",
+        banburismus.intercepts().len(),
+        banburismus.pairs().len()
+    );
+    report.push_str(&format!(
+        "{} pairs in depth at {:+.1} db or better\n",
+        depths.len(),
+        threshold
+    ));
+    for depth in &depths {
+        report.push_str(&format!("  {}\n", depth));
+    }
+    if !scritchmus.contradictions.is_empty() {
+        report.push_str(&format!(
+            "{} set aside for contradicting better depths\n",
+            scritchmus.contradictions.len()
+        ));
+        for depth in &scritchmus.contradictions {
+            report.push_str(&format!("  {}\n", depth));
+        }
+    }
+    report.push_str("Chains on the right wheel\n");
+    for chain in scritchmus.chains() {
+        report.push_str(&format!("  {}\n", chain));
+    }
+    report.push_str("Right wheel\n");
+    let right_wheels = scritchmus.right_wheels(model);
+    for (wheel, placements) in &right_wheels {
+        match placements {
+            0 => report.push_str(&format!("  {:<5} ruled out\n", wheel)),
+            1 => report.push_str(&format!("  {:<5} 1 placement\n", wheel)),
+            _ => report.push_str(&format!("  {:<5} {} placements\n", wheel, placements)),
+        }
+    }
+    let survivors: Vec<&str> = right_wheels
+        .iter()
+        .filter(|&&(_, placements)| placements > 0)
+        .map(|&(wheel, _)| wheel)
+        .collect();
+    report.push_str(&format!(
+        "{} of {} wheel orders remain\n",
+        banburismus::wheel_orders(model, &survivors).len(),
+        model.wheel_orders().len()
+    ));
+    Ok(report)
+}
+
+fn run_zygalski(
+    indicators: &str,
+    model: Model,
+    wheels: Option<&str>,
+    reflector: &str,
+) -> Result<String, EnigmaError> {
+    let indicators = indicators
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(
+            |line| match line.split_whitespace().collect::<Vec<_>>()[..] {
+                [grundstellung, encrypted_key] => Ok(Indicator {
+                    grundstellung: grundstellung.to_uppercase(),
+                    encrypted_key: encrypted_key.to_uppercase(),
+                }),
+                _ => Err(EnigmaError::InvalidIndicator(format!(
+                    "expected a Grundstellung and a doubled key, found {}",
+                    line.trim()
+                ))),
+            },
+        )
+        .collect::<Result<Vec<_>, _>>()?;
+    let females = zygalski::females(&indicators)?;
+    let orders = match wheels {
+        Some(wheels) => vec![wheels.split(',').map(str::trim).collect()],
+        None => model.wheel_orders(),
+    };
+    let apertures = zygalski::search_orders(&orders, reflector, &females)?;
+
+    let mut report = format!(
+        "{} females among {} indicators\n{} apertures over {} wheel orders\n",
+        females.len(),
+        indicators.len(),
+        apertures.len(),
+        orders.len()
+    );
+    for aperture in &apertures {
+        report.push_str(&format!("{}\n", aperture));
+    }
+    Ok(report)
+}
+
+fn write_zygalski_sheets(
+    wheels: &str,
+    rings: Option<&str>,
+    reflector: &str,
+    pair: usize,
+    out: &str,
+    pdf: bool,
+) -> Result<String, EnigmaError> {
+    let order: Vec<&str> = wheels.split(',').map(str::trim).collect();
+    let rings = rings
+        .map(str::to_uppercase)
+        .unwrap_or_else(|| "A".repeat(order.len()));
+    if pair == 0 {
+        return Err(EnigmaError::InvalidSearch(
+            "letters of the key count from 1".to_string(),
+        ));
+    }
+    let sheets = zygalski::sheets(&order, &rings, reflector, pair - 1)?;
+    if pdf {
+        if let Err(err) = fs::write(out, zygalski::sheets_pdf(&sheets)) {
+            fail(format!("Failed to write {}: {}", out, err));
+        }
+        return Ok(format!("Wrote {} sheets to {}\n", sheets.len(), out));
+    }
+    if let Err(err) = fs::create_dir_all(out) {
+        fail(format!(
+            "Failed to create the sheet directory {}: {}",
+            out, err
+        ));
+    }
+    for sheet in &sheets {
+        let name = if sheet.prefix.is_empty() {
+            "sheet".to_string()
+        } else {
+            format!("sheet-{}", sheet.prefix)
+        };
+        let path = std::path::Path::new(out).join(format!("{}.svg", name));
+        if let Err(err) = fs::write(&path, sheet.to_svg()) {
+            fail(format!("Failed to write {}: {}", path.display(), err));
         }
     }
+    Ok(format!("Wrote {} sheets to {}\n", sheets.len(), out))
+}
+
+fn run_crib_drag(
+    crib: &str,
+    ciphertext: &str,
+    ciphertext_file: &str,
+    model: Model,
+    reflector: &str,
+) -> Result<String, EnigmaError> {
+    let fixed_point_free = reflectors::by_name(reflector)
+        .ok_or_else(|| EnigmaError::InvalidCrib(format!("unknown reflector {}", reflector)))?
+        .is_fixed_point_free();
+    if !fixed_point_free {
+        return Err(EnigmaError::InvalidCrib(format!(
+            "{} has fixed points, so no placement can be ruled out",
+            reflector
+        )));
+    }
+
+    let crib = crib.to_uppercase();
+    let ciphertext = ungroup(ciphertext);
+    let menus = crib_drag(&crib, &ciphertext)?;
+    let placements = ciphertext.len() - crib.len() + 1;
+    let mut report = format!(
+        "{} of {} placements of {} fit\n",
+        menus.len(),
+        placements,
+        crib
+    );
+    for menu in &menus {
+        report.push_str(&format!(
+            "\n{}About {:.1} stops per wheel order\n",
+            menu,
+            menu.expected_stops(model.rotor_count())
+        ));
+        report.push_str(&format!(
+            "enigma bombe --model {} --reflector {} --crib {} --ciphertext {} --offset {}\n",
+            model, reflector, crib, ciphertext_file, menu.offset
+        ));
+    }
+    Ok(report)
+}
+
+fn load_ngrams(path: &str) -> NgramModel {
+    let model = read_file(path, "n-gram model");
+    serde_json::from_str(&model).unwrap_or_else(|err| {
+        fail(format!(
+            "Failed to parse the n-gram model {}: {}",
+            path, err
+        ))
+    })
+}
+
+fn load_naval_tables(path: &str) -> NavalTables {
+    let tables = read_file(path, "naval tables");
+    serde_json::from_str(&tables).unwrap_or_else(|err| {
+        fail(format!(
+            "Failed to parse the naval tables {}: {}",
+            path, err
+        ))
+    })
 }
 
 #[cfg(test)]
 mod main_tests {
     use super::*;
+    use enigma_shark::naval::Kenngruppen;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
     use std::time::Instant;
 
     #[test]
     fn test_encrypt_with_default_config() {
         let input = "BLETCHLEY".to_string();
-        let mut machine = match setup_enigma_from_config(None) {
+        let machine = match setup_enigma_from_config(None) {
             Ok(machine) => machine,
             Err(err) => panic!("Failed to set up the enigma machine for test: {}", err),
         };
-        let encrypted = encrypt_with_enigma(input.clone(), &mut machine);
+        let encrypted = encrypt(machine, &input);
         assert_ne!(encrypted, input);
     }
 
@@ -183,16 +1476,571 @@ mod main_tests {
         .to_string();
 
         let input = "BLETCHLEY".to_string();
-        let mut machine = match setup_enigma_from_config(Some(config)) {
+        let machine = match setup_enigma_from_config(Some(config.clone())) {
             Ok(machine) => machine,
             Err(err) => panic!("Failed to set up the enigma machine for test: {}", err),
         };
-        let encrypted = encrypt_with_enigma(input.clone(), &mut machine);
+        let encrypted = encrypt(machine, &input);
         assert_ne!(encrypted, input);
+
+        // Plugged on both sides, the same settings decrypt the message again
+        let machine = setup_enigma_from_config(Some(config)).unwrap();
+        assert_eq!(encrypt(machine, &encrypted), input);
+    }
+
+    #[test]
+    fn test_encrypt_with_preserve_case() {
+        let args = Args::parse_from(["enigma", "--preserve-case"]);
+        let encrypted = encrypt_text("bletchley", &args);
+        assert!(encrypted
+            .trim_end()
+            .chars()
+            .all(|ch| ch.is_ascii_lowercase()));
+    }
+
+    /// Encrypts `text` on `machine` the way the binary does without options, dropping the
+    /// final newline.
+    fn encrypt(machine: EnigmaMachine, text: &str) -> String {
+        let args = Args::parse_from(["enigma"]);
+        encrypt_on(machine, text, &args).trim_end().to_string()
+    }
+
+    fn encrypt_text(text: &str, args: &Args) -> String {
+        encrypt_on(setup_enigma_from_config(None).unwrap(), text, args)
+    }
+
+    fn encrypt_on(mut machine: EnigmaMachine, text: &str, args: &Args) -> String {
+        machine.set_non_alpha_policy(args.non_alpha);
+        machine.set_preserve_case(args.preserve_case);
+        let format = GroupFormat::new(args.groups, args.groups_per_line);
+        let output = GroupWriter::new(Vec::new(), format);
+        let output = encrypt_stream(text.as_bytes(), machine, args, output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_encrypt_stream_with_convention() {
+        let text = "Kommandant an {Prien}: Planquadrat 42.\n";
+        let mut args = Args::parse_from(["enigma", "--convention", "kriegsmarine"]);
+        let encrypted = encrypt_text(text, &args);
+
+        args.decrypt = true;
+        let decrypted = encrypt_text(&encrypted, &args);
+
+        assert_eq!(decrypted, "KDT AN PRIEN: PLANQUADRAT 42.\n");
+    }
+
+    fn prepare(text: &str, args: &Args) -> String {
+        let machine = setup_enigma_from_config(None).unwrap();
+        let mut keyed = Keyed::new(text.as_bytes(), Preparation::new(args, &machine));
+        let mut prepared = String::new();
+        keyed.read_to_string(&mut prepared).unwrap();
+        prepared
+    }
+
+    #[test]
+    fn test_keyed_convention_sees_names_across_chunks() {
+        // The name and its umlaut straddle the end of the first 4096-byte read
+        let text = format!(
+            "{}Kommandant {{Günther Prien}}: Planquadrat 42.",
+            "Wetter gut. ".repeat(340)
+        );
+        let args = Args::parse_from(["enigma", "--convention", "kriegsmarine"]);
+        let prepared = prepare(&text, &args);
+
+        assert_eq!(
+            prepared,
+            Convention::Kriegsmarine.preprocess(&text).unwrap()
+        );
+        assert!(prepared.ends_with("KDTXJGUENTHERPRIENJXXPLANQUADRATXQVIERZWOQX"));
+    }
+
+    #[test]
+    fn test_keyed_keeps_characters_split_across_chunks() {
+        // "ü" is two bytes, the first of them the last byte of the first read
+        let text = format!("{}ü {}", "A".repeat(4095), "B".repeat(10));
+        let args = Args::parse_from(["enigma", "--non-alpha", "pass-through"]);
+
+        assert_eq!(prepare(&text, &args), text.to_uppercase());
+    }
+
+    #[test]
+    fn test_encrypt_stream_ends_the_line_before_an_error() {
+        let args = Args::parse_from(["enigma"]);
+        let machine = setup_enigma_from_config(None).unwrap();
+        let mut written = Vec::new();
+        let output = GroupWriter::new(
+            &mut written,
+            GroupFormat::new(args.groups, args.groups_per_line),
+        );
+
+        assert!(encrypt_stream("HELLO WORLD".as_bytes(), machine, &args, output).is_err());
+        assert_eq!(written.len(), 6);
+        assert_eq!(written.last(), Some(&b'\n'));
+    }
+
+    #[test]
+    fn test_encrypt_stream_matches_machine_across_chunks() {
+        let text = "WETTERVORHERSAGE BISKAYA ".repeat(400);
+        let args = Args::parse_from(["enigma", "--non-alpha", "skip"]);
+        let mut machine = setup_enigma_from_config(None).unwrap();
+        machine.set_non_alpha_policy(NonAlphaPolicy::Skip);
+        let expected = machine.encrypt_message(&text).unwrap();
+
+        assert!(text.len() > 4096);
+        assert_eq!(
+            encrypt_text(&format!("  {}\n", text), &args),
+            expected + "\n"
+        );
+    }
+
+    #[test]
+    fn test_decrypt_ignores_grouping() {
+        let args = Args::parse_from(["enigma", "--decrypt"]);
+
+        let machine = setup_enigma_from_config(None).unwrap();
+        let encrypted = encrypt(machine, "WETTERVORHERSAGE");
+        let grouped = GroupFormat::new(5, 2).format(&encrypted);
+
+        assert_eq!(encrypt_text(&grouped, &args), "WETTERVORHERSAGE\n");
+    }
+
+    #[test]
+    fn test_trace_with_enigma() {
+        let args = Args::parse_from(["enigma", "--trace", "json"]);
+        let mut machine = setup_enigma_from_config(None).unwrap();
+        let report =
+            trace_with_enigma("BLETCHLEY", &mut machine, &args, TraceFormat::Json).unwrap();
+        let lamps: String = report
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .map(|trace| trace["lamp"].as_str().unwrap().to_string())
+            .collect();
+        let mut plain = setup_enigma_from_config(None).unwrap();
+        assert_eq!(lamps, plain.encrypt_message("BLETCHLEY").unwrap());
+        assert_eq!(machine.positions(), plain.positions());
+
+        let table = trace_with_enigma("b", &mut machine, &args, TraceFormat::Table).unwrap();
+        assert!(table.starts_with("key B  windows "));
+        assert_eq!(table.matches("forward").count(), 4);
+        assert!(trace_with_enigma("B B", &mut machine, &args, TraceFormat::Table).is_err());
+    }
+
+    #[test]
+    fn test_send_and_receive() {
+        for procedure in ["pre-1940", "post-1940"] {
+            let args =
+                Args::parse_from(["enigma", "send", "--procedure", procedure, "--groups", "5"]);
+            let mut machine = setup_enigma_from_config(None).unwrap();
+            machine.set_non_alpha_policy(NonAlphaPolicy::Skip);
+            let sent = send_message("Wetter vorhersage\n", &mut machine, &args).unwrap();
+
+            let args = Args::parse_from(["enigma", "receive", "--procedure", procedure]);
+            let mut machine = setup_enigma_from_config(None).unwrap();
+            let received = receive_message(&sent, &mut machine, &args).unwrap();
+            assert_eq!(received, "WETTERVORHERSAGE");
+        }
+    }
+
+    #[test]
+    fn test_send_uppercases_keys() {
+        let send = |message_key: &str, grundstellung: &str| {
+            let args = Args::parse_from([
+                "enigma",
+                "send",
+                "--message-key",
+                message_key,
+                "--grundstellung",
+                grundstellung,
+            ]);
+            let mut machine = setup_enigma_from_config(None).unwrap();
+            send_message("WETTERVORHERSAGE", &mut machine, &args).unwrap()
+        };
+        assert_eq!(send("wxcq", "jezb"), send("WXCQ", "JEZB"));
+    }
+
+    #[test]
+    fn test_send_and_receive_with_header() {
+        for procedure in ["pre-1940", "post-1940"] {
+            let args = Args::parse_from([
+                "enigma",
+                "send",
+                "--procedure",
+                procedure,
+                "--to",
+                "U6Z",
+                "--from",
+                "C",
+                "--time",
+                "1510",
+                "--groups",
+                "5",
+            ]);
+            let mut machine = setup_enigma_from_config(None).unwrap();
+            let sent = send_message("FEINDLICHEKRAEFTE", &mut machine, &args).unwrap();
+            assert!(sent.starts_with("U6Z DE C 1510 = 1TL = 17 = "));
+            assert_eq!(sent.matches("TL =").count(), 1);
+
+            let args = Args::parse_from(["enigma", "receive", "--header"]);
+            let mut machine = setup_enigma_from_config(None).unwrap();
+            let received = receive_message(&sent, &mut machine, &args).unwrap();
+            assert_eq!(received, "FEINDLICHEKRAEFTE");
+        }
+    }
+
+    #[test]
+    fn test_send_with_seed_is_reproducible() {
+        let args = Args::parse_from(["enigma", "send", "--seed", "1940"]);
+        let send = || {
+            let mut machine = setup_enigma_from_config(None).unwrap();
+            send_message("WETTERVORHERSAGE", &mut machine, &args).unwrap()
+        };
+        assert_eq!(send(), send());
+    }
+
+    #[test]
+    fn test_send_and_receive_in_parts() {
+        let plaintext = "KEINEBESONDERENEREIGNISSE".repeat(12);
+        let args = Args::parse_from(["enigma", "send", "--to", "U6Z", "--from", "C"]);
+        let mut machine = setup_enigma_from_config(None).unwrap();
+        let sent = send_message(&plaintext, &mut machine, &args).unwrap();
+        assert!(sent.contains("2TLE 1TL"));
+        assert!(sent.contains("2TLE 2TL"));
+
+        let args = Args::parse_from(["enigma", "receive", "--header"]);
+        let mut machine = setup_enigma_from_config(None).unwrap();
+        assert_eq!(
+            receive_message(&sent, &mut machine, &args).unwrap(),
+            plaintext
+        );
+
+        let first_part = sent.split("\n\n").next().unwrap();
+        let mut machine = setup_enigma_from_config(None).unwrap();
+        assert_eq!(
+            receive_message(first_part, &mut machine, &args),
+            Err(EnigmaError::MissingPart { part: 2, parts: 2 })
+        );
+    }
+
+    #[test]
+    fn test_send_and_receive_naval() {
+        let tables = NavalTables::generate(&mut StdRng::seed_from_u64(1), 10);
+        let path = std::env::temp_dir().join(format!("naval-tables-{}.json", std::process::id()));
+        fs::write(&path, serde_json::to_string(&tables).unwrap()).unwrap();
+        let path = path.to_str().unwrap();
+
+        let args = Args::parse_from(["enigma", "send", "--naval-tables", path, "--groups", "4"]);
+        let mut machine = setup_enigma_from_config(None).unwrap();
+        machine.set_non_alpha_policy(NonAlphaPolicy::Skip);
+        let sent = send_message("U-Boot aufgetaucht", &mut machine, &args).unwrap();
+
+        let args = Args::parse_from(["enigma", "receive", "--naval-tables", path]);
+        let mut machine = setup_enigma_from_config(None).unwrap();
+        let received = receive_message(&sent, &mut machine, &args);
+        fs::remove_file(path).unwrap();
+        assert_eq!(received.unwrap(), "UBOOTAUFGETAUCHT");
+    }
+
+    #[test]
+    fn test_setup_from_keysheet() {
+        let month = "2026-02".parse().unwrap();
+        let sheet = KeySheet::generate(&mut StdRng::seed_from_u64(2), Model::M4, month, false);
+        let json = serde_json::to_string(&sheet).unwrap();
+
+        let machine = setup_enigma_from_keysheet(&json, 17, Some(Model::M4)).unwrap();
+        assert_eq!(machine.positions(), "AAAA");
+        let encrypted = encrypt(machine, "U");
+        assert_ne!(encrypted, "U");
+
+        assert_eq!(
+            setup_enigma_from_keysheet(&json, 30, None).err().unwrap(),
+            "Day 30 is not on the key sheet for 2026-02"
+        );
+        assert_eq!(
+            setup_enigma_from_keysheet(&json, 17, Some(Model::M3))
+                .err()
+                .unwrap(),
+            "The key sheet is for the m4, not the m3"
+        );
+    }
+
+    #[test]
+    fn test_run_bombe() {
+        let config = r#"
+        {
+            "rotors": [
+                {"type_": "iv", "position": "B", "ring": "A"},
+                {"type_": "i", "position": "Q", "ring": "A"},
+                {"type_": "ii", "position": "X", "ring": "A"}
+            ],
+            "reflector": "ukw_b",
+            "plugboard": {"A": "R", "G": "K", "O": "X"}
+        }
+        "#
+        .to_string();
+        let machine = setup_enigma_from_config(Some(config)).unwrap();
+        let ciphertext = encrypt(machine, "WETTERVORHERSAGEBISKAYA");
+
+        let report = run_bombe(
+            "wettervorhersage",
+            &GroupFormat::FIVE.format(&ciphertext),
+            0,
+            Model::M3,
+            Some("iv,i,ii"),
+            None,
+            "ukw_b",
+        )
+        .unwrap();
+        assert!(report.starts_with("Menu for WETTERVORHERSAGE at offset 0"));
+        assert!(report.contains("type IV type I type II UKW-B rings AAA positions BQX"));
+
+        assert!(run_bombe(
+            "WETTER",
+            &ciphertext,
+            0,
+            Model::M3,
+            Some("iv,x,ii"),
+            None,
+            "ukw_b"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_run_crack() {
+        let config = r#"
+        {
+            "rotors": [
+                {"type_": "v", "position": "M", "ring": "C"},
+                {"type_": "iii", "position": "D", "ring": "Q"},
+                {"type_": "i", "position": "K", "ring": "H"}
+            ],
+            "reflector": "ukw_b",
+            "plugboard": {"E": "N", "S": "T"}
+        }
+        "#
+        .to_string();
+        let machine = setup_enigma_from_config(Some(config)).unwrap();
+        let plaintext = "MELDUNGXVONXUXSIEBENXVIERXKONVOIXGESICHTETXQUADRATXBEXZWEIXNEUNXZEHNXDAMPFERXDREIXBEWACHERXKURSXOSTXFAHRTXNEUNXSEEMEILENXHALTEXVERBINDUNGXERBITTEXANGRIFFSBEFEHL";
+        let ciphertext = encrypt(machine, plaintext);
+
+        let report = run_crack(
+            &GroupFormat::FIVE.format(&ciphertext),
+            Model::M3,
+            Some("v,iii,i"),
+            Some("ukw_b"),
+            Some("CQH"),
+            1,
+            Language::German.model(),
+        )
+        .unwrap();
+        assert!(report.starts_with("type V type III type I UKW-B rings CQH positions MDK"));
+        assert!(report.ends_with(&format!("{}\n", plaintext)));
+
+        assert!(run_crack(
+            "ABC",
+            Model::M3,
+            Some("v,ix,i"),
+            None,
+            None,
+            1,
+            Language::German.model()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_run_rejewski() {
+        let config = r#"
+        {
+            "rotors": [
+                {"type_": "ii", "position": "A", "ring": "A"},
+                {"type_": "iii", "position": "A", "ring": "A"},
+                {"type_": "i", "position": "A", "ring": "A"}
+            ],
+            "reflector": "ukw_b",
+            "plugboard": {"Q": "W", "E": "R"}
+        }
+        "#
+        .to_string();
+        let mut machine = setup_enigma_from_config(Some(config)).unwrap();
+        let mut rng = StdRng::seed_from_u64(1938);
+        let indicators: Vec<String> = (0..200)
+            .map(|_| {
+                let key = random_key(&mut rng, 3);
+                Procedure::DoubledIndicator
+                    .encrypt_indicator(&mut machine, "GLN", &key)
+                    .unwrap()
+                    .encrypted_key
+            })
+            .collect();
+
+        let report = run_rejewski(
+            &indicators.join(" "),
+            Model::EnigmaI,
+            Some("ii,iii,i"),
+            None,
+            "ukw_b",
+        )
+        .unwrap();
+        assert!(report.starts_with("Characteristic ("));
+        assert!(report.contains("type II type III type I Grundstellung GLN\n"));
+
+        assert!(run_rejewski("ABCDEF", Model::EnigmaI, Some("ii,iii,i"), None, "ukw_b").is_err());
+    }
+
+    #[test]
+    fn test_run_recover() {
+        let config = r#"
+        {
+            "rotors": [
+                {"type_": "v", "position": "M", "ring": "C"},
+                {"type_": "iii", "position": "D", "ring": "Q"},
+                {"type_": "i", "position": "K", "ring": "H"}
+            ],
+            "reflector": "ukw_b",
+            "plugboard": {"E": "N", "S": "T", "A": "Z"}
+        }
+        "#
+        .to_string();
+        let machine = setup_enigma_from_config(Some(config)).unwrap();
+        let plaintext = "WETTERVORHERSAGEXFUERXDIEXDEUTSCHEXBUCHT";
+        let ciphertext = encrypt(machine, plaintext);
+
+        let report = run_recover(
+            plaintext,
+            &GroupFormat::FIVE.format(&ciphertext),
+            Model::M3,
+            Some("v,iii,i"),
+            Some("ukw_b"),
+            Some("CQH"),
+            LIMIT,
+        )
+        .unwrap();
+        assert!(report.starts_with("1 key fits over 1 wheel orders\n"));
+        assert!(report
+            .contains("type V type III type I UKW-B rings CQH positions MDK steckers AZ EN ST"));
+
+        assert!(run_recover(
+            plaintext,
+            "ABC",
+            Model::M3,
+            Some("v,iii,i"),
+            None,
+            None,
+            LIMIT
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_run_banburismus() {
+        let tables = NavalTables::generate(&mut StdRng::seed_from_u64(1941), 10);
+        let mut machine = setup_enigma_from_config(None).unwrap();
+        let text = enigma_shark::ngram::normalize(include_str!("../data/german.txt"), Some('X'));
+        let messages: Vec<String> = "ABCDEFGH"
+            .chars()
+            .enumerate()
+            .map(|(i, last)| {
+                let kenngruppen = Kenngruppen {
+                    schluesselkenngruppe: "AAA".to_string(),
+                    verfahrenkenngruppe: format!("VF{}", last),
+                };
+                let plaintext: String = text.chars().cycle().skip(i * 650).take(600).collect();
+                let message = tables
+                    .encrypt_with(&mut machine, "QQDV", &kenngruppen, ('X', 'Y'), &plaintext)
+                    .unwrap();
+                tables.format(&message, GroupFormat::new(4, 10))
+            })
+            .collect();
+
+        let report = run_banburismus(
+            &messages.join("\n\n"),
+            &tables,
+            Model::M4,
+            THRESHOLD,
+            Language::German.model(),
+        )
+        .unwrap();
+        assert!(report.starts_with("8 intercepts, 28 pairs"));
+        assert!(report.contains(" pairs in depth at +17.0 db or better\n"));
+        assert!(report.contains("Right wheel\n  i     "));
+        assert!(report.contains(" of 672 wheel orders remain\n"));
+
+        assert!(run_banburismus(
+            "ABCD EFGH IJKL",
+            &tables,
+            Model::M4,
+            THRESHOLD,
+            Language::German.model()
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_run_zygalski() {
+        let config = r#"
+        {
+            "rotors": [
+                {"type_": "ii", "position": "A", "ring": "A"},
+                {"type_": "iii", "position": "A", "ring": "K"},
+                {"type_": "i", "position": "A", "ring": "D"}
+            ],
+            "reflector": "ukw_b",
+            "plugboard": {"Q": "W", "E": "R"}
+        }
+        "#
+        .to_string();
+        let mut machine = setup_enigma_from_config(Some(config)).unwrap();
+        let mut rng = StdRng::seed_from_u64(1939);
+        let indicators: Vec<String> = (0..300)
+            .map(|_| {
+                let grundstellung = random_key(&mut rng, 3);
+                let key = random_key(&mut rng, 3);
+                let indicator = Procedure::DoubledIndicator
+                    .encrypt_indicator(&mut machine, &grundstellung, &key)
+                    .unwrap();
+                format!("{} {}", indicator.grundstellung, indicator.encrypted_key)
+            })
+            .collect();
+
+        let report = run_zygalski(
+            &indicators.join("\n"),
+            Model::EnigmaI,
+            Some("ii,iii,i"),
+            "ukw_b",
+        )
+        .unwrap();
+        assert!(report.contains(" females among 300 indicators\n"));
+        assert!(report.contains("type II type III type I UKW-B rings AKD\n"));
+
+        assert!(run_zygalski("KWT PQRPSX PQ", Model::EnigmaI, None, "ukw_b").is_err());
+    }
+
+    #[test]
+    fn test_run_crib_drag() {
+        let machine = setup_enigma_from_config(None).unwrap();
+        let ciphertext = encrypt(machine, "XXXKEINEBESONDERENEREIGNISSEXXXXXXX");
+
+        let report = run_crib_drag(
+            "keinebesondereneReignisse",
+            &ciphertext,
+            "intercept.txt",
+            Model::M3,
+            "ukw_b",
+        )
+        .unwrap();
+        assert!(report.contains("placements of KEINEBESONDERENEREIGNISSE fit"));
+        assert!(report.contains("Menu for KEINEBESONDERENEREIGNISSE at offset 3"));
+        assert!(report.contains(
+            "enigma bombe --model m3 --reflector ukw_b --crib KEINEBESONDERENEREIGNISSE \
+             --ciphertext intercept.txt --offset 3"
+        ));
+
+        assert!(run_crib_drag("WETTER", &ciphertext, "intercept.txt", Model::M3, "ukw_x").is_err());
     }
 
     #[test]
-    #[should_panic(expected = "Unsupported rotor type!")]
     fn test_invalid_rotor_type() {
         let config = r#"
         {
@@ -202,31 +2050,34 @@ mod main_tests {
         }
         "#
         .to_string();
-        setup_enigma_from_config(Some(config)).unwrap();
+        assert_eq!(
+            setup_enigma_from_config(Some(config)).err().unwrap(),
+            "Unsupported rotor type: invalid_type"
+        );
+        assert!(setup_enigma_from_config(Some("{".to_string()))
+            .err()
+            .unwrap()
+            .starts_with("Failed to parse the machine configuration"));
     }
 
     #[test]
     fn benchmark_encrypt_5mb() {
         // Generate a 5MB string input
-        let input: String = std::iter::repeat("A").take(5 * 1024).collect();
+        let input: String = "A".repeat(5 * 1024);
 
-        let mut machine = match setup_enigma_from_config(None) {
+        let machine = match setup_enigma_from_config(None) {
             Ok(machine) => machine,
             Err(err) => panic!("Failed to set up the enigma machine for test: {}", err),
         };
-
-        let mut cursor = Cursor::new(input.into_bytes());
-        let mut buffer = [0; 4096];
+        let args = Args::parse_from(["enigma"]);
+        let output = GroupWriter::new(
+            io::sink(),
+            GroupFormat::new(args.groups, args.groups_per_line),
+        );
 
         let start_time = Instant::now();
 
-        while let Ok(len) = cursor.read(&mut buffer) {
-            if len == 0 {
-                break;
-            }
-            let input_chunk = String::from_utf8_lossy(&buffer[..len]);
-            let _ = encrypt_with_enigma(input_chunk.to_string(), &mut machine);
-        }
+        encrypt_stream(Cursor::new(input.into_bytes()), machine, &args, output).unwrap();
 
         let elapsed_time = start_time.elapsed();
 
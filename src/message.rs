@@ -0,0 +1,531 @@
+//! Radio messages with their historical preamble.
+//!
+//! A transmitted message opens with a header line naming the receiving and sending stations,
+//! the time of origin, the part number, the letter count and the indicator, for example:
+//!
+//! ```text
+//! U6Z DE C 1510 = 1TL = 45 = EHZ TBS =
+//! TVEXS QBLTW LDAHH YEOEF PTWYB LENDP MKOXL DFAMU DWIHZ
+//! ```
+//!
+//! The indicator is the Grundstellung in the clear followed by the encrypted message key, once
+//! or doubled depending on the procedure. The Grundstellung is always the operator's own choice
+//! for the message: a header in the clear must never give away the daily key.
+//!
+//! Messages longer than about 250 letters were split into parts (Teile), each sent under its
+//! own message key. A part's header then also gives the number of parts, as in `3TLE 2TL` for
+//! the second of three.
+
+use crate::error::EnigmaError;
+use crate::format::{ungroup, GroupFormat};
+use crate::machine::EnigmaMachine;
+use crate::procedure::{random_key, Indicator, KeyedMessage, Procedure};
+use rand::RngCore;
+
+/// The longest part an operator would send before starting a new one.
+pub const PART_LENGTH: usize = 250;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Message {
+    /// Callsign of the receiving station.
+    pub to: String,
+    /// Callsign of the sending station.
+    pub from: String,
+    /// Time of origin as `HHMM`.
+    pub time: String,
+    /// Part number, starting at 1.
+    pub part: u32,
+    /// Number of parts the whole message was split into.
+    pub parts: u32,
+    pub indicator: Indicator,
+    pub ciphertext: String,
+}
+
+impl Message {
+    pub fn new(to: &str, from: &str, time: &str, message: KeyedMessage) -> Self {
+        Message {
+            to: to.to_string(),
+            from: from.to_string(),
+            time: time.to_string(),
+            part: 1,
+            parts: 1,
+            indicator: message.indicator,
+            ciphertext: message.ciphertext,
+        }
+    }
+
+    /// The letters A-Z in the body; anything passed through unkeyed does not count.
+    pub fn letter_count(&self) -> usize {
+        count_letters(&self.ciphertext)
+    }
+
+    /// The procedure the indicator was keyed under: a doubled message key is twice as long as
+    /// its Grundstellung.
+    pub fn procedure(&self) -> Procedure {
+        if self.indicator.encrypted_key.len() == 2 * self.indicator.grundstellung.len() {
+            Procedure::DoubledIndicator
+        } else {
+            Procedure::SingleIndicator
+        }
+    }
+
+    pub fn decrypt(&self, machine: &mut EnigmaMachine) -> Result<String, EnigmaError> {
+        self.procedure().decrypt(
+            machine,
+            &KeyedMessage {
+                indicator: self.indicator.clone(),
+                ciphertext: self.ciphertext.clone(),
+            },
+        )
+    }
+
+    /// Lays out the header line followed by the body in `groups`.
+    pub fn format(&self, groups: GroupFormat) -> String {
+        let part = match self.parts {
+            1 => format!("{}TL", self.part),
+            parts => format!("{}TLE {}TL", parts, self.part),
+        };
+        format!(
+            "{} DE {} {} = {} = {} = {} {} =\n{}",
+            self.to,
+            self.from,
+            self.time,
+            part,
+            self.letter_count(),
+            self.indicator.grundstellung,
+            self.indicator.encrypted_key,
+            groups.format(&self.ciphertext)
+        )
+    }
+
+    /// Reads a message from intercept text, checking the letter count against the body.
+    ///
+    /// Blank lines before the header are skipped and the part number may be left out.
+    pub fn parse(text: &str) -> Result<Self, EnigmaError> {
+        let mut lines = text.lines().skip_while(|line| line.trim().is_empty());
+        let header = lines
+            .next()
+            .ok_or_else(|| EnigmaError::InvalidHeader("no header line".to_string()))?;
+        let mut fields = header
+            .split('=')
+            .map(str::trim)
+            .filter(|field| !field.is_empty());
+
+        let address: Vec<&str> = fields
+            .next()
+            .map(|field| field.split_whitespace().collect())
+            .unwrap_or_default();
+        let (to, from, time) = match address.as_slice() {
+            [to, "DE", from, time] if time.len() == 4 && is_digits(time) => (*to, *from, *time),
+            _ => {
+                return Err(EnigmaError::InvalidHeader(format!(
+                    "expected 'TO DE FROM HHMM', found '{}'",
+                    header
+                )))
+            }
+        };
+
+        let mut part = 1;
+        let mut parts = 1;
+        let mut letter_count = None;
+        let mut indicator = None;
+        for field in fields {
+            let words: Vec<&str> = field.split_whitespace().collect();
+            match words.as_slice() {
+                [total, number] if total.ends_with("TLE") && number.ends_with("TL") => {
+                    parts = parse_part(total, "TLE")?;
+                    part = parse_part(number, "TL")?;
+                }
+                [number] if number.ends_with("TL") => {
+                    part = parse_part(number, "TL")?;
+                }
+                [count] if is_digits(count) => {
+                    letter_count = count.parse().ok();
+                }
+                [grundstellung, encrypted_key]
+                    if is_letters(grundstellung) && is_letters(encrypted_key) =>
+                {
+                    indicator = Some(Indicator {
+                        grundstellung: grundstellung.to_string(),
+                        encrypted_key: encrypted_key.to_string(),
+                    });
+                }
+                _ => {
+                    return Err(EnigmaError::InvalidHeader(format!(
+                        "unexpected field '{}'",
+                        field
+                    )))
+                }
+            }
+        }
+
+        let letter_count = letter_count
+            .ok_or_else(|| EnigmaError::InvalidHeader("missing letter count".to_string()))?;
+        let indicator =
+            indicator.ok_or_else(|| EnigmaError::InvalidHeader("missing indicator".to_string()))?;
+        let ciphertext = ungroup(&lines.collect::<Vec<_>>().join("\n"));
+        let found = count_letters(&ciphertext);
+        if found != letter_count {
+            return Err(EnigmaError::LetterCount {
+                expected: letter_count,
+                found,
+            });
+        }
+
+        Ok(Message {
+            to: to.to_string(),
+            from: from.to_string(),
+            time: time.to_string(),
+            part,
+            parts,
+            indicator,
+            ciphertext,
+        })
+    }
+}
+
+/// Reads every message in `text`, each starting at its header line.
+pub fn parse_all(text: &str) -> Result<Vec<Message>, EnigmaError> {
+    let mut blocks: Vec<String> = Vec::new();
+    for line in text.lines() {
+        match blocks.last_mut() {
+            Some(block) if !line.contains('=') => {
+                block.push('\n');
+                block.push_str(line);
+            }
+            _ if line.trim().is_empty() => {}
+            _ => blocks.push(line.to_string()),
+        }
+    }
+    if blocks.is_empty() {
+        return Err(EnigmaError::InvalidHeader("no header line".to_string()));
+    }
+    blocks.iter().map(|block| Message::parse(block)).collect()
+}
+
+/// Puts the parts of a split message back in order, checking they share one header and none is
+/// missing or doubled.
+pub fn reassemble(mut parts: Vec<Message>) -> Result<Vec<Message>, EnigmaError> {
+    if let Some((first, rest)) = parts.split_first() {
+        if let Some(other) = rest.iter().find(|message| {
+            (&message.to, &message.from, &message.time) != (&first.to, &first.from, &first.time)
+        }) {
+            return Err(EnigmaError::InvalidHeader(format!(
+                "part {} is from {} to {} at {}, part {} from {} to {} at {}",
+                first.part,
+                first.from,
+                first.to,
+                first.time,
+                other.part,
+                other.from,
+                other.to,
+                other.time
+            )));
+        }
+    }
+    parts.sort_by_key(|message| message.part);
+    let total = parts.iter().map(|message| message.parts).max().unwrap_or(1);
+    if parts.iter().any(|message| message.parts != total) {
+        return Err(EnigmaError::InvalidHeader(
+            "parts disagree on how many parts the message has".to_string(),
+        ));
+    }
+    for (expected, message) in (1..=total).zip(parts.iter()) {
+        if message.part != expected {
+            return Err(if message.part < expected {
+                EnigmaError::InvalidHeader(format!("part {} received twice", message.part))
+            } else {
+                EnigmaError::MissingPart {
+                    part: expected,
+                    parts: total,
+                }
+            });
+        }
+    }
+    match parts.len() as u32 {
+        found if found < total => Err(EnigmaError::MissingPart {
+            part: found + 1,
+            parts: total,
+        }),
+        found if found > total => Err(EnigmaError::InvalidHeader(format!(
+            "{} parts received for a message of {}",
+            found, total
+        ))),
+        _ => Ok(parts),
+    }
+}
+
+/// Sends `plaintext` in parts of at most `part_length` letters, each under a fresh random
+/// message key and Grundstellung.
+///
+/// Under the doubled procedure this is the form used from late 1938: the doubled message key is
+/// keyed at a Grundstellung the operator picks for the message, not the daily one, since the
+/// Grundstellung goes out in the clear.
+#[allow(clippy::too_many_arguments)]
+pub fn encrypt_parts<R: RngCore + ?Sized>(
+    procedure: Procedure,
+    machine: &mut EnigmaMachine,
+    rng: &mut R,
+    to: &str,
+    from: &str,
+    time: &str,
+    plaintext: &str,
+    part_length: usize,
+) -> Result<Vec<Message>, EnigmaError> {
+    let key_len = machine.rotors().len();
+    let letters: Vec<char> = plaintext.chars().collect();
+    let chunks: Vec<String> = letters
+        .chunks(part_length.max(1))
+        .map(|chunk| chunk.iter().collect())
+        .collect();
+    let parts = chunks.len().max(1) as u32;
+
+    let mut messages = Vec::new();
+    for (index, chunk) in chunks.iter().enumerate() {
+        let grundstellung = random_key(rng, key_len);
+        let message_key = random_key(rng, key_len);
+        let keyed = procedure.encrypt(machine, &grundstellung, &message_key, chunk)?;
+        let mut message = Message::new(to, from, time, keyed);
+        message.part = index as u32 + 1;
+        message.parts = parts;
+        messages.push(message);
+    }
+    Ok(messages)
+}
+
+fn parse_part(field: &str, suffix: &str) -> Result<u32, EnigmaError> {
+    field
+        .strip_suffix(suffix)
+        .filter(|number| is_digits(number))
+        .and_then(|number| number.parse().ok())
+        .filter(|&number| number > 0)
+        .ok_or_else(|| EnigmaError::InvalidHeader(format!("invalid part number {}", field)))
+}
+
+fn is_digits(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|ch| ch.is_ascii_digit())
+}
+
+fn is_letters(text: &str) -> bool {
+    !text.is_empty() && text.chars().all(|ch| ch.is_ascii_uppercase())
+}
+
+fn count_letters(text: &str) -> usize {
+    text.chars().filter(char::is_ascii_uppercase).count()
+}
+
+#[cfg(test)]
+mod message_tests {
+    use super::*;
+    use crate::plugboard::Plugboard;
+    use crate::reflector::reflectors;
+    use crate::rotor::rotors;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn daily_key() -> EnigmaMachine {
+        EnigmaMachine::new(
+            vec![
+                rotors::type_ii('A', 'B'),
+                rotors::type_iv('A', 'U'),
+                rotors::type_v('A', 'L'),
+            ],
+            reflectors::ukw_b(),
+            Plugboard::new(vec![('A', 'V'), ('B', 'S'), ('C', 'G'), ('D', 'L')]).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_parse_intercept() {
+        let text = "\nU6Z DE C 1510 = 45 = EHZ TBS =\n\
+                    TVEXS QBLTW LDAHH YEOEF PTWYB LENDP MKOXL DFAMU DWIHZ\n";
+        let message = Message::parse(text).unwrap();
+        assert_eq!(message.to, "U6Z");
+        assert_eq!(message.from, "C");
+        assert_eq!(message.time, "1510");
+        assert_eq!(message.part, 1);
+        assert_eq!(message.indicator.grundstellung, "EHZ");
+        assert_eq!(message.indicator.encrypted_key, "TBS");
+        assert_eq!(message.letter_count(), 45);
+    }
+
+    #[test]
+    fn test_format_parse_round_trip() {
+        for (procedure, grundstellung) in [
+            (Procedure::SingleIndicator, "QWE"),
+            (Procedure::DoubledIndicator, "JEZ"),
+        ] {
+            let keyed = procedure
+                .encrypt(&mut daily_key(), grundstellung, "BLA", "FEINDLICHEKRAEFTE")
+                .unwrap();
+            let mut message = Message::new("U6Z", "C", "1510", keyed);
+            message.part = 2;
+            message.parts = 3;
+
+            let text = message.format(GroupFormat::FIVE);
+            assert!(text.starts_with("U6Z DE C 1510 = 3TLE 2TL = 17 = "));
+            let parsed = Message::parse(&text).unwrap();
+            assert_eq!(parsed, message);
+            assert_eq!(parsed.procedure(), procedure);
+            assert_eq!(
+                parsed.decrypt(&mut daily_key()).unwrap(),
+                "FEINDLICHEKRAEFTE"
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_rejects_wrong_letter_count() {
+        assert_eq!(
+            Message::parse("U6Z DE C 1510 = 12 = EHZ TBS =\nTVEXS QBLTW"),
+            Err(EnigmaError::LetterCount {
+                expected: 12,
+                found: 10
+            })
+        );
+    }
+
+    #[test]
+    fn test_letter_count_skips_passed_through_characters() {
+        let message = Message::new(
+            "U6Z",
+            "C",
+            "1510",
+            KeyedMessage {
+                indicator: Indicator {
+                    grundstellung: "EHZ".to_string(),
+                    encrypted_key: "TBS".to_string(),
+                },
+                ciphertext: "TVEXS-QBL.TW".to_string(),
+            },
+        );
+        assert_eq!(message.letter_count(), 10);
+
+        let text = message.format(GroupFormat::FIVE);
+        assert!(text.starts_with("U6Z DE C 1510 = 1TL = 10 = "));
+        assert_eq!(Message::parse(&text).unwrap(), message);
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed_header() {
+        assert!(Message::parse("TVEXS QBLTW").is_err());
+        assert!(Message::parse("U6Z DE C 1510 = 10 =\nTVEXS QBLTW").is_err());
+        assert!(Message::parse("U6Z DE C 15H0 = 10 = EHZ TBS =\nTVEXS QBLTW").is_err());
+    }
+
+    #[test]
+    fn test_split_parts_round_trip() {
+        let plaintext = "KEINEBESONDERENEREIGNISSE".repeat(4);
+        let mut rng = StdRng::seed_from_u64(1);
+        for procedure in [Procedure::SingleIndicator, Procedure::DoubledIndicator] {
+            let parts = encrypt_parts(
+                procedure,
+                &mut daily_key(),
+                &mut rng,
+                "U6Z",
+                "C",
+                "1510",
+                &plaintext,
+                40,
+            )
+            .unwrap();
+            assert_eq!(parts.len(), 3);
+            assert_ne!(parts[0].indicator, parts[1].indicator);
+
+            let text: Vec<String> = parts
+                .iter()
+                .rev()
+                .map(|part| part.format(GroupFormat::FIVE))
+                .collect();
+            let received = reassemble(parse_all(&text.join("\n\n")).unwrap()).unwrap();
+            let decrypted: String = received
+                .iter()
+                .map(|part| part.decrypt(&mut daily_key()).unwrap())
+                .collect();
+            assert_eq!(decrypted, plaintext);
+        }
+    }
+
+    #[test]
+    fn test_header_never_gives_away_daily_grundstellung() {
+        let mut rng = StdRng::seed_from_u64(36);
+        for procedure in [Procedure::SingleIndicator, Procedure::DoubledIndicator] {
+            let mut machine = daily_key();
+            machine.set_positions("XKD").unwrap();
+            let parts = encrypt_parts(
+                procedure,
+                &mut machine,
+                &mut rng,
+                "U6Z",
+                "C",
+                "1510",
+                &"KEINEBESONDERENEREIGNISSE".repeat(20),
+                25,
+            )
+            .unwrap();
+            assert_eq!(parts.len(), 20);
+            for part in &parts {
+                let text = part.format(GroupFormat::FIVE);
+                let header = text.lines().next().unwrap();
+                assert_ne!(part.indicator.grundstellung, "XKD");
+                assert!(!header.contains("XKD"), "{}", header);
+            }
+        }
+    }
+
+    #[test]
+    fn test_reassemble_reports_missing_part() {
+        let mut parts = encrypt_parts(
+            Procedure::SingleIndicator,
+            &mut daily_key(),
+            &mut StdRng::seed_from_u64(2),
+            "U6Z",
+            "C",
+            "1510",
+            &"A".repeat(30),
+            10,
+        )
+        .unwrap();
+
+        let last = parts.pop().unwrap();
+        assert_eq!(
+            reassemble(parts.clone()),
+            Err(EnigmaError::MissingPart { part: 3, parts: 3 })
+        );
+        parts.remove(0);
+        parts.push(last);
+        assert_eq!(
+            reassemble(parts),
+            Err(EnigmaError::MissingPart { part: 1, parts: 3 })
+        );
+    }
+
+    #[test]
+    fn test_reassemble_rejects_parts_of_other_messages() {
+        let parts = encrypt_parts(
+            Procedure::SingleIndicator,
+            &mut daily_key(),
+            &mut StdRng::seed_from_u64(3),
+            "U6Z",
+            "C",
+            "1510",
+            &"A".repeat(20),
+            10,
+        )
+        .unwrap();
+
+        for change in 0..3 {
+            let mut parts = parts.clone();
+            match change {
+                0 => parts[1].to = "U9K".to_string(),
+                1 => parts[1].from = "D".to_string(),
+                _ => parts[1].time = "1520".to_string(),
+            }
+            assert!(
+                matches!(reassemble(parts), Err(EnigmaError::InvalidHeader(_))),
+                "after change {}",
+                change
+            );
+        }
+    }
+}
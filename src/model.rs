@@ -0,0 +1,143 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// The Enigma variants key sheets are issued for, each with its own box of wheels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Model {
+    /// Enigma I of the Heer and Luftwaffe: three of wheels I-V.
+    EnigmaI,
+    /// Kriegsmarine M3: three of wheels I-VIII.
+    M3,
+    /// Kriegsmarine M4: a Greek wheel and thin reflector ahead of three of wheels I-VIII.
+    M4,
+}
+
+impl Model {
+    /// Names of the wheels that go in the stepping slots.
+    pub fn wheels(&self) -> &'static [&'static str] {
+        match self {
+            Model::EnigmaI => &["i", "ii", "iii", "iv", "v"],
+            Model::M3 | Model::M4 => &["i", "ii", "iii", "iv", "v", "vi", "vii", "viii"],
+        }
+    }
+
+    /// Names of the Greek wheels, which take the leftmost slot of the M4.
+    pub fn greek_wheels(&self) -> &'static [&'static str] {
+        match self {
+            Model::M4 => &["beta", "gamma"],
+            Model::EnigmaI | Model::M3 => &[],
+        }
+    }
+
+    /// Names of the fixed reflectors the model takes.
+    pub fn reflectors(&self) -> &'static [&'static str] {
+        match self {
+            Model::EnigmaI | Model::M3 => &["ukw_b", "ukw_c"],
+            Model::M4 => &["ukw_b_thin", "ukw_c_thin"],
+        }
+    }
+
+    /// Number of rotors in the machine, Greek wheel included.
+    pub fn rotor_count(&self) -> usize {
+        match self {
+            Model::EnigmaI | Model::M3 => 3,
+            Model::M4 => 4,
+        }
+    }
+
+    /// Every wheel order the model's box allows, leftmost wheel first.
+    pub fn wheel_orders(&self) -> Vec<Vec<&'static str>> {
+        let wheels = self.wheels();
+        let mut orders = Vec::new();
+        for &left in wheels {
+            for &middle in wheels.iter().filter(|&&wheel| wheel != left) {
+                for &right in wheels
+                    .iter()
+                    .filter(|&&wheel| wheel != left && wheel != middle)
+                {
+                    orders.push(vec![left, middle, right]);
+                }
+            }
+        }
+        match self.greek_wheels() {
+            [] => orders,
+            greek => greek
+                .iter()
+                .flat_map(|&greek| {
+                    orders.iter().map(move |order| {
+                        std::iter::once(greek)
+                            .chain(order.iter().copied())
+                            .collect()
+                    })
+                })
+                .collect(),
+        }
+    }
+
+    /// The rewirable UKW-D only fits the full-width reflector slot.
+    pub fn supports_ukw_d(&self) -> bool {
+        *self != Model::M4
+    }
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "enigma-i" | "i" => Ok(Model::EnigmaI),
+            "m3" => Ok(Model::M3),
+            "m4" => Ok(Model::M4),
+            _ => Err(format!(
+                "Unknown model: {} (expected enigma-i, m3 or m4)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Model::EnigmaI => "enigma-i",
+            Model::M3 => "m3",
+            Model::M4 => "m4",
+        };
+        f.write_str(name)
+    }
+}
+
+#[cfg(test)]
+mod model_tests {
+    use super::*;
+    use crate::rotor::rotors;
+
+    #[test]
+    fn test_from_str_round_trips_display() {
+        for model in [Model::EnigmaI, Model::M3, Model::M4] {
+            assert_eq!(model.to_string().parse::<Model>(), Ok(model));
+        }
+        assert_eq!("M4".parse::<Model>(), Ok(Model::M4));
+        assert!("m5".parse::<Model>().is_err());
+    }
+
+    #[test]
+    fn test_wheel_orders() {
+        assert_eq!(Model::EnigmaI.wheel_orders().len(), 60);
+        assert_eq!(Model::M3.wheel_orders().len(), 336);
+        let orders = Model::M4.wheel_orders();
+        assert_eq!(orders.len(), 672);
+        assert_eq!(orders[0], ["beta", "i", "ii", "iii"]);
+    }
+
+    #[test]
+    fn test_wheels_exist() {
+        for model in [Model::EnigmaI, Model::M3, Model::M4] {
+            for name in model.wheels().iter().chain(model.greek_wheels()) {
+                assert!(rotors::from_name(name, 'A', 'A').is_some(), "{}", name);
+            }
+        }
+    }
+}
@@ -0,0 +1,435 @@
+//! Kriegsmarine indicator procedure with the Kenngruppenbuch and bigram tables.
+//!
+//! The operator draws two trigrams from the Kenngruppenbuch: the Schlüsselkenngruppe, which
+//! identifies the key net, and the Verfahrenkenngruppe. Keyed at the daily Grundstellung, the
+//! Verfahrenkenngruppe gives the message key. Both trigrams are written one above the other,
+//! padded with a random letter each:
+//!
+//! ```text
+//! x S S S
+//! V V V y
+//! ```
+//!
+//! Each vertical pair is swapped through the bigram table (Doppelbuchstabentauschtafel) and the
+//! two rows become the two four-letter indicator groups, sent at the start and again at the end
+//! of the message.
+
+use crate::error::EnigmaError;
+use crate::format::{ungroup, GroupFormat};
+use crate::machine::EnigmaMachine;
+use rand::seq::SliceRandom;
+use rand::{Rng, RngCore};
+use serde::{Deserialize, Serialize};
+
+const BIGRAMS: usize = 26 * 26;
+const INDICATOR_LEN: usize = 8;
+
+/// The list of trigrams operators draw Kenngruppen from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Kenngruppenbuch {
+    pub groups: Vec<String>,
+}
+
+impl Kenngruppenbuch {
+    /// Draws `count` distinct random trigrams.
+    pub fn generate<R: RngCore + ?Sized>(rng: &mut R, count: usize) -> Self {
+        let mut trigrams: Vec<u16> = (0..26 * 26 * 26).collect();
+        trigrams.shuffle(rng);
+        let groups = trigrams
+            .into_iter()
+            .take(count)
+            .map(|n| {
+                [n / 676, n / 26 % 26, n % 26]
+                    .iter()
+                    .map(|&i| (b'A' + i as u8) as char)
+                    .collect()
+            })
+            .collect();
+        Kenngruppenbuch { groups }
+    }
+
+    pub fn pick<R: RngCore + ?Sized>(&self, rng: &mut R) -> Option<&str> {
+        self.groups.choose(rng).map(String::as_str)
+    }
+
+    pub fn contains(&self, group: &str) -> bool {
+        self.groups.iter().any(|g| g == group)
+    }
+}
+
+/// A reciprocal substitution of letter pairs: if `AB` becomes `XY`, `XY` becomes `AB`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<(String, String)>", into = "Vec<(String, String)>")]
+pub struct BigramTable {
+    swaps: Vec<u16>,
+}
+
+impl BigramTable {
+    /// Pairs up all 676 bigrams at random.
+    pub fn generate<R: RngCore + ?Sized>(rng: &mut R) -> Self {
+        let mut bigrams: Vec<u16> = (0..BIGRAMS as u16).collect();
+        bigrams.shuffle(rng);
+        let mut swaps = vec![0; BIGRAMS];
+        for pair in bigrams.chunks(2) {
+            swaps[pair[0] as usize] = pair[1];
+            swaps[pair[1] as usize] = pair[0];
+        }
+        BigramTable { swaps }
+    }
+
+    pub fn substitute(&self, first: char, second: char) -> Option<(char, char)> {
+        let index = bigram_index(first, second)?;
+        let swapped = self.swaps[index];
+        Some((letter(swapped / 26), letter(swapped % 26)))
+    }
+}
+
+impl TryFrom<Vec<(String, String)>> for BigramTable {
+    type Error = String;
+
+    fn try_from(pairs: Vec<(String, String)>) -> Result<Self, Self::Error> {
+        let mut swaps = vec![u16::MAX; BIGRAMS];
+        for (from, to) in &pairs {
+            let parse = |bigram: &str| {
+                let mut chars = bigram.chars();
+                match (chars.next(), chars.next(), chars.next()) {
+                    (Some(a), Some(b), None) => bigram_index(a, b),
+                    _ => None,
+                }
+                .ok_or_else(|| format!("Invalid bigram: {}", bigram))
+            };
+            let (from, to) = (parse(from)?, parse(to)?);
+            if swaps[from] != u16::MAX || swaps[to] != u16::MAX {
+                return Err("Bigram table lists a bigram twice".to_string());
+            }
+            swaps[from] = to as u16;
+            swaps[to] = from as u16;
+        }
+        if swaps.contains(&u16::MAX) {
+            return Err("Bigram table does not cover every bigram".to_string());
+        }
+        Ok(BigramTable { swaps })
+    }
+}
+
+impl From<BigramTable> for Vec<(String, String)> {
+    fn from(table: BigramTable) -> Self {
+        (0..BIGRAMS)
+            .filter(|&index| index <= table.swaps[index] as usize)
+            .map(|index| {
+                let swapped = table.swaps[index];
+                (
+                    [letter(index as u16 / 26), letter(index as u16 % 26)]
+                        .iter()
+                        .collect(),
+                    [letter(swapped / 26), letter(swapped % 26)]
+                        .iter()
+                        .collect(),
+                )
+            })
+            .collect()
+    }
+}
+
+/// The two trigrams behind a naval indicator.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Kenngruppen {
+    pub schluesselkenngruppe: String,
+    pub verfahrenkenngruppe: String,
+}
+
+/// A naval message: the two indicator groups and the body they key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NavalMessage {
+    pub indicator: [String; 2],
+    pub ciphertext: String,
+}
+
+/// Everything a naval operator needs besides the daily key.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NavalTables {
+    pub kenngruppenbuch: Kenngruppenbuch,
+    pub bigram_table: BigramTable,
+}
+
+impl NavalTables {
+    pub fn generate<R: RngCore + ?Sized>(rng: &mut R, kenngruppen: usize) -> Self {
+        NavalTables {
+            kenngruppenbuch: Kenngruppenbuch::generate(rng, kenngruppen),
+            bigram_table: BigramTable::generate(rng),
+        }
+    }
+
+    /// Writes `kenngruppen` into the two indicator groups, padding with `fillers`.
+    pub fn encode_indicator(
+        &self,
+        kenngruppen: &Kenngruppen,
+        fillers: (char, char),
+    ) -> Result<[String; 2], EnigmaError> {
+        let top: Vec<char> = std::iter::once(fillers.0)
+            .chain(kenngruppen.schluesselkenngruppe.chars())
+            .collect();
+        let bottom: Vec<char> = kenngruppen
+            .verfahrenkenngruppe
+            .chars()
+            .chain(std::iter::once(fillers.1))
+            .collect();
+        if top.len() != 4 || bottom.len() != 4 {
+            return Err(EnigmaError::InvalidIndicator(
+                "Kenngruppen must be three letters each".to_string(),
+            ));
+        }
+        self.swap_columns(&top, &bottom)
+    }
+
+    /// Reads the Kenngruppen back out of the two indicator groups.
+    pub fn decode_indicator(&self, indicator: &[String; 2]) -> Result<Kenngruppen, EnigmaError> {
+        let top: Vec<char> = indicator[0].chars().collect();
+        let bottom: Vec<char> = indicator[1].chars().collect();
+        if top.len() != 4 || bottom.len() != 4 {
+            return Err(EnigmaError::InvalidIndicator(
+                "naval indicator groups must be four letters each".to_string(),
+            ));
+        }
+        let [top, bottom] = self.swap_columns(&top, &bottom)?;
+        Ok(Kenngruppen {
+            schluesselkenngruppe: top[1..].to_string(),
+            verfahrenkenngruppe: bottom[..3].to_string(),
+        })
+    }
+
+    /// The message key: the Verfahrenkenngruppe keyed at the daily Grundstellung. On machines
+    /// with more than three rotors the extra leftmost rotors stay at their Grundstellung.
+    pub fn message_key(
+        &self,
+        machine: &mut EnigmaMachine,
+        grundstellung: &str,
+        verfahrenkenngruppe: &str,
+    ) -> Result<String, EnigmaError> {
+        machine.set_positions(grundstellung)?;
+        let fixed = grundstellung
+            .len()
+            .saturating_sub(verfahrenkenngruppe.len());
+        let mut key = grundstellung[..fixed].to_string();
+        for ch in verfahrenkenngruppe.chars() {
+            key.push(
+                machine
+                    .encrypt_char(ch)
+                    .ok_or(EnigmaError::InvalidCharacter(ch))?,
+            );
+        }
+        Ok(key)
+    }
+
+    /// Sends `plaintext` under a freshly drawn pair of Kenngruppen.
+    pub fn encrypt<R: RngCore + ?Sized>(
+        &self,
+        machine: &mut EnigmaMachine,
+        grundstellung: &str,
+        rng: &mut R,
+        plaintext: &str,
+    ) -> Result<NavalMessage, EnigmaError> {
+        let mut draw = || {
+            self.kenngruppenbuch
+                .pick(rng)
+                .map(str::to_string)
+                .ok_or_else(|| {
+                    EnigmaError::InvalidIndicator("the Kenngruppenbuch is empty".to_string())
+                })
+        };
+        let kenngruppen = Kenngruppen {
+            schluesselkenngruppe: draw()?,
+            verfahrenkenngruppe: draw()?,
+        };
+        let fillers = (
+            (b'A' + rng.gen_range(0..26)) as char,
+            (b'A' + rng.gen_range(0..26)) as char,
+        );
+        self.encrypt_with(machine, grundstellung, &kenngruppen, fillers, plaintext)
+    }
+
+    /// Sends `plaintext` under the given Kenngruppen and filler letters.
+    pub fn encrypt_with(
+        &self,
+        machine: &mut EnigmaMachine,
+        grundstellung: &str,
+        kenngruppen: &Kenngruppen,
+        fillers: (char, char),
+        plaintext: &str,
+    ) -> Result<NavalMessage, EnigmaError> {
+        let indicator = self.encode_indicator(kenngruppen, fillers)?;
+        let message_key =
+            self.message_key(machine, grundstellung, &kenngruppen.verfahrenkenngruppe)?;
+        machine.set_positions(&message_key)?;
+        let ciphertext = machine
+            .encrypt_iter(plaintext.chars())
+            .collect::<Result<String, EnigmaError>>()?;
+        Ok(NavalMessage {
+            indicator,
+            ciphertext,
+        })
+    }
+
+    /// Receives `message`, rejecting Schlüsselkenngruppen missing from the Kenngruppenbuch.
+    pub fn decrypt(
+        &self,
+        machine: &mut EnigmaMachine,
+        grundstellung: &str,
+        message: &NavalMessage,
+    ) -> Result<String, EnigmaError> {
+        let kenngruppen = self.decode_indicator(&message.indicator)?;
+        if !self
+            .kenngruppenbuch
+            .contains(&kenngruppen.schluesselkenngruppe)
+        {
+            return Err(EnigmaError::InvalidIndicator(format!(
+                "Schlüsselkenngruppe {} is not in the Kenngruppenbuch",
+                kenngruppen.schluesselkenngruppe
+            )));
+        }
+        let message_key =
+            self.message_key(machine, grundstellung, &kenngruppen.verfahrenkenngruppe)?;
+        machine.set_positions(&message_key)?;
+        machine.encrypt_iter(message.ciphertext.chars()).collect()
+    }
+
+    /// Lays out `message` with the indicator groups at both ends of the body.
+    pub fn format(&self, message: &NavalMessage, groups: GroupFormat) -> String {
+        let indicator = message.indicator.join(" ");
+        format!(
+            "{}\n{}\n{}",
+            indicator,
+            groups.format(&message.ciphertext),
+            indicator
+        )
+    }
+
+    /// Reads back a message laid out by `format`, checking both indicator copies agree.
+    pub fn parse(&self, text: &str) -> Result<NavalMessage, EnigmaError> {
+        let letters = ungroup(text);
+        if letters.len() < 2 * INDICATOR_LEN || !letters.is_ascii() {
+            return Err(EnigmaError::InvalidIndicator(
+                "message is too short to carry both indicator copies".to_string(),
+            ));
+        }
+        let (start, rest) = letters.split_at(INDICATOR_LEN);
+        let (body, end) = rest.split_at(rest.len() - INDICATOR_LEN);
+        if start != end {
+            return Err(EnigmaError::InvalidIndicator(format!(
+                "indicator groups differ at start ({}) and end ({})",
+                start, end
+            )));
+        }
+        Ok(NavalMessage {
+            indicator: [start[..4].to_string(), start[4..].to_string()],
+            ciphertext: body.to_string(),
+        })
+    }
+
+    fn swap_columns(&self, top: &[char], bottom: &[char]) -> Result<[String; 2], EnigmaError> {
+        let mut rows = [String::new(), String::new()];
+        for (&first, &second) in top.iter().zip(bottom) {
+            let (first, second) = self.bigram_table.substitute(first, second).ok_or_else(|| {
+                EnigmaError::InvalidIndicator(format!("{}{} is not a bigram", first, second))
+            })?;
+            rows[0].push(first);
+            rows[1].push(second);
+        }
+        Ok(rows)
+    }
+}
+
+fn bigram_index(first: char, second: char) -> Option<usize> {
+    (first.is_ascii_uppercase() && second.is_ascii_uppercase())
+        .then(|| (first as u8 - b'A') as usize * 26 + (second as u8 - b'A') as usize)
+}
+
+fn letter(index: u16) -> char {
+    (b'A' + index as u8) as char
+}
+
+#[cfg(test)]
+mod naval_tests {
+    use super::*;
+    use crate::plugboard::Plugboard;
+    use crate::reflector::reflectors;
+    use crate::rotor::rotors;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn daily_key() -> EnigmaMachine {
+        EnigmaMachine::new(
+            vec![
+                rotors::type_i('A', 'A'),
+                rotors::type_iv('A', 'C'),
+                rotors::type_ii('A', 'D'),
+                rotors::type_v('A', 'Q'),
+            ],
+            reflectors::ukw_c(),
+            Plugboard::new(vec![('A', 'T'), ('B', 'L'), ('D', 'F'), ('G', 'J')]).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_bigram_table_is_reciprocal() {
+        let table = BigramTable::generate(&mut StdRng::seed_from_u64(1));
+        for first in 'A'..='Z' {
+            for second in 'A'..='Z' {
+                let (a, b) = table.substitute(first, second).unwrap();
+                assert_eq!(table.substitute(a, b), Some((first, second)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_bigram_table_json_round_trip() {
+        let table = BigramTable::generate(&mut StdRng::seed_from_u64(2));
+        let json = serde_json::to_string(&table).unwrap();
+        assert_eq!(serde_json::from_str::<BigramTable>(&json).unwrap(), table);
+
+        assert!(serde_json::from_str::<BigramTable>(r#"[["AB", "CD"]]"#).is_err());
+    }
+
+    #[test]
+    fn test_indicator_round_trip() {
+        let tables = NavalTables::generate(&mut StdRng::seed_from_u64(3), 20);
+        let kenngruppen = Kenngruppen {
+            schluesselkenngruppe: "SAL".to_string(),
+            verfahrenkenngruppe: "THL".to_string(),
+        };
+        let indicator = tables.encode_indicator(&kenngruppen, ('X', 'Q')).unwrap();
+        assert_eq!(indicator[0].len(), 4);
+        assert_eq!(tables.decode_indicator(&indicator).unwrap(), kenngruppen);
+    }
+
+    #[test]
+    fn test_naval_message_round_trip() {
+        let mut rng = StdRng::seed_from_u64(4);
+        let tables = NavalTables::generate(&mut rng, 20);
+
+        let sent = tables
+            .encrypt(&mut daily_key(), "BQRD", &mut rng, "UBOOTAUFGETAUCHT")
+            .unwrap();
+        let text = tables.format(&sent, GroupFormat::FOUR);
+        let received = tables.parse(&text).unwrap();
+        assert_eq!(received, sent);
+        assert_eq!(
+            tables.decrypt(&mut daily_key(), "BQRD", &received).unwrap(),
+            "UBOOTAUFGETAUCHT"
+        );
+    }
+
+    #[test]
+    fn test_message_key_keeps_greek_wheel() {
+        let tables = NavalTables::generate(&mut StdRng::seed_from_u64(5), 1);
+        let key = tables.message_key(&mut daily_key(), "BQRD", "THL").unwrap();
+        assert_eq!(key.len(), 4);
+        assert!(key.starts_with('B'));
+    }
+
+    #[test]
+    fn test_parse_rejects_mismatched_indicator() {
+        let tables = NavalTables::generate(&mut StdRng::seed_from_u64(6), 1);
+        assert!(tables.parse("ABCD EFGH IJKL ABCD EFGZ").is_err());
+    }
+}
@@ -1,5 +1,5 @@
 /// Represents the Plugboard component of an Enigma machine.
-
+#[derive(Clone)]
 pub struct Plugboard {
     plugboard_map: Vec<(char, char)>,
 }
@@ -18,7 +18,7 @@ impl Plugboard {
             if chars_seen.contains(&a) || chars_seen.contains(&b) {
                 return false;
             }
-            if !('A'..='Z').contains(&a) || !('A'..='Z').contains(&b) {
+            if !a.is_ascii_uppercase() || !b.is_ascii_uppercase() {
                 return false;
             }
             chars_seen.insert(a);
@@ -27,6 +27,11 @@ impl Plugboard {
         true
     }
 
+    /// The plugged pairs, in the order they were given.
+    pub fn pairs(&self) -> &[(char, char)] {
+        &self.plugboard_map
+    }
+
     pub fn pass_through(&self, c: char) -> char {
         self.plugboard_map
             .iter()
@@ -38,14 +43,16 @@ impl Plugboard {
 
 pub mod plugboards {
     use super::Plugboard;
-    use rand::prelude::ThreadRng;
     use rand::seq::SliceRandom;
-    use rand::{thread_rng, Rng};
+    use rand::{Rng, RngCore};
 
-    pub fn generate_random_mappings() -> Result<Plugboard, &'static str> {
+    /// Plugs 1 to 13 random pairs, drawn from `rng`; a seeded `rng` gives the same plugboard
+    /// every time.
+    pub fn generate_random_mappings<R: RngCore + ?Sized>(
+        rng: &mut R,
+    ) -> Result<Plugboard, &'static str> {
         let mut chars: Vec<char> = ('A'..='Z').collect();
-        let mut rng: ThreadRng = thread_rng();
-        chars.shuffle(&mut rng);
+        chars.shuffle(rng);
 
         // Perhaps this should be len
         let num_mappings: usize = rng.gen_range(1..=13);
@@ -62,6 +69,8 @@ pub mod plugboards {
 #[cfg(test)]
 mod plugboard_tests {
     use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
 
     #[test]
     fn test_pass_through_with_known_mapping() {
@@ -79,10 +88,19 @@ mod plugboard_tests {
 
     #[test]
     fn test_random_mapping_generation() {
-        let plugboard = plugboards::generate_random_mappings().unwrap();
+        let plugboard =
+            plugboards::generate_random_mappings(&mut StdRng::seed_from_u64(1)).unwrap();
         // Now that we've unwrapped the Result, we have direct access to the Plugboard instance.
         assert!(plugboard.plugboard_map.len() <= 13);
-        assert!(plugboard.plugboard_map.len() >= 1);
+        assert!(!plugboard.plugboard_map.is_empty());
+    }
+
+    #[test]
+    fn test_seeded_mapping_generation_is_reproducible() {
+        let first = plugboards::generate_random_mappings(&mut StdRng::seed_from_u64(1940)).unwrap();
+        let second =
+            plugboards::generate_random_mappings(&mut StdRng::seed_from_u64(1940)).unwrap();
+        assert_eq!(first.plugboard_map, second.plugboard_map);
     }
 
     #[test]
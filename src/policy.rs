@@ -0,0 +1,96 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// What the machine does with characters that have no key (anything outside `A-Z`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NonAlphaPolicy {
+    /// Reject the character with `EnigmaError::InvalidCharacter`.
+    #[default]
+    Error,
+    /// Drop the character silently.
+    Skip,
+    /// Emit the character unchanged, without stepping the rotors.
+    PassThrough,
+    /// Rewrite the character the way operators did (space -> X, digits spelled out) and
+    /// encrypt the replacement. Characters with no convention are rejected.
+    Substitute,
+}
+
+impl NonAlphaPolicy {
+    /// The letters an operator would have keyed in place of `ch`, if there is a convention
+    /// for it.
+    pub fn substitute(ch: char) -> Option<&'static str> {
+        match ch {
+            ' ' | '\n' | '\t' | '.' => Some("X"),
+            ',' => Some("Y"),
+            '?' => Some("UD"),
+            ':' => Some("XX"),
+            '-' | '/' => Some("YY"),
+            '0' => Some("NULL"),
+            '1' => Some("EINS"),
+            '2' => Some("ZWO"),
+            '3' => Some("DREI"),
+            '4' => Some("VIER"),
+            '5' => Some("FUENF"),
+            '6' => Some("SECHS"),
+            '7' => Some("SIEBEN"),
+            '8' => Some("ACHT"),
+            '9' => Some("NEUN"),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for NonAlphaPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "error" => Ok(NonAlphaPolicy::Error),
+            "skip" => Ok(NonAlphaPolicy::Skip),
+            "pass-through" | "passthrough" => Ok(NonAlphaPolicy::PassThrough),
+            "substitute" => Ok(NonAlphaPolicy::Substitute),
+            _ => Err(format!(
+                "Unknown non-alpha policy: {} (expected error, skip, pass-through or substitute)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for NonAlphaPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            NonAlphaPolicy::Error => "error",
+            NonAlphaPolicy::Skip => "skip",
+            NonAlphaPolicy::PassThrough => "pass-through",
+            NonAlphaPolicy::Substitute => "substitute",
+        };
+        f.write_str(name)
+    }
+}
+
+#[cfg(test)]
+mod policy_tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str_round_trips_display() {
+        for policy in [
+            NonAlphaPolicy::Error,
+            NonAlphaPolicy::Skip,
+            NonAlphaPolicy::PassThrough,
+            NonAlphaPolicy::Substitute,
+        ] {
+            assert_eq!(policy.to_string().parse::<NonAlphaPolicy>(), Ok(policy));
+        }
+        assert!("drop".parse::<NonAlphaPolicy>().is_err());
+    }
+
+    #[test]
+    fn test_substitutions() {
+        assert_eq!(NonAlphaPolicy::substitute(' '), Some("X"));
+        assert_eq!(NonAlphaPolicy::substitute('2'), Some("ZWO"));
+        assert_eq!(NonAlphaPolicy::substitute('#'), None);
+    }
+}
@@ -0,0 +1,557 @@
+//! Historical plaintext conventions.
+//!
+//! Operators rewrote messages into plain `A-Z` before keying them. The rules differ per
+//! service; this module implements the following subset:
+//!
+//! | | Heer | Luftwaffe | Kriegsmarine |
+//! |---|---|---|---|
+//! | word break, full stop, `:` | `X`, `XX` | `X`, `XX` | `X`, `XX` |
+//! | comma | `ZZ` | `Y` | `Y` |
+//! | `?`, `-` `/`, brackets | `UD`, `YY`, `KK` | `UD`, `YY`, `KK` | `UD`, `YY`, `KK` |
+//! | `CH` | `Q` | `Q` | kept |
+//! | numbers | digits spelled out | top-row keys framed by `J` | digits spelled out, framed by `Q` |
+//! | `{Name}` | letters doubled | letters doubled | framed by `J` |
+//!
+//! Umlauts become `AE`/`OE`/`UE`, `ß` becomes `SS` and each service's abbreviation table is
+//! applied to whole words first. Proper names are marked by the writer with braces since nothing
+//! in the text identifies them reliably; words that contain a separator such as `X` are keyed
+//! like names so they read back intact.
+
+use crate::error::EnigmaError;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Convention {
+    Heer,
+    Luftwaffe,
+    Kriegsmarine,
+}
+
+const SPELLED_DIGITS: [&str; 10] = [
+    "NULL", "EINS", "ZWO", "DREI", "VIER", "FUENF", "SECHS", "SIEBEN", "ACHT", "NEUN",
+];
+
+/// The top row of the Enigma keyboard, standing in for 1-9 and 0.
+const TOP_ROW_DIGITS: [char; 10] = ['P', 'Q', 'W', 'E', 'R', 'T', 'Z', 'U', 'I', 'O'];
+
+const HEER_ABBREVIATIONS: &[(&str, &str)] = &[
+    ("OBERKOMMANDO DES HEERES", "OKH"),
+    ("OBERKOMMANDO DER WEHRMACHT", "OKW"),
+    ("ARMEEKORPS", "AK"),
+    ("DIVISION", "DIV"),
+    ("REGIMENT", "RGT"),
+    ("BATAILLON", "BTL"),
+    ("KOMMANDEUR", "KDR"),
+    ("INFANTERIE", "INF"),
+];
+
+const LUFTWAFFE_ABBREVIATIONS: &[(&str, &str)] = &[
+    ("OBERKOMMANDO DER LUFTWAFFE", "OKL"),
+    ("OBERKOMMANDO DER WEHRMACHT", "OKW"),
+    ("KAMPFGESCHWADER", "KG"),
+    ("JAGDGESCHWADER", "JG"),
+    ("STUKAGESCHWADER", "STG"),
+];
+
+const KRIEGSMARINE_ABBREVIATIONS: &[(&str, &str)] = &[
+    ("OBERKOMMANDO DER MARINE", "OKM"),
+    ("BEFEHLSHABER DER UNTERSEEBOOTE", "BDU"),
+    ("KOMMANDANT", "KDT"),
+    ("FREGATTENKAPITAEN", "FKPT"),
+    ("KORVETTENKAPITAEN", "KKPT"),
+    ("KAPITAENLEUTNANT", "KPTLT"),
+];
+
+impl Convention {
+    /// Rewrites modern plaintext into keyable `A-Z` text following this service's rules.
+    ///
+    /// Letters outside `A-Z` other than umlauts and `ß` are rejected rather than dropped.
+    pub fn preprocess(&self, text: &str) -> Result<String, EnigmaError> {
+        let mut normalized = expand_umlauts(&text.to_uppercase());
+        for &(phrase, abbreviation) in self.abbreviations() {
+            normalized = replace_words(&normalized, phrase, abbreviation);
+        }
+
+        let mut output = String::new();
+        let mut word = String::new();
+        // Whitespace between two words keys as a break; punctuation takes its place
+        let mut after_word = false;
+        let mut spaced = false;
+        let mut chars = normalized.trim().chars().peekable();
+        while let Some(ch) = chars.next() {
+            let starts_word = ch.is_ascii_uppercase() || ch.is_ascii_digit() || ch == '{';
+            if starts_word && spaced {
+                output.push('X');
+                spaced = false;
+            }
+            if ch.is_ascii_uppercase() {
+                word.push(ch);
+                after_word = true;
+                continue;
+            }
+            self.flush_word(&mut word, &mut output);
+
+            match ch {
+                '{' => {
+                    let name: String = chars
+                        .by_ref()
+                        .take_while(|&c| c != '}')
+                        .filter(char::is_ascii_uppercase)
+                        .collect();
+                    output.push_str(&self.encode_name(&name));
+                    after_word = true;
+                }
+                '0'..='9' => {
+                    let mut digits = ch.to_string();
+                    while let Some(&next) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                        digits.push(next);
+                        chars.next();
+                    }
+                    output.push_str(&self.encode_number(&digits));
+                    after_word = true;
+                }
+                _ if ch.is_whitespace() => spaced = after_word,
+                _ if ch.is_alphanumeric() => return Err(EnigmaError::InvalidCharacter(ch)),
+                _ => {
+                    let mark = self.punctuation(ch);
+                    if !mark.is_empty() {
+                        output.push_str(mark);
+                        after_word = false;
+                        spaced = false;
+                    }
+                }
+            }
+        }
+        self.flush_word(&mut word, &mut output);
+        Ok(output)
+    }
+
+    /// Best-effort reverse of `preprocess` for decrypted text: restores word breaks,
+    /// punctuation, numbers, `CH` and marked names.
+    ///
+    /// A full stop keys like a word break, so it only comes back at the end of the text.
+    pub fn format(&self, decrypted: &str) -> String {
+        let letters: String = decrypted
+            .chars()
+            .filter(char::is_ascii_alphabetic)
+            .map(|ch| ch.to_ascii_uppercase())
+            .collect();
+
+        let pieces = vec![Piece::Keyed(letters)];
+        let pieces = match self {
+            Convention::Heer => undouble_names(pieces, &["XX", "ZZ", "YY"]),
+            Convention::Luftwaffe => {
+                undouble_names(decode_frames(pieces, 'J', decode_top_row), &["XX", "YY"])
+            }
+            Convention::Kriegsmarine => {
+                let pieces = decode_frames(pieces, 'Q', decode_spelled);
+                decode_frames(pieces, 'J', |name| Some(name.to_string()))
+            }
+        };
+        self.restore(&pieces)
+    }
+
+    fn abbreviations(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Convention::Heer => HEER_ABBREVIATIONS,
+            Convention::Luftwaffe => LUFTWAFFE_ABBREVIATIONS,
+            Convention::Kriegsmarine => KRIEGSMARINE_ABBREVIATIONS,
+        }
+    }
+
+    fn replaces_ch(&self) -> bool {
+        !matches!(self, Convention::Kriegsmarine)
+    }
+
+    /// Keys a finished word. One that would read back as punctuation is keyed like a name.
+    fn flush_word(&self, word: &mut String, output: &mut String) {
+        if word.is_empty() {
+            return;
+        }
+        if (0..word.len()).any(|index| self.separator(&word[index..]).is_some()) {
+            output.push_str(&self.encode_name(word));
+        } else if self.replaces_ch() {
+            output.push_str(&word.replace("CH", "Q"));
+        } else {
+            output.push_str(word);
+        }
+        word.clear();
+    }
+
+    fn punctuation(&self, ch: char) -> &'static str {
+        match ch {
+            '.' | '!' => "X",
+            ':' => "XX",
+            ',' if self == &Convention::Heer => "ZZ",
+            ',' => "Y",
+            '?' => "UD",
+            '-' | '/' => "YY",
+            '(' | ')' => "KK",
+            _ => "",
+        }
+    }
+
+    /// The separator `text` starts with, if any: its length and the mark it stands for. A
+    /// lone `X` is a word break, or a full stop at the end of the text.
+    fn separator(&self, text: &str) -> Option<(usize, &'static str)> {
+        let separators: &[(&str, &str)] = match self {
+            Convention::Heer => &[("XX", ": "), ("ZZ", ", "), ("YY", "/"), ("X", " ")],
+            _ => &[("XX", ": "), ("YY", "/"), ("Y", ", "), ("X", " ")],
+        };
+        separators
+            .iter()
+            .find(|(keyed, _)| text.starts_with(keyed))
+            .map(|&(keyed, mark)| (keyed.len(), mark))
+    }
+
+    fn encode_number(&self, digits: &str) -> String {
+        let spelled = || {
+            digits
+                .chars()
+                .map(|d| SPELLED_DIGITS[d.to_digit(10).unwrap() as usize])
+                .collect::<String>()
+        };
+        match self {
+            Convention::Heer => spelled().replace("CH", "Q"),
+            Convention::Luftwaffe => {
+                let keys: String = digits
+                    .chars()
+                    .map(|d| TOP_ROW_DIGITS[d.to_digit(10).unwrap() as usize])
+                    .collect();
+                format!("J{}J", keys)
+            }
+            Convention::Kriegsmarine => format!("Q{}Q", spelled()),
+        }
+    }
+
+    fn encode_name(&self, name: &str) -> String {
+        match self {
+            Convention::Kriegsmarine => format!("J{}J", name),
+            _ => name.chars().flat_map(|ch| [ch, ch]).collect(),
+        }
+    }
+
+    /// Joins decoded pieces back into text. Separators only count as such after a word, so an
+    /// `X` or `Y` that starts the text or follows another separator stays a letter.
+    fn restore(&self, pieces: &[Piece]) -> String {
+        let mut output = String::new();
+        let mut after_word = false;
+        for (index, piece) in pieces.iter().enumerate() {
+            let keyed = match piece {
+                Piece::Keyed(keyed) if self.replaces_ch() => keyed.replace('Q', "CH"),
+                Piece::Keyed(keyed) => keyed.clone(),
+                Piece::Decoded(decoded) => {
+                    output.push_str(decoded);
+                    after_word = true;
+                    continue;
+                }
+            };
+            let mut rest = keyed.as_str();
+            while let Some(ch) = rest.chars().next() {
+                match self.separator(rest).filter(|_| after_word) {
+                    Some((len, mark)) => {
+                        rest = &rest[len..];
+                        let at_end = rest.is_empty() && index + 1 == pieces.len();
+                        output.push_str(if at_end && mark == " " { "." } else { mark });
+                        after_word = false;
+                    }
+                    None => {
+                        output.push(ch);
+                        rest = &rest[ch.len_utf8()..];
+                        after_word = true;
+                    }
+                }
+            }
+        }
+        output.trim_end().to_string()
+    }
+}
+
+impl FromStr for Convention {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "heer" => Ok(Convention::Heer),
+            "luftwaffe" => Ok(Convention::Luftwaffe),
+            "kriegsmarine" => Ok(Convention::Kriegsmarine),
+            _ => Err(format!(
+                "Unknown convention: {} (expected heer, luftwaffe or kriegsmarine)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Convention {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Convention::Heer => "heer",
+            Convention::Luftwaffe => "luftwaffe",
+            Convention::Kriegsmarine => "kriegsmarine",
+        };
+        f.write_str(name)
+    }
+}
+
+fn expand_umlauts(text: &str) -> String {
+    let mut expanded = String::with_capacity(text.len());
+    for ch in text.chars() {
+        match ch {
+            'Ä' => expanded.push_str("AE"),
+            'Ö' => expanded.push_str("OE"),
+            'Ü' => expanded.push_str("UE"),
+            'ß' | 'ẞ' => expanded.push_str("SS"),
+            _ => expanded.push(ch),
+        }
+    }
+    expanded
+}
+
+/// Replaces `phrase` with `replacement` wherever it stands as whole words.
+fn replace_words(text: &str, phrase: &str, replacement: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find(phrase) {
+        let end = start + phrase.len();
+        let before_ok = !rest[..start]
+            .chars()
+            .next_back()
+            .is_some_and(|c| c.is_alphabetic());
+        let after_ok = !rest[end..]
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphabetic());
+
+        output.push_str(&rest[..start]);
+        output.push_str(if before_ok && after_ok {
+            replacement
+        } else {
+            phrase
+        });
+        rest = &rest[end..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Decrypted text on its way back through `Convention::format`.
+enum Piece {
+    /// Letters as they were keyed.
+    Keyed(String),
+    /// A number or name that has been decoded already.
+    Decoded(String),
+}
+
+/// Appends keyed letters, joining them to keyed letters just before so no separator is cut.
+fn push_keyed(pieces: &mut Vec<Piece>, text: &str) {
+    match pieces.last_mut() {
+        _ if text.is_empty() => {}
+        Some(Piece::Keyed(last)) => last.push_str(text),
+        _ => pieces.push(Piece::Keyed(text.to_string())),
+    }
+}
+
+/// Collapses runs of at least three doubled letters ("GGOOEERR...") back into a single name.
+/// A doubled `marks` pair that joins a name to a word on either side is punctuation, not part
+/// of the name.
+fn undouble_names(pieces: Vec<Piece>, marks: &[&str]) -> Vec<Piece> {
+    let mut output = Vec::with_capacity(pieces.len());
+    for piece in pieces {
+        let text = match piece {
+            Piece::Keyed(text) => text,
+            decoded => {
+                output.push(decoded);
+                continue;
+            }
+        };
+        let bytes = text.as_bytes();
+        let mut i = 0;
+        let mut after_mark = false;
+        while i < bytes.len() {
+            let mut pairs = 0;
+            while i + 2 * pairs + 1 < bytes.len()
+                && bytes[i + 2 * pairs] == bytes[i + 2 * pairs + 1]
+            {
+                pairs += 1;
+            }
+            let mark = pairs > 0
+                && i > 0
+                && bytes[i - 1] != b'X'
+                && !after_mark
+                && marks.contains(&&text[i..i + 2]);
+            after_mark = pairs > 3 && mark;
+            if after_mark {
+                push_keyed(&mut output, &text[i..i + 2]);
+                i += 2;
+            } else if pairs >= 3 && !mark {
+                // Punctuation runs straight on into the next word, where a name ends in a break
+                let end = i + 2 * pairs;
+                if pairs > 3 && end < bytes.len() && bytes[end] != b'X' {
+                    pairs -= usize::from(marks.contains(&&text[end - 2..end]));
+                }
+                let name = (0..pairs).map(|p| bytes[i + 2 * p] as char).collect();
+                output.push(Piece::Decoded(name));
+                i += 2 * pairs;
+            } else {
+                push_keyed(&mut output, &text[i..=i]);
+                i += 1;
+            }
+        }
+    }
+    output
+}
+
+/// Decodes every `frame`-delimited run of keyed text that `decode` accepts. A frame letter
+/// that does not open a decodable run is left as keyed text.
+fn decode_frames(
+    pieces: Vec<Piece>,
+    frame: char,
+    decode: impl Fn(&str) -> Option<String>,
+) -> Vec<Piece> {
+    let mut output = Vec::with_capacity(pieces.len());
+    for piece in pieces {
+        let text = match piece {
+            Piece::Keyed(text) => text,
+            decoded => {
+                output.push(decoded);
+                continue;
+            }
+        };
+        let mut rest = text.as_str();
+        while let Some(start) = rest.find(frame) {
+            let Some(len) = rest[start + 1..].find(frame) else {
+                break;
+            };
+            match decode(&rest[start + 1..start + 1 + len]) {
+                Some(decoded) => {
+                    push_keyed(&mut output, &rest[..start]);
+                    output.push(Piece::Decoded(decoded));
+                    rest = &rest[start + len + 2..];
+                }
+                None => {
+                    push_keyed(&mut output, &rest[..=start]);
+                    rest = &rest[start + 1..];
+                }
+            }
+        }
+        push_keyed(&mut output, rest);
+    }
+    output
+}
+
+fn decode_top_row(keys: &str) -> Option<String> {
+    keys.chars()
+        .map(|key| {
+            TOP_ROW_DIGITS
+                .iter()
+                .position(|&k| k == key)
+                .and_then(|digit| char::from_digit(digit as u32, 10))
+        })
+        .collect()
+}
+
+fn decode_spelled(mut words: &str) -> Option<String> {
+    let mut digits = String::new();
+    while !words.is_empty() {
+        let digit = SPELLED_DIGITS
+            .iter()
+            .position(|spelled| words.starts_with(spelled))?;
+        digits.push(char::from_digit(digit as u32, 10)?);
+        words = &words[SPELLED_DIGITS[digit].len()..];
+    }
+    (!digits.is_empty()).then_some(digits)
+}
+
+#[cfg(test)]
+mod preprocess_tests {
+    use super::*;
+
+    #[test]
+    fn test_heer_preprocess() {
+        assert_eq!(
+            Convention::Heer
+                .preprocess("Nach Süden, 6. Division.")
+                .unwrap(),
+            "NAQXSUEDENZZSEQSXDIVX"
+        );
+        assert_eq!(
+            Convention::Heer.format("NAQXSUEDENZZSEQSXDIVX"),
+            "NACH SUEDEN, SECHS DIV."
+        );
+    }
+
+    #[test]
+    fn test_luftwaffe_numbers_use_top_row() {
+        assert_eq!(
+            Convention::Luftwaffe.preprocess("Angriff 1930").unwrap(),
+            "ANGRIFFXJQOEPJ"
+        );
+        assert_eq!(
+            Convention::Luftwaffe.format("ANGRIFFXJQOEPJ"),
+            "ANGRIFF 1930"
+        );
+    }
+
+    #[test]
+    fn test_kriegsmarine_preprocess() {
+        assert_eq!(
+            Convention::Kriegsmarine
+                .preprocess("Kommandant {Prien}: Planquadrat 42, fertig.")
+                .unwrap(),
+            "KDTXJPRIENJXXPLANQUADRATXQVIERZWOQYFERTIGX"
+        );
+    }
+
+    #[test]
+    fn test_kriegsmarine_format() {
+        assert_eq!(
+            Convention::Kriegsmarine.format("KDTXJPRIENJXXPLANQUADRATXQVIERZWOQYFERTIGX"),
+            "KDT PRIEN: PLANQUADRAT 42, FERTIG."
+        );
+    }
+
+    #[test]
+    fn test_words_with_separator_letters_round_trip() {
+        let text = "Taxi nach Sylt: Xaver, Yvonne.";
+        for convention in [Convention::Heer, Convention::Kriegsmarine] {
+            let keyed = convention.preprocess(text).unwrap();
+            assert_eq!(convention.format(&keyed), "TAXI NACH SYLT: XAVER, YVONNE.");
+        }
+        // Without a word before them, X and Y are letters
+        assert_eq!(Convention::Kriegsmarine.format("XAVERX"), "XAVER.");
+    }
+
+    #[test]
+    fn test_unknown_letters_are_rejected() {
+        assert_eq!(
+            Convention::Heer.preprocess("Café"),
+            Err(EnigmaError::InvalidCharacter('É'))
+        );
+        assert_eq!(Convention::Heer.preprocess("„Halt“").unwrap(), "HALT");
+    }
+
+    #[test]
+    fn test_heer_names_round_trip() {
+        let keyed = Convention::Heer.preprocess("An {Rommel} sofort").unwrap();
+        assert_eq!(keyed, "ANXRROOMMMMEELLXSOFORT");
+        assert_eq!(Convention::Heer.format(&keyed), "AN ROMMEL SOFORT");
+    }
+
+    #[test]
+    fn test_abbreviations_only_match_whole_words() {
+        assert_eq!(
+            Convention::Heer
+                .preprocess("Divisionen der Division")
+                .unwrap(),
+            "DIVISIONENXDERXDIV"
+        );
+    }
+
+    #[test]
+    fn test_convention_from_str() {
+        assert_eq!("kriegsmarine".parse(), Ok(Convention::Kriegsmarine));
+        assert!("navy".parse::<Convention>().is_err());
+    }
+}
@@ -0,0 +1,350 @@
+//! Message key procedures (Spruchschlüssel).
+//!
+//! The daily key fixes wheel order, rings and plugboard. Each message is then sent at its own
+//! message key, itself encrypted at a Grundstellung (basic setting) so the receiver can recover
+//! it:
+//!
+//! - **Doubled indicator** (until May 1940): the Grundstellung is part of the daily key. The
+//!   operator keys the message key twice at it and the result leads the message body.
+//! - **Single indicator** (from May 1940): the operator picks a Grundstellung per message, sends
+//!   it in the clear and keys the message key once at it.
+
+use crate::error::EnigmaError;
+use crate::format::{ungroup, GroupFormat};
+use crate::machine::EnigmaMachine;
+use rand::{Rng, RngCore};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Procedure {
+    DoubledIndicator,
+    SingleIndicator,
+}
+
+/// The encrypted message key together with the Grundstellung it was encrypted at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Indicator {
+    pub grundstellung: String,
+    pub encrypted_key: String,
+}
+
+/// A message body along with the indicator needed to decrypt it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyedMessage {
+    pub indicator: Indicator,
+    pub ciphertext: String,
+}
+
+impl Procedure {
+    /// Encrypts `message_key` at `grundstellung`, doubling it if the procedure calls for it.
+    pub fn encrypt_indicator(
+        &self,
+        machine: &mut EnigmaMachine,
+        grundstellung: &str,
+        message_key: &str,
+    ) -> Result<Indicator, EnigmaError> {
+        if message_key.len() != grundstellung.len() {
+            return Err(EnigmaError::PositionCount {
+                expected: grundstellung.len(),
+                found: message_key.len(),
+            });
+        }
+        machine.set_positions(grundstellung)?;
+        let keyed = match self {
+            Procedure::DoubledIndicator => message_key.repeat(2),
+            Procedure::SingleIndicator => message_key.to_string(),
+        };
+        Ok(Indicator {
+            grundstellung: grundstellung.to_string(),
+            encrypted_key: encrypt_letters(machine, &keyed)?,
+        })
+    }
+
+    /// Recovers the message key from `indicator`, checking a doubled key repeats.
+    pub fn decrypt_indicator(
+        &self,
+        machine: &mut EnigmaMachine,
+        indicator: &Indicator,
+    ) -> Result<String, EnigmaError> {
+        machine.set_positions(&indicator.grundstellung)?;
+        let decrypted = encrypt_letters(machine, &indicator.encrypted_key)?;
+        let key_len = indicator.grundstellung.len();
+
+        match self {
+            Procedure::DoubledIndicator => {
+                let (first, second) = decrypted.split_at(decrypted.len() / 2);
+                if first != second || first.len() != key_len {
+                    return Err(EnigmaError::InvalidIndicator(format!(
+                        "doubled message key does not repeat: {}",
+                        decrypted
+                    )));
+                }
+                Ok(first.to_string())
+            }
+            Procedure::SingleIndicator => {
+                if decrypted.len() != key_len {
+                    return Err(EnigmaError::InvalidIndicator(format!(
+                        "expected a {} letter message key, found {}",
+                        key_len, decrypted
+                    )));
+                }
+                Ok(decrypted)
+            }
+        }
+    }
+
+    /// Sends `plaintext`: encrypts the indicator at `grundstellung`, then the body at
+    /// `message_key`.
+    pub fn encrypt(
+        &self,
+        machine: &mut EnigmaMachine,
+        grundstellung: &str,
+        message_key: &str,
+        plaintext: &str,
+    ) -> Result<KeyedMessage, EnigmaError> {
+        let indicator = self.encrypt_indicator(machine, grundstellung, message_key)?;
+        machine.set_positions(message_key)?;
+        let ciphertext = machine
+            .encrypt_iter(plaintext.chars())
+            .collect::<Result<String, EnigmaError>>()?;
+        Ok(KeyedMessage {
+            indicator,
+            ciphertext,
+        })
+    }
+
+    /// Receives `message`: recovers the message key, then decrypts the body at it.
+    pub fn decrypt(
+        &self,
+        machine: &mut EnigmaMachine,
+        message: &KeyedMessage,
+    ) -> Result<String, EnigmaError> {
+        let message_key = self.decrypt_indicator(machine, &message.indicator)?;
+        machine.set_positions(&message_key)?;
+        machine.encrypt_iter(message.ciphertext.chars()).collect()
+    }
+
+    /// Lays out `message` for transmission.
+    ///
+    /// A single indicator goes on its own line as `GRUNDSTELLUNG KEY`; a doubled indicator
+    /// leads the body.
+    pub fn format(&self, message: &KeyedMessage, groups: GroupFormat) -> String {
+        match self {
+            Procedure::DoubledIndicator => groups.format(&format!(
+                "{}{}",
+                message.indicator.encrypted_key, message.ciphertext
+            )),
+            Procedure::SingleIndicator => format!(
+                "{} {}\n{}",
+                message.indicator.grundstellung,
+                message.indicator.encrypted_key,
+                groups.format(&message.ciphertext)
+            ),
+        }
+    }
+
+    /// Reads back a message laid out by `format`. The doubled procedure takes its
+    /// Grundstellung from the daily key, `daily_grundstellung`.
+    pub fn parse(
+        &self,
+        text: &str,
+        daily_grundstellung: &str,
+    ) -> Result<KeyedMessage, EnigmaError> {
+        let key_len = daily_grundstellung.len();
+        match self {
+            Procedure::DoubledIndicator => {
+                let letters = ungroup(text);
+                if letters.len() < 2 * key_len {
+                    return Err(EnigmaError::InvalidIndicator(
+                        "message is shorter than its indicator".to_string(),
+                    ));
+                }
+                let (encrypted_key, ciphertext) = letters.split_at(2 * key_len);
+                Ok(KeyedMessage {
+                    indicator: Indicator {
+                        grundstellung: daily_grundstellung.to_string(),
+                        encrypted_key: encrypted_key.to_string(),
+                    },
+                    ciphertext: ciphertext.to_string(),
+                })
+            }
+            Procedure::SingleIndicator => {
+                let mut lines = text.lines().skip_while(|line| line.trim().is_empty());
+                let header: Vec<&str> = lines
+                    .next()
+                    .map(|line| line.split_whitespace().collect())
+                    .unwrap_or_default();
+                match header.as_slice() {
+                    [grundstellung, encrypted_key]
+                        if grundstellung.len() == key_len && encrypted_key.len() == key_len =>
+                    {
+                        Ok(KeyedMessage {
+                            indicator: Indicator {
+                                grundstellung: grundstellung.to_string(),
+                                encrypted_key: encrypted_key.to_string(),
+                            },
+                            ciphertext: ungroup(&lines.collect::<Vec<_>>().join("\n")),
+                        })
+                    }
+                    _ => Err(EnigmaError::InvalidIndicator(format!(
+                        "expected a first line of two {} letter groups",
+                        key_len
+                    ))),
+                }
+            }
+        }
+    }
+}
+
+impl FromStr for Procedure {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "doubled" | "pre-1940" => Ok(Procedure::DoubledIndicator),
+            "single" | "post-1940" => Ok(Procedure::SingleIndicator),
+            _ => Err(format!(
+                "Unknown procedure: {} (expected doubled/pre-1940 or single/post-1940)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for Procedure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Procedure::DoubledIndicator => f.write_str("pre-1940"),
+            Procedure::SingleIndicator => f.write_str("post-1940"),
+        }
+    }
+}
+
+/// Picks `len` random letters, as an operator would for a message key or Grundstellung.
+pub fn random_key<R: RngCore + ?Sized>(rng: &mut R, len: usize) -> String {
+    (0..len)
+        .map(|_| (b'A' + rng.gen_range(0..26)) as char)
+        .collect()
+}
+
+fn encrypt_letters(machine: &mut EnigmaMachine, letters: &str) -> Result<String, EnigmaError> {
+    letters
+        .chars()
+        .map(|ch| {
+            machine
+                .encrypt_char(ch)
+                .ok_or(EnigmaError::InvalidCharacter(ch))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod procedure_tests {
+    use super::*;
+    use crate::plugboard::Plugboard;
+    use crate::reflector::reflectors;
+    use crate::rotor::rotors;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn daily_key() -> EnigmaMachine {
+        EnigmaMachine::new(
+            vec![
+                rotors::type_ii('A', 'B'),
+                rotors::type_iv('A', 'U'),
+                rotors::type_v('A', 'L'),
+            ],
+            reflectors::ukw_b(),
+            Plugboard::new(vec![('A', 'V'), ('B', 'S'), ('C', 'G'), ('D', 'L')]).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_doubled_indicator_round_trip() {
+        let procedure = Procedure::DoubledIndicator;
+        let sent = procedure
+            .encrypt(&mut daily_key(), "JEZ", "WXC", "KEINEBESONDERENEREIGNISSE")
+            .unwrap();
+        assert_eq!(sent.indicator.encrypted_key.len(), 6);
+
+        let text = procedure.format(&sent, GroupFormat::FIVE);
+        let received = procedure.parse(&text, "JEZ").unwrap();
+        assert_eq!(received, sent);
+        assert_eq!(
+            procedure.decrypt(&mut daily_key(), &received).unwrap(),
+            "KEINEBESONDERENEREIGNISSE"
+        );
+    }
+
+    #[test]
+    fn test_single_indicator_round_trip() {
+        let procedure = Procedure::SingleIndicator;
+        let sent = procedure
+            .encrypt(&mut daily_key(), "QWE", "BLA", "WETTERVORHERSAGE")
+            .unwrap();
+        let text = procedure.format(&sent, GroupFormat::FOUR);
+        assert!(text.starts_with("QWE "));
+
+        let received = procedure.parse(&text, "AAA").unwrap();
+        assert_eq!(
+            procedure.decrypt(&mut daily_key(), &received).unwrap(),
+            "WETTERVORHERSAGE"
+        );
+    }
+
+    #[test]
+    fn test_decrypt_indicator_rejects_garbled_doubling() {
+        let procedure = Procedure::DoubledIndicator;
+        let mut indicator = procedure
+            .encrypt_indicator(&mut daily_key(), "JEZ", "WXC")
+            .unwrap();
+        assert_eq!(
+            procedure.decrypt_indicator(&mut daily_key(), &indicator),
+            Ok("WXC".to_string())
+        );
+
+        let last = indicator.encrypted_key.pop().unwrap();
+        indicator
+            .encrypted_key
+            .push(if last == 'A' { 'B' } else { 'A' });
+        assert!(matches!(
+            procedure.decrypt_indicator(&mut daily_key(), &indicator),
+            Err(EnigmaError::InvalidIndicator(_))
+        ));
+    }
+
+    #[test]
+    fn test_encrypt_indicator_rejects_short_key_before_moving_rotors() {
+        let mut machine = daily_key();
+        assert_eq!(
+            Procedure::SingleIndicator.encrypt_indicator(&mut machine, "JEZ", "WX"),
+            Err(EnigmaError::PositionCount {
+                expected: 3,
+                found: 2
+            })
+        );
+        assert_eq!(machine.positions(), "AAA");
+    }
+
+    #[test]
+    fn test_parse_rejects_missing_header() {
+        assert!(Procedure::SingleIndicator
+            .parse("ABCDE FGHIJ", "AAA")
+            .is_err());
+        assert!(Procedure::DoubledIndicator.parse("ABC", "AAA").is_err());
+    }
+
+    #[test]
+    fn test_random_key() {
+        let key = random_key(&mut StdRng::seed_from_u64(1), 4);
+        assert_eq!(key.len(), 4);
+        assert!(key.chars().all(|ch| ch.is_ascii_uppercase()));
+    }
+
+    #[test]
+    fn test_random_key_with_seed() {
+        let key = |seed| random_key(&mut StdRng::seed_from_u64(seed), 3);
+        assert_eq!(key(1941), key(1941));
+    }
+}
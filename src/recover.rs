@@ -0,0 +1,394 @@
+//! Known-plaintext key recovery.
+//!
+//! Given a plaintext and its ciphertext, the plugboard need not be guessed wholesale. At a trial
+//! setting each key press of the unplugged machine is a permutation `S`, and the pair says that
+//! the plugboard partner of each ciphertext letter is `S` of the partner of its plaintext
+//! letter. Guessing one letter's partner therefore forces the partners of every letter it meets
+//! in the pair, and theirs in turn; a letter forced onto two partners, or onto a letter already
+//! taken, rules the guess out. Where propagation stops short of the whole pair, the next letter
+//! is guessed the same way, so every plugboard that fits is found rather than the first.
+//!
+//! Unlike the Bombe, which stops on a menu and leaves its stops to be tested, this returns full
+//! keys checked against every letter of the pair. Letters the pair never reaches are left free:
+//! any pairing among them fits as well.
+//!
+//! Rings are handled as in `crack`: when unknown, the rings of the two wheels that move during a
+//! message are swept and the slower wheels keep ring `A`. While a wheel stands still its ring
+//! trades against its start position, so each such key stands for 26 per slower wheel.
+
+use crate::bombe::Stop;
+use crate::crack::{parse_rings, swept_rings};
+use crate::error::EnigmaError;
+use crate::format::ungroup;
+use crate::model::Model;
+use crate::tables::{Stack, Stacks, Walk};
+use rayon::prelude::*;
+use std::fmt;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+const LETTERS: usize = 26;
+
+/// Most keys a search returns before giving up and asking for a longer pair.
+pub const LIMIT: usize = 1000;
+
+/// Plugboard partners found so far, by letter.
+type Plugs = [Option<u8>; LETTERS];
+
+/// A full key that turns the plaintext into the ciphertext.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Recovery {
+    /// The key; `steckers` lists plugged pairs only.
+    pub key: Stop,
+    /// Letters whose plugging the pair does not reach.
+    pub free: String,
+}
+
+impl fmt::Display for Recovery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.key)?;
+        if !self.free.is_empty() {
+            write!(f, " free {}", self.free)?;
+        }
+        Ok(())
+    }
+}
+
+/// Searches the keys under which a plaintext encrypts to a ciphertext.
+pub struct Recoverer {
+    /// Letters in the pair, so key presses to look up.
+    presses: usize,
+    /// Key presses each letter takes part in, with the letter on the other side.
+    links: Vec<Vec<(usize, u8)>>,
+    /// Letters of the pair, most linked first, in the order they are guessed.
+    letters: Vec<u8>,
+    limit: usize,
+}
+
+impl Recoverer {
+    pub fn new(plaintext: &str, ciphertext: &str) -> Result<Self, EnigmaError> {
+        let plaintext = indices(plaintext)?;
+        let ciphertext = indices(ciphertext)?;
+        if plaintext.is_empty() || plaintext.len() != ciphertext.len() {
+            return Err(EnigmaError::InvalidSearch(format!(
+                "{} letters of plaintext for {} of ciphertext",
+                plaintext.len(),
+                ciphertext.len()
+            )));
+        }
+        let mut links = vec![Vec::new(); LETTERS];
+        for (press, (&plain, &cipher)) in plaintext.iter().zip(&ciphertext).enumerate() {
+            links[plain as usize].push((press, cipher));
+            links[cipher as usize].push((press, plain));
+        }
+        let mut letters: Vec<u8> = (0..LETTERS as u8)
+            .filter(|&letter| !links[letter as usize].is_empty())
+            .collect();
+        letters.sort_by_key(|&letter| std::cmp::Reverse(links[letter as usize].len()));
+        Ok(Recoverer {
+            presses: plaintext.len(),
+            links,
+            letters,
+            limit: LIMIT,
+        })
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Most keys to return; a search that finds more fails instead.
+    pub fn set_limit(&mut self, limit: usize) {
+        self.limit = limit;
+    }
+
+    /// Every key of `model` that fits the pair, over all its wheel orders and reflectors.
+    pub fn recover(&self, model: Model, rings: Option<&str>) -> Result<Vec<Recovery>, EnigmaError> {
+        self.recover_orders(&model.wheel_orders(), model.reflectors(), rings)
+    }
+
+    /// Every key that fits the pair, over the given wheel orders (names as for
+    /// `rotors::from_name`) and reflectors, in parallel.
+    pub fn recover_orders(
+        &self,
+        orders: &[Vec<&str>],
+        reflector_names: &[&str],
+        rings: Option<&str>,
+    ) -> Result<Vec<Recovery>, EnigmaError> {
+        let stacks = Stacks::new(orders, reflector_names)?;
+        let ring_settings = match rings {
+            Some(rings) => vec![parse_rings(rings, stacks.wheel_count())?],
+            None => swept_rings(stacks.wheel_count()),
+        };
+        let jobs = stacks.jobs(&ring_settings);
+        let found = AtomicUsize::new(0);
+        let recoveries: Vec<Recovery> = jobs
+            .par_iter()
+            .flat_map_iter(|&(order, reflector, rings)| {
+                let wheels: Vec<String> = stacks.orders[order]
+                    .iter()
+                    .map(|rotor| rotor.model().to_string())
+                    .collect();
+                let reflector_model = stacks.reflectors[reflector].model();
+                self.sweep_positions(&stacks.tables[order][reflector], rings, &found)
+                    .into_iter()
+                    .map(move |(positions, plugs)| {
+                        recovery(&wheels, reflector_model, rings, &positions, &plugs)
+                    })
+            })
+            .collect();
+        if found.load(Ordering::Relaxed) > self.limit {
+            return Err(EnigmaError::InvalidSearch(format!(
+                "more than {} keys fit; a longer pair would narrow them down",
+                self.limit
+            )));
+        }
+        Ok(recoveries)
+    }
+
+    /// The start positions of one wheel order, reflector and ring setting that fit, each with
+    /// every plugboard that fits there.
+    fn sweep_positions(
+        &self,
+        stack: &Stack,
+        rings: &[u8],
+        found: &AtomicUsize,
+    ) -> Vec<(Vec<u8>, Plugs)> {
+        let wheels = stack.wheel_count();
+        let mut fits = Vec::new();
+        let mut walk = Walk::default();
+        let mut start = vec![0; wheels];
+        let mut positions = vec![0; wheels];
+        for setting in 0..LETTERS.pow(wheels as u32) {
+            if found.load(Ordering::Relaxed) > self.limit {
+                break;
+            }
+            let mut rest = setting;
+            for position in start.iter_mut().rev() {
+                *position = (rest % LETTERS) as u8;
+                rest /= LETTERS;
+            }
+            positions.copy_from_slice(&start);
+            stack.walk(rings, &mut positions, self.presses, &mut walk);
+            let mut solutions = Vec::new();
+            self.solve(
+                stack,
+                &walk,
+                [None; LETTERS],
+                Vec::new(),
+                &mut solutions,
+                found,
+            );
+            fits.extend(solutions.into_iter().map(|plugs| (start.clone(), plugs)));
+        }
+        fits
+    }
+
+    /// Propagates the partners in `queue`, then guesses the most linked open letter and
+    /// recurses; every complete fit is pushed to `solutions`.
+    fn solve(
+        &self,
+        stack: &Stack,
+        walk: &Walk,
+        mut plugs: Plugs,
+        mut queue: Vec<u8>,
+        solutions: &mut Vec<Plugs>,
+        found: &AtomicUsize,
+    ) {
+        if !self.propagate(stack, walk, &mut plugs, &mut queue) {
+            return;
+        }
+        let Some(&letter) = self
+            .letters
+            .iter()
+            .find(|&&letter| plugs[letter as usize].is_none())
+        else {
+            solutions.push(plugs);
+            found.fetch_add(1, Ordering::Relaxed);
+            return;
+        };
+        for partner in 0..LETTERS as u8 {
+            if found.load(Ordering::Relaxed) > self.limit {
+                return;
+            }
+            let mut guess = plugs;
+            let mut queue = Vec::new();
+            if assign(&mut guess, letter, partner, &mut queue) {
+                self.solve(stack, walk, guess, queue, solutions, found);
+            }
+        }
+    }
+
+    /// Follows every press from the letters in `queue`; false on a contradiction.
+    fn propagate(
+        &self,
+        stack: &Stack,
+        walk: &Walk,
+        plugs: &mut Plugs,
+        queue: &mut Vec<u8>,
+    ) -> bool {
+        while let Some(letter) = queue.pop() {
+            let Some(partner) = plugs[letter as usize] else {
+                continue;
+            };
+            for &(press, other) in &self.links[letter as usize] {
+                if !assign(plugs, other, stack.press(walk, press, partner), queue) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+}
+
+/// Plugs `letter` to `partner` unless either is taken otherwise; queues what changed.
+fn assign(plugs: &mut Plugs, letter: u8, partner: u8, queue: &mut Vec<u8>) -> bool {
+    match (plugs[letter as usize], plugs[partner as usize]) {
+        (Some(plugged), _) => plugged == partner,
+        (None, Some(_)) => false,
+        (None, None) => {
+            plugs[letter as usize] = Some(partner);
+            plugs[partner as usize] = Some(letter);
+            queue.push(letter);
+            if partner != letter {
+                queue.push(partner);
+            }
+            true
+        }
+    }
+}
+
+fn recovery(
+    wheels: &[String],
+    reflector: &str,
+    rings: &[u8],
+    positions: &[u8],
+    plugs: &Plugs,
+) -> Recovery {
+    let steckers = plugs
+        .iter()
+        .enumerate()
+        .filter_map(|(letter, &partner)| {
+            partner
+                .filter(|&partner| letter < partner as usize)
+                .map(|partner| (self::letter(letter as u8), self::letter(partner)))
+        })
+        .collect();
+    let free = plugs
+        .iter()
+        .enumerate()
+        .filter(|(_, partner)| partner.is_none())
+        .map(|(letter, _)| self::letter(letter as u8))
+        .collect();
+    Recovery {
+        key: Stop {
+            wheels: wheels.to_vec(),
+            reflector: reflector.to_string(),
+            rings: rings.iter().map(|&ring| letter(ring)).collect(),
+            positions: positions.iter().map(|&position| letter(position)).collect(),
+            steckers,
+        },
+        free,
+    }
+}
+
+fn indices(text: &str) -> Result<Vec<u8>, EnigmaError> {
+    ungroup(&text.to_uppercase())
+        .chars()
+        .map(|ch| {
+            ch.is_ascii_uppercase()
+                .then(|| ch as u8 - b'A')
+                .ok_or(EnigmaError::InvalidCharacter(ch))
+        })
+        .collect()
+}
+
+fn letter(index: u8) -> char {
+    (b'A' + index) as char
+}
+
+#[cfg(test)]
+mod recover_tests {
+    use super::*;
+    use crate::machine::EnigmaMachine;
+    use crate::plugboard::Plugboard;
+    use crate::reflector::reflectors;
+    use crate::rotor::rotors;
+
+    const PLAINTEXT: &str = "WETTERVORHERSAGEXBISKAYAXSTURMXAUSXWESTXSICHTXGERING";
+
+    fn machine(rings: &str, positions: &str, plugs: Vec<(char, char)>) -> EnigmaMachine {
+        let rotors = ["ii", "iv", "i"]
+            .iter()
+            .zip(rings.chars().zip(positions.chars()))
+            .filter_map(|(name, (ring, position))| rotors::from_name(name, position, ring))
+            .collect();
+        EnigmaMachine::new(rotors, reflectors::ukw_b(), Plugboard::new(plugs).unwrap())
+    }
+
+    fn encrypt(machine: &mut EnigmaMachine, text: &str) -> String {
+        text.chars()
+            .filter_map(|ch| machine.encrypt_char(ch))
+            .collect()
+    }
+
+    #[test]
+    fn test_recover_with_known_rings() {
+        let plugs = vec![
+            ('A', 'W'),
+            ('B', 'T'),
+            ('E', 'Q'),
+            ('R', 'X'),
+            ('S', 'Z'),
+            ('G', 'K'),
+        ];
+        let ciphertext = encrypt(&mut machine("MCK", "QVZ", plugs.clone()), PLAINTEXT);
+        let recoveries = Recoverer::new(PLAINTEXT, &ciphertext)
+            .unwrap()
+            .recover_orders(&[vec!["ii", "iv", "i"]], &["ukw_b"], Some("MCK"))
+            .unwrap();
+
+        assert!(!recoveries.is_empty());
+        let found = recoveries
+            .iter()
+            .find(|recovery| recovery.key.positions == "QVZ")
+            .unwrap();
+        assert_eq!(found.key.wheels, ["type II", "type IV", "type I"]);
+        for &(a, b) in &plugs {
+            assert!(found.key.steckers.contains(&(a, b)), "{}", found);
+        }
+        for recovery in &recoveries {
+            // Every key returned really fits, free letters left unplugged
+            let mut machine = machine(
+                &recovery.key.rings,
+                &recovery.key.positions,
+                recovery.key.steckers.clone(),
+            );
+            assert_eq!(recovery.key.wheels[0], "type II");
+            assert_eq!(encrypt(&mut machine, PLAINTEXT), ciphertext);
+        }
+        assert!(found
+            .to_string()
+            .starts_with("type II type IV type I UKW-B rings MCK positions QVZ steckers AW BT"));
+    }
+
+    #[test]
+    fn test_limit_and_invalid_pairs() {
+        let ciphertext = encrypt(&mut machine("AAA", "AAA", Vec::new()), "WETTER");
+        let mut recoverer = Recoverer::new("WETTER", &ciphertext).unwrap();
+        recoverer.set_limit(10);
+        assert!(recoverer
+            .recover_orders(&[vec!["ii", "iv", "i"]], &["ukw_b"], Some("AAA"))
+            .is_err());
+
+        assert!(Recoverer::new("WETTER", "ABC").is_err());
+        assert!(Recoverer::new("", "").is_err());
+        assert!(Recoverer::new("WETTER", "ABC1EF").is_err());
+        let recoverer = Recoverer::new("WETTER", &ciphertext).unwrap();
+        assert!(recoverer
+            .recover_orders(&[vec!["ii", "x", "i"]], &["ukw_b"], Some("AAA"))
+            .is_err());
+        assert!(recoverer
+            .recover_orders(&[vec!["ii", "iv", "i"]], &["ukw_b"], Some("AA"))
+            .is_err());
+    }
+}
@@ -1,5 +1,5 @@
 /// Represents the Reflector component of an Enigma machine.
-
+#[derive(Clone)]
 pub struct Reflector {
     wiring: String,
     model: String,
@@ -13,6 +13,16 @@ impl Reflector {
         }
     }
 
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
+    /// True if no letter reflects to itself, which is what keeps the machine from ever
+    /// encrypting a letter to itself.
+    pub fn is_fixed_point_free(&self) -> bool {
+        ('A'..='Z').all(|ch| self.encrypt(ch) != Some(ch))
+    }
+
     pub fn encrypt(&self, char_in: char) -> Option<char> {
         const ALPHABET: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
         ALPHABET
@@ -33,11 +43,52 @@ pub mod reflectors {
         Reflector::new("FVPJIAOYEDRZXWGCTKUQSBNMHL", "UKW-C")
     }
 
+    /// Thin B of the M4. Next to Beta at `A` with ring `A`, it wires like UKW-B.
+    pub fn ukw_b_thin() -> Reflector {
+        Reflector::new("ENKQAUYWJICOPBLMDXZVFTHRGS", "UKW-B thin")
+    }
+
+    /// Thin C of the M4. Next to Gamma at `A` with ring `A`, it wires like UKW-C.
+    pub fn ukw_c_thin() -> Reflector {
+        Reflector::new("RDOBJNTKVEHMLFCWZAXGYIPSUQ", "UKW-C thin")
+    }
+
+    /// The field-rewirable UKW-D. `J` and `Y` are always wired together; `pairs` must connect
+    /// the other 24 letters.
+    pub fn ukw_d(pairs: &[(char, char)]) -> Option<Reflector> {
+        let mut wiring: Vec<char> = "ABCDEFGHIJKLMNOPQRSTUVWXYZ".chars().collect();
+        let mut wired = [false; 26];
+        for &(a, b) in pairs.iter().chain(&[('J', 'Y')]) {
+            if !a.is_ascii_uppercase() || !b.is_ascii_uppercase() || a == b {
+                return None;
+            }
+            let (i, j) = ((a as u8 - b'A') as usize, (b as u8 - b'A') as usize);
+            if wired[i] || wired[j] {
+                return None;
+            }
+            wired[i] = true;
+            wired[j] = true;
+            wiring[i] = b;
+            wiring[j] = a;
+        }
+        if wired.contains(&false) {
+            return None;
+        }
+        Some(Reflector::new(&wiring.iter().collect::<String>(), "UKW-D"))
+    }
+
     pub fn from_name(name: &str) -> Reflector {
+        by_name(name).unwrap_or_else(|| panic!("Unknown reflector type: {}", name))
+    }
+
+    /// Like `from_name`, but returns `None` for an unknown name.
+    pub fn by_name(name: &str) -> Option<Reflector> {
         match name {
-            "ukw_b" => ukw_b(),
-            "ukw_c" => ukw_c(),
-            _ => panic!("Unknown reflector type: {}", name),
+            "ukw_b" => Some(ukw_b()),
+            "ukw_c" => Some(ukw_c()),
+            "ukw_b_thin" => Some(ukw_b_thin()),
+            "ukw_c_thin" => Some(ukw_c_thin()),
+            _ => None,
         }
     }
 }
@@ -66,6 +117,13 @@ mod reflector_tests {
         }
     }
 
+    #[test]
+    fn test_is_fixed_point_free() {
+        assert!(reflectors::ukw_b().is_fixed_point_free());
+        assert!(reflectors::ukw_c_thin().is_fixed_point_free());
+        assert!(!Reflector::new("ABCDEFGHIJKLMNOPQRSTUVWXYZ", "identity").is_fixed_point_free());
+    }
+
     #[test]
     fn test_reflector_from_name() {
         let reflector_b = reflectors::from_name("ukw_b");
@@ -77,6 +135,26 @@ mod reflector_tests {
         assert_eq!(reflector_c.encrypt('A'), Some('F'));
     }
 
+    #[test]
+    fn test_ukw_d_is_reciprocal() {
+        let pairs: Vec<(char, char)> = "ABCDEFGHIKLMNOPQRSTUVWXZ"
+            .chars()
+            .collect::<Vec<_>>()
+            .chunks(2)
+            .map(|pair| (pair[0], pair[1]))
+            .collect();
+        let reflector = reflectors::ukw_d(&pairs).unwrap();
+        assert_eq!(reflector.encrypt('J'), Some('Y'));
+        for c in "ABCDEFGHIJKLMNOPQRSTUVWXYZ".chars() {
+            let reflected = reflector.encrypt(c).unwrap();
+            assert_ne!(reflected, c);
+            assert_eq!(reflector.encrypt(reflected), Some(c));
+        }
+
+        assert!(reflectors::ukw_d(&pairs[1..]).is_none());
+        assert!(reflectors::ukw_d(&[('J', 'A')]).is_none());
+    }
+
     #[test]
     #[should_panic(expected = "Unknown reflector type")]
     fn test_unknown_reflector() {
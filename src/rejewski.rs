@@ -0,0 +1,480 @@
+//! Rejewski's characteristics.
+//!
+//! Under the doubled indicator procedure every message of a day opens with its message key
+//! keyed twice at the same Grundstellung. The first and fourth indicator letters are the same
+//! key letter through the permutations of key presses 1 and 4, A and D. Both are involutions,
+//! so the first letter goes to the fourth under the product AD; likewise BE and CF. Some
+//! eighty indicators usually pin all three products down.
+//!
+//! The plugboard only conjugates the products, which keeps the lengths of their cycles. That
+//! *characteristic* therefore depends on the wheel order, rings and Grundstellung alone, and a
+//! catalogue of the characteristic of every wheel order and start position turns a day's
+//! indicators into a short list of candidate Grundstellungen.
+//!
+//! In this machine the signal passes the slower wheels before the fast one, so while they
+//! stand still they sit between plugboard and fast wheel and conjugate the products just as
+//! the plugboard does. The characteristic then only tells the fast wheel's setting, and
+//! whether a turnover falls inside the indicator: every Grundstellung comes with the 26^2
+//! settings of the slower wheels that share it, which the catalogue lists as candidates too.
+//!
+//! Since this machine adds the ring to the wiring offset, rings cannot be folded into start
+//! positions; a catalogue is built for one ring setting. Rotors are stepped exactly as the
+//! machine steps them, so a turnover inside the indicator is catalogued as it happens.
+
+use crate::error::EnigmaError;
+use crate::machine;
+use crate::model::Model;
+use crate::reflector::{reflectors, Reflector};
+use crate::rotor::{rotors, Rotor};
+use crate::scrambler::Scrambler;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fmt;
+
+const LETTERS: usize = 26;
+
+/// Cycle lengths of the products AD, BE, CF (one per rotor), each longest first.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Characteristic {
+    pub cycles: Vec<Vec<u8>>,
+}
+
+impl Characteristic {
+    /// Reads the products off a day's doubled indicators, which must all be keyed at the same
+    /// Grundstellung and determine every product completely.
+    pub fn from_indicators(indicators: &[&str]) -> Result<Self, EnigmaError> {
+        let key_len = indicators
+            .first()
+            .map_or(0, |indicator| indicator.len() / 2);
+        if key_len == 0 {
+            return Err(EnigmaError::InvalidIndicator(
+                "no doubled indicators".to_string(),
+            ));
+        }
+        let mut products = vec![[None; LETTERS]; key_len];
+        for indicator in indicators {
+            if indicator.len() != 2 * key_len {
+                return Err(EnigmaError::InvalidIndicator(format!(
+                    "{} is not a doubled {} letter key",
+                    indicator, key_len
+                )));
+            }
+            if let Some(invalid) = indicator.chars().find(|ch| !ch.is_ascii_uppercase()) {
+                return Err(EnigmaError::InvalidCharacter(invalid));
+            }
+            let letters = indicator.as_bytes();
+            for (product, mapping) in products.iter_mut().enumerate() {
+                let (from, to) = (letters[product] - b'A', letters[product + key_len] - b'A');
+                match mapping[from as usize] {
+                    Some(known) if known != to => {
+                        return Err(EnigmaError::InvalidIndicator(format!(
+                            "indicators disagree: product {} takes {} to both {} and {}",
+                            product + 1,
+                            (b'A' + from) as char,
+                            (b'A' + known) as char,
+                            (b'A' + to) as char
+                        )));
+                    }
+                    _ => mapping[from as usize] = Some(to),
+                }
+            }
+        }
+
+        let cycles = products
+            .iter()
+            .enumerate()
+            .map(|(product, mapping)| {
+                let known = mapping.iter().flatten().count();
+                let mut permutation = [0; LETTERS];
+                for (letter, image) in mapping.iter().enumerate() {
+                    match image {
+                        Some(image) => permutation[letter] = *image,
+                        None => {
+                            return Err(EnigmaError::InvalidIndicator(format!(
+                                "only {} of {} letters of product {} are known",
+                                known,
+                                LETTERS,
+                                product + 1
+                            )))
+                        }
+                    }
+                }
+                cycle_lengths(&permutation).ok_or_else(|| {
+                    EnigmaError::InvalidIndicator(format!(
+                        "product {} is not a permutation",
+                        product + 1
+                    ))
+                })
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Characteristic { cycles })
+    }
+
+    /// The characteristic of `rotors` (rings as set, positions ignored) keyed at
+    /// `grundstellung`, without a plugboard.
+    pub fn at(
+        rotors: &[Rotor],
+        reflector: &Reflector,
+        grundstellung: &str,
+    ) -> Result<Self, EnigmaError> {
+        let found = grundstellung.chars().count();
+        if found != rotors.len() {
+            return Err(EnigmaError::PositionCount {
+                expected: rotors.len(),
+                found,
+            });
+        }
+        if let Some(invalid) = grundstellung.chars().find(|ch| !ch.is_ascii_uppercase()) {
+            return Err(EnigmaError::InvalidPosition(invalid));
+        }
+        let mut stack = rotors.to_vec();
+        for (rotor, position) in stack.iter_mut().zip(grundstellung.chars()) {
+            rotor.set_position(position);
+        }
+        Ok(Self::of_stack(&mut stack, reflector, &mut Scrambler::new()))
+    }
+
+    /// Keys the doubled indicator on `stack` from its current positions.
+    fn of_stack(stack: &mut [Rotor], reflector: &Reflector, scrambler: &mut Scrambler) -> Self {
+        let key_len = stack.len();
+        let presses: Vec<[u8; LETTERS]> = (0..2 * key_len)
+            .map(|_| {
+                machine::step(stack);
+                let mut permutation = [0; LETTERS];
+                for (input, output) in permutation.iter_mut().enumerate() {
+                    *output = scrambler
+                        .scramble(stack, reflector, input as u8)
+                        .unwrap_or(input as u8);
+                }
+                permutation
+            })
+            .collect();
+        let cycles = (0..key_len)
+            .map(|product| {
+                let mut permutation = [0; LETTERS];
+                for (letter, image) in permutation.iter_mut().enumerate() {
+                    *image = presses[product + key_len][presses[product][letter] as usize];
+                }
+                cycle_lengths(&permutation).unwrap_or_default()
+            })
+            .collect();
+        Characteristic { cycles }
+    }
+}
+
+impl fmt::Display for Characteristic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let products: Vec<String> = self
+            .cycles
+            .iter()
+            .map(|lengths| {
+                let lengths: Vec<String> = lengths.iter().map(u8::to_string).collect();
+                format!("({})", lengths.join(" "))
+            })
+            .collect();
+        f.write_str(&products.join(" "))
+    }
+}
+
+/// A wheel order and Grundstellung from the catalogue.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CatalogueEntry {
+    /// Rotor models, leftmost first.
+    pub wheels: Vec<String>,
+    pub grundstellung: String,
+}
+
+impl fmt::Display for CatalogueEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} Grundstellung {}",
+            self.wheels.join(" "),
+            self.grundstellung
+        )
+    }
+}
+
+/// The characteristic of every start position of a set of wheel orders, at one ring setting
+/// and reflector.
+pub struct Catalogue {
+    wheels: Vec<Vec<String>>,
+    rings: String,
+    reflector: String,
+    /// Wheel order index and start position, by characteristic.
+    entries: HashMap<Characteristic, Vec<(usize, usize)>>,
+}
+
+impl Catalogue {
+    /// Catalogues every wheel order of `model`.
+    pub fn for_model(model: Model, rings: &str, reflector: &str) -> Result<Self, EnigmaError> {
+        Self::build(&model.wheel_orders(), rings, reflector)
+    }
+
+    /// Catalogues the given wheel orders (names as for `rotors::from_name`) in parallel.
+    pub fn build(orders: &[Vec<&str>], rings: &str, reflector: &str) -> Result<Self, EnigmaError> {
+        let reflector_name = reflector;
+        let reflector = reflectors::by_name(reflector).ok_or_else(|| {
+            EnigmaError::InvalidSearch(format!("unknown reflector {}", reflector))
+        })?;
+        let stacks = orders
+            .iter()
+            .map(|order| wheel_stack(order, rings))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let characteristics: Vec<Vec<Characteristic>> = stacks
+            .par_iter()
+            .map(|stack| {
+                let mut stack = stack.clone();
+                let mut scrambler = Scrambler::new();
+                (0..LETTERS.pow(stack.len() as u32))
+                    .map(|start| {
+                        let grundstellung = positions(start, stack.len());
+                        for (rotor, position) in stack.iter_mut().zip(grundstellung.chars()) {
+                            rotor.set_position(position);
+                        }
+                        Characteristic::of_stack(&mut stack, &reflector, &mut scrambler)
+                    })
+                    .collect()
+            })
+            .collect();
+
+        let mut entries: HashMap<Characteristic, Vec<(usize, usize)>> = HashMap::new();
+        for (order, characteristics) in characteristics.into_iter().enumerate() {
+            for (start, characteristic) in characteristics.into_iter().enumerate() {
+                entries
+                    .entry(characteristic)
+                    .or_default()
+                    .push((order, start));
+            }
+        }
+        Ok(Catalogue {
+            wheels: stacks
+                .iter()
+                .map(|stack| {
+                    stack
+                        .iter()
+                        .map(|rotor| rotor.model().to_string())
+                        .collect()
+                })
+                .collect(),
+            rings: rings.to_string(),
+            reflector: reflector_name.to_string(),
+            entries,
+        })
+    }
+
+    pub fn rings(&self) -> &str {
+        &self.rings
+    }
+
+    pub fn reflector(&self) -> &str {
+        &self.reflector
+    }
+
+    /// Number of start positions catalogued.
+    pub fn len(&self) -> usize {
+        self.entries.values().map(Vec::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Number of different characteristics among them.
+    pub fn characteristics(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Every wheel order and Grundstellung with this characteristic.
+    pub fn lookup(&self, characteristic: &Characteristic) -> Vec<CatalogueEntry> {
+        self.entries
+            .get(characteristic)
+            .map(|entries| {
+                entries
+                    .iter()
+                    .map(|&(order, start)| CatalogueEntry {
+                        wheels: self.wheels[order].clone(),
+                        grundstellung: positions(start, self.wheels[order].len()),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// The candidate wheel orders and Grundstellungen for a day's doubled indicators.
+    pub fn candidates(&self, indicators: &[&str]) -> Result<Vec<CatalogueEntry>, EnigmaError> {
+        Ok(self.lookup(&Characteristic::from_indicators(indicators)?))
+    }
+}
+
+/// Lengths of the cycles of `permutation`, longest first, or `None` if it is not one.
+fn cycle_lengths(permutation: &[u8; LETTERS]) -> Option<Vec<u8>> {
+    let mut seen = [false; LETTERS];
+    let mut lengths = Vec::new();
+    for start in 0..LETTERS {
+        let mut letter = start;
+        let mut length = 0;
+        while !seen[letter] {
+            seen[letter] = true;
+            letter = permutation[letter] as usize;
+            length += 1;
+        }
+        if length > 0 {
+            if letter != start {
+                return None;
+            }
+            lengths.push(length);
+        }
+    }
+    lengths.sort_unstable_by(|a, b| b.cmp(a));
+    Some(lengths)
+}
+
+fn wheel_stack(order: &[&str], rings: &str) -> Result<Vec<Rotor>, EnigmaError> {
+    if rings.chars().count() != order.len() {
+        return Err(EnigmaError::PositionCount {
+            expected: order.len(),
+            found: rings.chars().count(),
+        });
+    }
+    order
+        .iter()
+        .zip(rings.chars())
+        .map(|(name, ring)| {
+            if !ring.is_ascii_uppercase() {
+                return Err(EnigmaError::InvalidPosition(ring));
+            }
+            rotors::from_name(name, 'A', ring)
+                .ok_or_else(|| EnigmaError::InvalidSearch(format!("unknown wheel {}", name)))
+        })
+        .collect()
+}
+
+/// Window letters of start position number `start`, leftmost rotor most significant.
+fn positions(start: usize, rotors: usize) -> String {
+    let mut letters = vec!['A'; rotors];
+    let mut rest = start;
+    for letter in letters.iter_mut().rev() {
+        *letter = (b'A' + (rest % LETTERS) as u8) as char;
+        rest /= LETTERS;
+    }
+    letters.into_iter().collect()
+}
+
+#[cfg(test)]
+mod rejewski_tests {
+    use super::*;
+    use crate::machine::EnigmaMachine;
+    use crate::plugboard::Plugboard;
+    use crate::procedure::{random_key, Procedure};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const GRUNDSTELLUNG: &str = "KWT";
+
+    fn wheels() -> Vec<Rotor> {
+        vec![
+            rotors::type_iii('A', 'B'),
+            rotors::type_i('A', 'A'),
+            rotors::type_ii('A', 'F'),
+        ]
+    }
+
+    /// A day of doubled indicators, keyed through a plugboard.
+    fn indicators(count: usize) -> Vec<String> {
+        let plugboard = Plugboard::new(vec![
+            ('A', 'M'),
+            ('F', 'I'),
+            ('N', 'V'),
+            ('P', 'S'),
+            ('T', 'U'),
+        ])
+        .unwrap();
+        let mut machine = EnigmaMachine::new(wheels(), reflectors::ukw_b(), plugboard);
+        let mut rng = StdRng::seed_from_u64(1932);
+        (0..count)
+            .map(|_| {
+                let key = random_key(&mut rng, 3);
+                Procedure::DoubledIndicator
+                    .encrypt_indicator(&mut machine, GRUNDSTELLUNG, &key)
+                    .unwrap()
+                    .encrypted_key
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_characteristic_ignores_the_plugboard() {
+        let indicators = indicators(200);
+        let indicators: Vec<&str> = indicators.iter().map(String::as_str).collect();
+        let from_indicators = Characteristic::from_indicators(&indicators).unwrap();
+        let at = Characteristic::at(&wheels(), &reflectors::ukw_b(), GRUNDSTELLUNG).unwrap();
+        assert_eq!(from_indicators, at);
+        for lengths in &at.cycles {
+            assert_eq!(
+                lengths.iter().map(|&length| length as usize).sum::<usize>(),
+                26
+            );
+            // Cycles of a product of two involutions without fixed points come in pairs
+            for pair in lengths.chunks(2) {
+                assert_eq!(pair[0], pair[1]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_catalogue_finds_the_grundstellung() {
+        let catalogue = Catalogue::build(&[vec!["iii", "i", "ii"]], "BAF", "ukw_b").unwrap();
+        assert_eq!(catalogue.len(), 26 * 26 * 26);
+        assert!(catalogue.characteristics() > 26);
+
+        let indicators = indicators(200);
+        let indicators: Vec<&str> = indicators.iter().map(String::as_str).collect();
+        let candidates = catalogue.candidates(&indicators).unwrap();
+        assert!(candidates.len() < catalogue.len() / 10);
+        assert!(candidates.contains(&CatalogueEntry {
+            wheels: vec![
+                "type III".to_string(),
+                "type I".to_string(),
+                "type II".to_string()
+            ],
+            grundstellung: GRUNDSTELLUNG.to_string(),
+        }));
+    }
+
+    #[test]
+    fn test_invalid_indicators() {
+        let indicators = indicators(200);
+        let indicators: Vec<&str> = indicators.iter().map(String::as_str).collect();
+        assert!(matches!(
+            Characteristic::from_indicators(&indicators[..10]),
+            Err(EnigmaError::InvalidIndicator(reason)) if reason.starts_with("only")
+        ));
+        assert!(Characteristic::from_indicators(&["ABCDE"]).is_err());
+        assert!(matches!(
+            Characteristic::from_indicators(&["ABCDEF", "ABCXEF"]),
+            Err(EnigmaError::InvalidIndicator(reason)) if reason.starts_with("indicators disagree")
+        ));
+        assert_eq!(
+            Characteristic::from_indicators(&["ABC1EF"]),
+            Err(EnigmaError::InvalidCharacter('1'))
+        );
+        assert!(Catalogue::build(&[vec!["iii", "x", "ii"]], "AAA", "ukw_b").is_err());
+    }
+
+    #[test]
+    fn test_display() {
+        let characteristic = Characteristic {
+            cycles: vec![
+                vec![13, 13],
+                vec![10, 10, 2, 2, 1, 1],
+                vec![4, 4, 3, 3, 3, 3, 2, 2, 1, 1],
+            ],
+        };
+        assert_eq!(
+            characteristic.to_string(),
+            "(13 13) (10 10 2 2 1 1) (4 4 3 3 3 3 2 2 1 1)"
+        );
+    }
+}
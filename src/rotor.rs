@@ -5,7 +5,8 @@
 /// As characters are encrypted, the rotor rotates, changing the current position (`position`) and thus the
 /// transformation it applies.
 ///
-/// The `notch` indicates at which position the rotor will cause the next rotor to its left to turn.
+/// The `notches` indicate at which positions the rotor will cause the next rotor to its left to
+/// turn; rotors VI-VIII have two. The Greek wheels of the M4 (Beta and Gamma) never step.
 /// The `ring` is a static setting that affects the rotor's behavior but doesn't move during encryption.
 /// Different rotor models (`model`) have different wiring patterns and notch positions.
 ///
@@ -17,10 +18,12 @@
 /// let rotor = rotors::type_i('A', 'A');
 /// let encrypted_char = rotor.pass_through_forward('A').unwrap();
 /// ```
+#[derive(Clone)]
 pub struct Rotor {
     letter_roll: String,
     position: char,
-    notch: char,
+    notches: Vec<char>,
+    stepping: bool,
     ring: char,
     model: String,
 }
@@ -32,12 +35,33 @@ impl Rotor {
         Rotor {
             letter_roll: letter_roll.to_string(),
             position,
-            notch,
+            notches: vec![notch],
+            stepping: true,
             ring,
             model: model.to_string(),
         }
     }
 
+    pub fn position(&self) -> char {
+        self.position
+    }
+
+    pub(crate) fn set_position(&mut self, position: char) {
+        self.position = position;
+    }
+
+    pub fn ring(&self) -> char {
+        self.ring
+    }
+
+    pub(crate) fn set_ring(&mut self, ring: char) {
+        self.ring = ring;
+    }
+
+    pub fn model(&self) -> &str {
+        &self.model
+    }
+
     fn position_of(c: char) -> Option<usize> {
         Self::ALPHABET.chars().position(|x| x == c)
     }
@@ -48,6 +72,9 @@ impl Rotor {
     }
 
     pub fn turn(&mut self) -> bool {
+        if !self.stepping {
+            return false;
+        }
         let current_pos = self
             .letter_roll
             .chars()
@@ -55,7 +82,7 @@ impl Rotor {
             .unwrap();
         let next_pos = (current_pos + 1) % self.letter_roll.len();
         self.position = self.letter_roll.chars().nth(next_pos).unwrap();
-        self.position == self.notch
+        self.notches.contains(&self.position)
     }
 
     pub fn pass_through_forward(&self, c: char) -> Option<char> {
@@ -67,7 +94,7 @@ impl Rotor {
 
     pub fn pass_through_reverse(&self, c: char) -> Option<char> {
         let letter_pos = self.letter_roll.chars().position(|x| x == c).unwrap();
-        let offset_letter_pos = (letter_pos + Self::ALPHABET.len()
+        let offset_letter_pos = (letter_pos + 2 * Self::ALPHABET.len()
             - Self::position_of(self.position).unwrap()
             - Self::position_of(self.ring).unwrap())
             % Self::ALPHABET.len();
@@ -82,7 +109,6 @@ pub mod rotors {
     /**
     For example, type_i maps E->A, K->B etc ...
     **/
-
     pub fn type_i(p: char, r: char) -> Rotor {
         Rotor::new("EKMFLGDQVZNTOWYHXUSPAIBRCJ", p, 'R', "type I", r)
     }
@@ -102,6 +128,63 @@ pub mod rotors {
     pub fn type_v(p: char, r: char) -> Rotor {
         Rotor::new("VZBRGITYUPSDNHLXAWMJQOFECK", p, 'A', "type V", r)
     }
+
+    pub fn type_vi(p: char, r: char) -> Rotor {
+        Rotor {
+            notches: vec!['Z', 'M'],
+            ..Rotor::new("JPGVOUMFYQBENHZRDKASXLICTW", p, 'Z', "type VI", r)
+        }
+    }
+
+    pub fn type_vii(p: char, r: char) -> Rotor {
+        Rotor {
+            notches: vec!['Z', 'M'],
+            ..Rotor::new("NZJHGRCXMYSWBOUFAIVLPEKQDT", p, 'Z', "type VII", r)
+        }
+    }
+
+    pub fn type_viii(p: char, r: char) -> Rotor {
+        Rotor {
+            notches: vec!['Z', 'M'],
+            ..Rotor::new("FKQHTLXOCBJSPDZRAMEWNIUYGV", p, 'Z', "type VIII", r)
+        }
+    }
+
+    /// Greek wheel of the M4; it never steps.
+    pub fn beta(p: char, r: char) -> Rotor {
+        Rotor {
+            notches: Vec::new(),
+            stepping: false,
+            ..Rotor::new("LEYJVCNIXWPBQMDRTAKZGFUHOS", p, 'A', "Beta", r)
+        }
+    }
+
+    /// Greek wheel of the M4; it never steps.
+    pub fn gamma(p: char, r: char) -> Rotor {
+        Rotor {
+            notches: Vec::new(),
+            stepping: false,
+            ..Rotor::new("FSOKANUERHMBTIYCWLQPZXVGJD", p, 'A', "Gamma", r)
+        }
+    }
+
+    /// Looks a rotor up by name, either `type_vi` or just `vi`, `beta` or `gamma`.
+    pub fn from_name(name: &str, p: char, r: char) -> Option<Rotor> {
+        let rotor = match name.strip_prefix("type_").unwrap_or(name) {
+            "i" => type_i,
+            "ii" => type_ii,
+            "iii" => type_iii,
+            "iv" => type_iv,
+            "v" => type_v,
+            "vi" => type_vi,
+            "vii" => type_vii,
+            "viii" => type_viii,
+            "beta" => beta,
+            "gamma" => gamma,
+            _ => return None,
+        };
+        Some(rotor(p, r))
+    }
 }
 
 #[cfg(test)]
@@ -126,14 +209,14 @@ mod rotor_tests {
     #[test]
     fn test_turn() {
         let mut rotor = rotors::type_i('A', 'A');
-        assert_eq!(rotor.turn(), false);
+        assert!(!rotor.turn());
         assert_eq!(rotor.position, 'I');
     }
 
     #[test]
     fn test_turn_with_notch() {
         let mut rotor = rotors::type_i('B', 'A');
-        assert_eq!(rotor.turn(), true);
+        assert!(rotor.turn());
         assert_eq!(rotor.position, 'R');
     }
 
@@ -151,6 +234,46 @@ mod rotor_tests {
         assert_eq!(rotor.pass_through_reverse('K'), Some('B'));
     }
 
+    #[test]
+    fn test_pass_through_reverse_with_ring() {
+        let rotor = rotors::type_i('Z', 'Q');
+        for c in Rotor::ALPHABET.chars() {
+            let forward = rotor.pass_through_forward(c).unwrap();
+            assert_eq!(rotor.pass_through_reverse(forward), Some(c));
+        }
+    }
+
+    #[test]
+    fn test_double_notch() {
+        let mut rotor = rotors::type_vi('A', 'A');
+        let carries = (0..26).filter(|_| rotor.turn()).count();
+        assert_eq!(carries, 2);
+    }
+
+    #[test]
+    fn test_greek_wheel_never_steps() {
+        let mut rotor = rotors::beta('C', 'A');
+        assert!(!rotor.turn());
+        assert_eq!(rotor.position(), 'C');
+    }
+
+    #[test]
+    fn test_from_name() {
+        assert_eq!(
+            rotors::from_name("type_iv", 'A', 'A').unwrap().model(),
+            "type IV"
+        );
+        assert_eq!(
+            rotors::from_name("viii", 'A', 'A').unwrap().model(),
+            "type VIII"
+        );
+        assert_eq!(
+            rotors::from_name("gamma", 'A', 'A').unwrap().model(),
+            "Gamma"
+        );
+        assert!(rotors::from_name("ix", 'A', 'A').is_none());
+    }
+
     #[test]
     fn test_rotor_types() {
         let rotor = rotors::type_ii('A', 'A');
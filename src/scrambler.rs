@@ -0,0 +1,225 @@
+use crate::reflector::Reflector;
+use crate::rotor::Rotor;
+
+const ALPHABET_LEN: usize = 26;
+
+type Permutation = [u8; ALPHABET_LEN];
+
+/// Cached, composed permutations for the rotor + reflector stack.
+///
+/// The signal enters `rotors[0]` and the rightmost rotor (the one stepped on every key press)
+/// sits next to the reflector. Between carries the slower rotors form a fixed permutation, so
+/// the stack splits into:
+///
+/// - an *outer* permutation through every rotor but the fast one, recomputed only when one of
+///   those rotors moves, and
+/// - a *reflected* permutation (fast rotor, reflector, fast rotor back) for each of the fast
+///   rotor's 26 positions, computed once.
+///
+/// Encrypting a character is then three table lookups instead of a walk through every rotor.
+///
+/// The tables only follow the window letters. Whoever changes a wiring, a ring or the reflector
+/// of the stack it is used with calls [`Scrambler::invalidate`] before the next key press.
+pub(crate) struct Scrambler {
+    reflected: Option<Vec<Permutation>>,
+    outer: Option<Outer>,
+}
+
+struct Outer {
+    positions: Vec<char>,
+    forward: Permutation,
+    reverse: Permutation,
+}
+
+impl Scrambler {
+    pub(crate) fn new() -> Self {
+        Scrambler {
+            reflected: None,
+            outer: None,
+        }
+    }
+
+    /// Drops every cached table, so the next key press rebuilds them from the stack it is given.
+    pub(crate) fn invalidate(&mut self) {
+        self.reflected = None;
+        self.outer = None;
+    }
+
+    /// Sends `input` (0..26) through the rotors, the reflector and back for the current rotor
+    /// positions.
+    pub(crate) fn scramble(
+        &mut self,
+        rotors: &[Rotor],
+        reflector: &Reflector,
+        input: u8,
+    ) -> Option<u8> {
+        let (fast, slow) = match rotors.split_last() {
+            Some(split) => split,
+            None => {
+                return reflector.encrypt(letter(input)).map(|ch| ch as u8 - b'A');
+            }
+        };
+
+        if self.reflected.is_none() {
+            self.reflected = Some(Self::build_reflected(fast, reflector)?);
+        }
+        if !self
+            .outer
+            .as_ref()
+            .is_some_and(|outer| same_positions(&outer.positions, slow))
+        {
+            self.outer = Some(Self::build_outer(slow)?);
+        }
+
+        let outer = self.outer.as_ref()?;
+        let reflected = self.reflected.as_ref()?;
+        let fast_index = (fast.position() as u8).checked_sub(b'A')? as usize;
+
+        let entered = outer.forward[input as usize];
+        let returned = reflected.get(fast_index)?[entered as usize];
+        Some(outer.reverse[returned as usize])
+    }
+
+    fn build_reflected(fast: &Rotor, reflector: &Reflector) -> Option<Vec<Permutation>> {
+        let mut probe = fast.clone();
+        (0..ALPHABET_LEN as u8)
+            .map(|position| {
+                probe.set_position(letter(position));
+                let mut table = [0; ALPHABET_LEN];
+                for (input, slot) in table.iter_mut().enumerate() {
+                    let ch = probe.pass_through_forward(letter(input as u8))?;
+                    let ch = reflector.encrypt(ch)?;
+                    *slot = probe.pass_through_reverse(ch)? as u8 - b'A';
+                }
+                Some(table)
+            })
+            .collect()
+    }
+
+    fn build_outer(slow: &[Rotor]) -> Option<Outer> {
+        let mut forward = [0; ALPHABET_LEN];
+        let mut reverse = [0; ALPHABET_LEN];
+        for (input, slot) in forward.iter_mut().enumerate() {
+            let ch = slow.iter().try_fold(letter(input as u8), |ch, rotor| {
+                rotor.pass_through_forward(ch)
+            })?;
+            *slot = ch as u8 - b'A';
+            reverse[*slot as usize] = input as u8;
+        }
+        Some(Outer {
+            positions: slow.iter().map(Rotor::position).collect(),
+            forward,
+            reverse,
+        })
+    }
+}
+
+fn letter(index: u8) -> char {
+    (b'A' + index) as char
+}
+
+fn same_positions(cached: &[char], rotors: &[Rotor]) -> bool {
+    cached.len() == rotors.len()
+        && cached
+            .iter()
+            .zip(rotors)
+            .all(|(position, rotor)| *position == rotor.position())
+}
+
+#[cfg(test)]
+mod scrambler_tests {
+    use super::*;
+    use crate::reflector::reflectors;
+    use crate::rotor::rotors;
+
+    fn sequential(rotors: &[Rotor], reflector: &Reflector, input: u8) -> Option<u8> {
+        let ch = rotors
+            .iter()
+            .try_fold(letter(input), |ch, rotor| rotor.pass_through_forward(ch))?;
+        let ch = reflector.encrypt(ch)?;
+        let ch = rotors
+            .iter()
+            .rev()
+            .try_fold(ch, |ch, rotor| rotor.pass_through_reverse(ch))?;
+        Some(ch as u8 - b'A')
+    }
+
+    #[test]
+    fn test_scramble_matches_sequential_path() {
+        let mut stack = vec![
+            rotors::type_i('Q', 'C'),
+            rotors::type_ii('E', 'A'),
+            rotors::type_v('Z', 'M'),
+        ];
+        let reflector = reflectors::ukw_c();
+        let mut scrambler = Scrambler::new();
+
+        for step in 0..60u8 {
+            stack[2].set_position(letter(step % 26));
+            if step % 26 == 0 {
+                stack[1].set_position(letter(step / 26));
+            }
+            for input in 0..26 {
+                assert_eq!(
+                    scrambler.scramble(&stack, &reflector, input),
+                    sequential(&stack, &reflector, input)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_invalidate_follows_changed_rings_and_reflector() {
+        let mut stack = vec![
+            rotors::type_iii('K', 'B'),
+            rotors::type_i('D', 'A'),
+            rotors::type_iv('R', 'F'),
+        ];
+        let mut reflector = reflectors::ukw_b();
+        let mut scrambler = Scrambler::new();
+
+        for change in 0..4 {
+            match change {
+                1 => stack[2].set_ring('M'),
+                2 => stack[0].set_ring('Z'),
+                3 => reflector = reflectors::ukw_c(),
+                _ => {}
+            }
+            scrambler.invalidate();
+            for input in 0..26 {
+                assert_eq!(
+                    scrambler.scramble(&stack, &reflector, input),
+                    sequential(&stack, &reflector, input),
+                    "after change {}",
+                    change
+                );
+            }
+        }
+        stack[2] = rotors::type_v('R', 'M');
+        scrambler.invalidate();
+        assert_eq!(
+            scrambler.scramble(&stack, &reflector, 7),
+            sequential(&stack, &reflector, 7)
+        );
+    }
+
+    #[test]
+    fn test_scramble_is_an_involution() {
+        let stack = vec![rotors::type_iii('K', 'B'), rotors::type_iv('D', 'A')];
+        let reflector = reflectors::ukw_b();
+        let mut scrambler = Scrambler::new();
+
+        for input in 0..26 {
+            let output = scrambler.scramble(&stack, &reflector, input).unwrap();
+            assert_ne!(output, input);
+            assert_eq!(scrambler.scramble(&stack, &reflector, output), Some(input));
+        }
+    }
+
+    #[test]
+    fn test_scramble_without_rotors() {
+        let reflector = reflectors::ukw_b();
+        let mut scrambler = Scrambler::new();
+        assert_eq!(scrambler.scramble(&[], &reflector, 0), Some(b'Y' - b'A'));
+    }
+}
@@ -0,0 +1,301 @@
+use crate::error::EnigmaError;
+use crate::machine::EnigmaMachine;
+use crate::policy::NonAlphaPolicy;
+use std::io::{self, Read, Write};
+use std::ops::Range;
+
+/// Wraps any `Read` and en/decrypts everything read through it.
+///
+/// The machine's rotor state carries over between reads, so the output is the same however the
+/// underlying stream happens to be chunked. Bytes without a key follow the machine's
+/// `NonAlphaPolicy`, so the output may be shorter or longer than the input. A byte the policy
+/// rejects fails one read, after everything before it; reading on carries on behind it.
+///
+/// # Example
+///
+/// ```rust
+/// use enigma_shark::{reflectors, rotors, EnigmaMachine, EnigmaReader, Plugboard};
+/// use std::io::Read;
+///
+/// let machine = EnigmaMachine::new(
+///     vec![rotors::type_i('A', 'A'), rotors::type_ii('B', 'A'), rotors::type_iii('C', 'A')],
+///     reflectors::ukw_b(),
+///     Plugboard::new(vec![]).unwrap(),
+/// );
+/// let mut reader = EnigmaReader::new("HELLO".as_bytes(), machine);
+///
+/// let mut encrypted = String::new();
+/// reader.read_to_string(&mut encrypted).unwrap();
+/// assert_eq!(encrypted.len(), 5);
+/// ```
+pub struct EnigmaReader<R: Read> {
+    inner: R,
+    machine: EnigmaMachine,
+    scratch: Vec<u8>,
+    /// The part of `scratch` read but not encrypted yet.
+    unread: Range<usize>,
+    pending: Vec<u8>,
+    consumed: usize,
+    pending_error: Option<io::Error>,
+}
+
+impl<R: Read> EnigmaReader<R> {
+    pub fn new(inner: R, machine: EnigmaMachine) -> Self {
+        EnigmaReader {
+            inner,
+            machine,
+            scratch: vec![0; 4096],
+            unread: 0..0,
+            pending: Vec::new(),
+            consumed: 0,
+            pending_error: None,
+        }
+    }
+
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    pub fn machine(&self) -> &EnigmaMachine {
+        &self.machine
+    }
+
+    /// Returns the underlying reader and the machine in its current rotor state.
+    pub fn into_parts(self) -> (R, EnigmaMachine) {
+        (self.inner, self.machine)
+    }
+}
+
+impl<R: Read> Read for EnigmaReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            if self.consumed < self.pending.len() {
+                let available = &self.pending[self.consumed..];
+                let len = available.len().min(buf.len());
+                buf[..len].copy_from_slice(&available[..len]);
+                self.consumed += len;
+                return Ok(len);
+            }
+            // Hand back what was encrypted before a bad byte first; the error surfaces after
+            if let Some(err) = self.pending_error.take() {
+                return Err(err);
+            }
+
+            if self.unread.is_empty() {
+                let len = self.inner.read(&mut self.scratch)?;
+                if len == 0 {
+                    return Ok(0);
+                }
+                self.unread = 0..len;
+            }
+            self.pending.clear();
+            self.consumed = 0;
+            match encrypt_into(
+                &mut self.machine,
+                &self.scratch[self.unread.clone()],
+                &mut self.pending,
+            ) {
+                Ok(()) => self.unread.start = self.unread.end,
+                // Reading on after the error picks up behind the bad byte
+                Err((valid, err)) => {
+                    self.unread.start += valid + 1;
+                    self.pending_error = Some(err);
+                }
+            }
+        }
+    }
+}
+
+/// Wraps any `Write` and en/decrypts everything written through it before passing it on.
+///
+/// Like `EnigmaReader`, the rotor state carries over between writes.
+pub struct EnigmaWriter<W: Write> {
+    inner: W,
+    machine: EnigmaMachine,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> EnigmaWriter<W> {
+    pub fn new(inner: W, machine: EnigmaMachine) -> Self {
+        EnigmaWriter {
+            inner,
+            machine,
+            buffer: Vec::new(),
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    pub fn machine(&self) -> &EnigmaMachine {
+        &self.machine
+    }
+
+    /// Returns the underlying writer and the machine in its current rotor state.
+    pub fn into_parts(self) -> (W, EnigmaMachine) {
+        (self.inner, self.machine)
+    }
+}
+
+impl<W: Write> Write for EnigmaWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.clear();
+        let valid = match encrypt_into(&mut self.machine, buf, &mut self.buffer) {
+            Ok(()) => buf.len(),
+            Err((0, err)) => return Err(err),
+            // The bad byte is left unconsumed, so the caller's next write reports it
+            Err((valid, _)) => valid,
+        };
+        self.inner.write_all(&self.buffer)?;
+        Ok(valid)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Encrypts `bytes` onto the end of `out`, stopping at the first byte the machine rejects.
+///
+/// Non-ASCII bytes can only be skipped or passed through as-is. On failure returns how many
+/// leading bytes of `bytes` were consumed along with the error.
+pub(crate) fn encrypt_into(
+    machine: &mut EnigmaMachine,
+    bytes: &[u8],
+    out: &mut Vec<u8>,
+) -> Result<(), (usize, io::Error)> {
+    for (index, &byte) in bytes.iter().enumerate() {
+        let result = if byte.is_ascii() {
+            machine.process_char(byte as char, |ch| out.push(ch as u8))
+        } else {
+            match machine.non_alpha_policy() {
+                NonAlphaPolicy::Skip => Ok(()),
+                NonAlphaPolicy::PassThrough => {
+                    out.push(byte);
+                    Ok(())
+                }
+                _ => Err(EnigmaError::InvalidCharacter(byte as char)),
+            }
+        };
+        if let Err(err) = result {
+            return Err((index, io::Error::new(io::ErrorKind::InvalidData, err)));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod stream_tests {
+    use super::*;
+    use crate::plugboard::Plugboard;
+    use crate::reflector::reflectors;
+    use crate::rotor::rotors;
+
+    fn setup_enigma_machine() -> EnigmaMachine {
+        EnigmaMachine::new(
+            vec![
+                rotors::type_i('A', 'A'),
+                rotors::type_ii('B', 'A'),
+                rotors::type_iii('C', 'A'),
+            ],
+            reflectors::ukw_b(),
+            Plugboard::new(vec![]).unwrap(),
+        )
+    }
+
+    /// A reader that hands out at most `chunk` bytes per call.
+    struct Trickle<'a> {
+        data: &'a [u8],
+        chunk: usize,
+    }
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = self.chunk.min(buf.len()).min(self.data.len());
+            buf[..len].copy_from_slice(&self.data[..len]);
+            self.data = &self.data[len..];
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_reader_matches_encrypt_message_across_chunks() {
+        let message = "WETTERVORHERSAGEBISKAYA".repeat(20);
+        let expected = setup_enigma_machine().encrypt_message(&message).unwrap();
+
+        let trickle = Trickle {
+            data: message.as_bytes(),
+            chunk: 7,
+        };
+        let mut reader = EnigmaReader::new(trickle, setup_enigma_machine());
+        let mut encrypted = String::new();
+        reader.read_to_string(&mut encrypted).unwrap();
+
+        assert_eq!(encrypted, expected);
+    }
+
+    #[test]
+    fn test_writer_round_trips_through_io_copy() {
+        let message = "KEINEBESONDERENEREIGNISSE";
+
+        let mut writer = EnigmaWriter::new(Vec::new(), setup_enigma_machine());
+        io::copy(&mut message.as_bytes(), &mut writer).unwrap();
+        let (encrypted, _) = writer.into_parts();
+
+        let mut reader = EnigmaReader::new(encrypted.as_slice(), setup_enigma_machine());
+        let mut decrypted = String::new();
+        reader.read_to_string(&mut decrypted).unwrap();
+
+        assert_eq!(decrypted, message);
+    }
+
+    #[test]
+    fn test_reader_returns_valid_prefix_before_error() {
+        let mut reader = EnigmaReader::new("ABC9DEF".as_bytes(), setup_enigma_machine());
+        let mut buf = [0; 16];
+
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+        let err = reader.read(&mut buf).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(err.to_string(), "Failed to encrypt character: '9'");
+
+        // The rest of the chunk is still there, behind the bad byte
+        let mut rest = String::new();
+        reader.read_to_string(&mut rest).unwrap();
+        let expected = setup_enigma_machine().encrypt_message("ABCDEF").unwrap();
+        assert_eq!(rest, expected[3..]);
+    }
+
+    #[test]
+    fn test_reader_substitutes_non_alpha() {
+        let mut machine = setup_enigma_machine();
+        machine.set_non_alpha_policy(NonAlphaPolicy::Substitute);
+        let mut reader = EnigmaReader::new("U 2".as_bytes(), machine);
+        let mut encrypted = String::new();
+        reader.read_to_string(&mut encrypted).unwrap();
+
+        let expected = setup_enigma_machine().encrypt_message("UXZWO").unwrap();
+        assert_eq!(encrypted, expected);
+    }
+
+    #[test]
+    fn test_writer_passes_through_non_ascii() {
+        let mut machine = setup_enigma_machine();
+        machine.set_non_alpha_policy(NonAlphaPolicy::PassThrough);
+        let mut writer = EnigmaWriter::new(Vec::new(), machine);
+        writer.write_all("AÄ\n".as_bytes()).unwrap();
+
+        let written = String::from_utf8(writer.get_ref().clone()).unwrap();
+        assert!(written.ends_with("Ä\n"));
+    }
+
+    #[test]
+    fn test_writer_stops_at_invalid_byte() {
+        let mut writer = EnigmaWriter::new(Vec::new(), setup_enigma_machine());
+
+        assert_eq!(writer.write(b"AB!CD").unwrap(), 2);
+        assert!(writer.write(b"!CD").is_err());
+        assert_eq!(writer.get_ref().len(), 2);
+    }
+}
@@ -0,0 +1,447 @@
+//! Rotor stacks as lookup tables.
+//!
+//! The searches try the same wheel order at far more settings than the cached `Scrambler` is
+//! meant for, and need the rings to change between trials. Here every wheel is tabulated once
+//! at each of its 26 wiring offsets (window letter plus ring), together with where a turn takes
+//! its window and whether it carries, so a trial at any rings and positions is table lookups.
+
+use crate::error::EnigmaError;
+use crate::reflector::{reflectors, Reflector};
+use crate::rotor::{rotors, Rotor};
+
+const LETTERS: usize = 26;
+
+/// A wheel as lookup tables, indexed by wiring offset (window letter plus ring).
+pub(crate) struct Wheel {
+    forward: [[u8; LETTERS]; LETTERS],
+    reverse: [[u8; LETTERS]; LETTERS],
+    /// Window letter after a turn from each window letter, and whether the turn carries.
+    turn: [(u8, bool); LETTERS],
+}
+
+impl Wheel {
+    /// Tabulates `rotor`, which must have ring `A` so its window letter is its offset.
+    fn new(rotor: &Rotor) -> Self {
+        let mut rotor = rotor.clone();
+        let mut wheel = Wheel {
+            forward: [[0; LETTERS]; LETTERS],
+            reverse: [[0; LETTERS]; LETTERS],
+            turn: [(0, false); LETTERS],
+        };
+        for offset in 0..LETTERS as u8 {
+            rotor.set_position(letter(offset));
+            for input in 0..LETTERS as u8 {
+                let (forward, reverse) = (
+                    rotor.pass_through_forward(letter(input)),
+                    rotor.pass_through_reverse(letter(input)),
+                );
+                wheel.forward[offset as usize][input as usize] = forward.map_or(input, index);
+                wheel.reverse[offset as usize][input as usize] = reverse.map_or(input, index);
+            }
+            let carry = rotor.turn();
+            wheel.turn[offset as usize] = (index(rotor.position()), carry);
+        }
+        wheel
+    }
+}
+
+/// One wheel order and reflector as lookup tables, without a plugboard.
+pub(crate) struct Stack {
+    wheels: Vec<Wheel>,
+    /// Fast wheel, reflector and fast wheel back, per offset of the fast wheel.
+    reflected: [[u8; LETTERS]; LETTERS],
+}
+
+impl Stack {
+    pub(crate) fn new(rotors: &[Rotor], reflector: &Reflector) -> Self {
+        let wheels: Vec<Wheel> = rotors.iter().map(Wheel::new).collect();
+        let mut reflected = [[0; LETTERS]; LETTERS];
+        if let Some(fast) = wheels.last() {
+            for (offset, table) in reflected.iter_mut().enumerate() {
+                for (input, output) in table.iter_mut().enumerate() {
+                    let inward = fast.forward[offset][input];
+                    let turned = reflector.encrypt(letter(inward)).map_or(inward, index);
+                    *output = fast.reverse[offset][turned as usize];
+                }
+            }
+        }
+        Stack { wheels, reflected }
+    }
+
+    pub(crate) fn wheel_count(&self) -> usize {
+        self.wheels.len()
+    }
+
+    /// Decrypts to uppercase ASCII from the window letters in `positions`, which are left where
+    /// the message ends.
+    pub(crate) fn decrypt(
+        &self,
+        ciphertext: &[u8],
+        rings: &[u8],
+        positions: &mut [u8],
+        plaintext: &mut Vec<u8>,
+    ) {
+        let fast = self.wheels.len() - 1;
+        let mut outer = self.outer(&positions[..fast], rings);
+        plaintext.clear();
+        for &cipher in ciphertext {
+            if self.step(positions) {
+                outer = self.outer(&positions[..fast], rings);
+            }
+            let offset = (positions[fast] + rings[fast]) as usize % LETTERS;
+            let reflected = self.reflected[offset][outer.0[cipher as usize] as usize];
+            plaintext.push(b'A' + outer.1[reflected as usize]);
+        }
+    }
+
+    /// Fills `presses` with the permutation of each key press from the window letters in
+    /// `positions`, which are left after the last press.
+    pub(crate) fn presses(
+        &self,
+        rings: &[u8],
+        positions: &mut [u8],
+        presses: &mut [[u8; LETTERS]],
+    ) {
+        let fast = self.wheels.len() - 1;
+        let mut outer = self.outer(&positions[..fast], rings);
+        for permutation in presses {
+            if self.step(positions) {
+                outer = self.outer(&positions[..fast], rings);
+            }
+            let offset = (positions[fast] + rings[fast]) as usize % LETTERS;
+            for (input, output) in permutation.iter_mut().enumerate() {
+                let reflected = self.reflected[offset][outer.0[input] as usize];
+                *output = outer.1[reflected as usize];
+            }
+        }
+    }
+
+    /// Records in `walk` the next `presses` key presses from the window letters in `positions`,
+    /// which are left after the last press, for `press` to look up letter by letter.
+    pub(crate) fn walk(&self, rings: &[u8], positions: &mut [u8], presses: usize, walk: &mut Walk) {
+        let fast = self.wheels.len() - 1;
+        walk.outers.clear();
+        walk.presses.clear();
+        walk.outers.push(self.outer(&positions[..fast], rings));
+        for _ in 0..presses {
+            if self.step(positions) {
+                walk.outers.push(self.outer(&positions[..fast], rings));
+            }
+            let offset = (positions[fast] + rings[fast]) as usize % LETTERS;
+            walk.presses.push((walk.outers.len() - 1, offset));
+        }
+    }
+
+    /// Where key press `press` of `walk` takes `input`.
+    pub(crate) fn press(&self, walk: &Walk, press: usize, input: u8) -> u8 {
+        let (outer, offset) = walk.presses[press];
+        let (inward, outward) = &walk.outers[outer];
+        outward[self.reflected[offset][inward[input as usize] as usize] as usize]
+    }
+
+    /// Decrypts once for every offset of the middle wheel and counts the letters that come out
+    /// in each run of key presses between two steps of the middle wheel, both with the slower
+    /// wheels at `positions` and with them moved on by a carry from the middle wheel. The fast
+    /// wheel starts at `positions` and `rings`.
+    pub(crate) fn count_runs(
+        &self,
+        ciphertext: &[u8],
+        rings: &[u8],
+        positions: &[u8],
+        runs: &mut Runs,
+    ) {
+        let fast = self.wheels.len() - 1;
+        let middle = fast - 1;
+        runs.presses.clear();
+        let mut run = 0;
+        let mut position = positions[fast];
+        for _ in ciphertext {
+            let (next, carry) = self.wheels[fast].turn[position as usize];
+            position = next;
+            run += carry as usize;
+            let offset = (position + rings[fast]) as usize % LETTERS;
+            runs.presses.push((run, offset));
+        }
+        runs.counts.clear();
+        runs.counts.resize(run + 1, [[[0; LETTERS]; LETTERS]; 2]);
+
+        let mut slower_rings = rings[..fast].to_vec();
+        slower_rings[middle] = 0;
+        let mut carried = positions[..fast].to_vec();
+        for wheel in (0..middle).rev() {
+            let (next, carry) = self.wheels[wheel].turn[carried[wheel] as usize];
+            carried[wheel] = next;
+            if !carry {
+                break;
+            }
+        }
+        for (before, mut slower) in [positions[..fast].to_vec(), carried]
+            .into_iter()
+            .enumerate()
+        {
+            for offset in 0..LETTERS {
+                slower[middle] = offset as u8;
+                let (inward, outward) = self.outer(&slower, &slower_rings);
+                for (&cipher, &(run, fast_offset)) in ciphertext.iter().zip(&runs.presses) {
+                    let reflected = self.reflected[fast_offset][inward[cipher as usize] as usize];
+                    runs.counts[run][before][offset][outward[reflected as usize] as usize] += 1;
+                }
+            }
+        }
+    }
+
+    /// Adds up the letter counts of `runs` with the middle wheel started at `position` and
+    /// `ring`, stepping it between runs. Only its first carry moves the slower wheels.
+    pub(crate) fn run_totals(&self, runs: &Runs, position: u8, ring: u8) -> [u32; LETTERS] {
+        let middle = &self.wheels[self.wheels.len() - 2];
+        let mut totals = [0; LETTERS];
+        let mut position = position;
+        let mut carried = 0;
+        for counts in &runs.counts {
+            let offset = (position + ring) as usize % LETTERS;
+            for (total, count) in totals.iter_mut().zip(&counts[carried][offset]) {
+                *total += count;
+            }
+            let (next, carry) = middle.turn[position as usize];
+            position = next;
+            carried |= carry as usize;
+        }
+        totals
+    }
+
+    /// Steps `positions` the way `machine::step` steps the rotors; true if a slower wheel moved.
+    fn step(&self, positions: &mut [u8]) -> bool {
+        let fast = self.wheels.len() - 1;
+        let mut wheel = fast;
+        loop {
+            let (next, carry) = self.wheels[wheel].turn[positions[wheel] as usize];
+            positions[wheel] = next;
+            if !carry || wheel == 0 {
+                break;
+            }
+            wheel -= 1;
+        }
+        wheel < fast
+    }
+
+    /// The slower wheels' permutation inwards and back out at the given window letters.
+    fn outer(&self, positions: &[u8], rings: &[u8]) -> ([u8; LETTERS], [u8; LETTERS]) {
+        let mut forward = [0; LETTERS];
+        let mut reverse = [0; LETTERS];
+        for input in 0..LETTERS {
+            let mut inward = input as u8;
+            let mut outward = input as u8;
+            for (wheel, (&position, &ring)) in self.wheels.iter().zip(positions.iter().zip(rings)) {
+                let offset = (position + ring) as usize % LETTERS;
+                inward = wheel.forward[offset][inward as usize];
+            }
+            for (wheel, (&position, &ring)) in
+                self.wheels.iter().zip(positions.iter().zip(rings)).rev()
+            {
+                let offset = (position + ring) as usize % LETTERS;
+                outward = wheel.reverse[offset][outward as usize];
+            }
+            forward[input] = inward;
+            reverse[input] = outward;
+        }
+        (forward, reverse)
+    }
+}
+
+/// The wheel orders and reflectors of a key search, tabulated as a `Stack` for each pairing.
+pub(crate) struct Stacks {
+    /// The wheels of each order, at ring `A`.
+    pub(crate) orders: Vec<Vec<Rotor>>,
+    pub(crate) reflectors: Vec<Reflector>,
+    /// Indexed by wheel order, then reflector.
+    pub(crate) tables: Vec<Vec<Stack>>,
+}
+
+impl Stacks {
+    /// Looks up the wheels (names as for `rotors::from_name`) and reflectors (as for
+    /// `reflectors::by_name`) and tabulates every wheel order with every reflector.
+    pub(crate) fn new(orders: &[Vec<&str>], reflector_names: &[&str]) -> Result<Self, EnigmaError> {
+        let wheel_count = orders.first().map_or(0, Vec::len);
+        if wheel_count == 0 || orders.iter().any(|order| order.len() != wheel_count) {
+            return Err(EnigmaError::InvalidSearch(
+                "wheel orders must all have the same number of wheels".to_string(),
+            ));
+        }
+        let orders = orders
+            .iter()
+            .map(|order| {
+                order
+                    .iter()
+                    .map(|name| {
+                        rotors::from_name(name, 'A', 'A').ok_or_else(|| {
+                            EnigmaError::InvalidSearch(format!("unknown wheel {}", name))
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let reflectors = reflector_names
+            .iter()
+            .map(|name| {
+                reflectors::by_name(name).ok_or_else(|| {
+                    EnigmaError::InvalidSearch(format!("unknown reflector {}", name))
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if reflectors.is_empty() {
+            return Err(EnigmaError::InvalidSearch(
+                "no reflector to try".to_string(),
+            ));
+        }
+
+        let tables = orders
+            .iter()
+            .map(|rotors| {
+                reflectors
+                    .iter()
+                    .map(|reflector| Stack::new(rotors, reflector))
+                    .collect()
+            })
+            .collect();
+        Ok(Stacks {
+            orders,
+            reflectors,
+            tables,
+        })
+    }
+
+    pub(crate) fn wheel_count(&self) -> usize {
+        self.orders[0].len()
+    }
+
+    /// Every wheel order and reflector, by index, paired with every ring setting.
+    pub(crate) fn jobs<'a>(&self, ring_settings: &'a [Vec<u8>]) -> Vec<(usize, usize, &'a [u8])> {
+        let mut jobs = Vec::new();
+        for order in 0..self.orders.len() {
+            for reflector in 0..self.reflectors.len() {
+                for rings in ring_settings {
+                    jobs.push((order, reflector, rings.as_slice()));
+                }
+            }
+        }
+        jobs
+    }
+}
+
+/// Key presses recorded by `Stack::walk`: the slower wheels' permutations in and out, and for
+/// each press which of them it uses and the offset of the fast wheel.
+#[derive(Default)]
+pub(crate) struct Walk {
+    outers: Vec<([u8; LETTERS], [u8; LETTERS])>,
+    presses: Vec<(usize, usize)>,
+}
+
+/// Letter counts recorded by `Stack::count_runs`: for each run of key presses between steps of
+/// the middle wheel, before and after its carry, how often each letter came out at each offset
+/// of the middle wheel.
+#[derive(Default)]
+pub(crate) struct Runs {
+    counts: Vec<[[[u32; LETTERS]; LETTERS]; 2]>,
+    presses: Vec<(usize, usize)>,
+}
+
+fn index(ch: char) -> u8 {
+    ch as u8 - b'A'
+}
+
+fn letter(index: u8) -> char {
+    (b'A' + index) as char
+}
+
+#[cfg(test)]
+mod tables_tests {
+    use super::*;
+    use crate::machine::EnigmaMachine;
+    use crate::plugboard::Plugboard;
+    use crate::reflector::reflectors;
+    use crate::rotor::rotors;
+
+    const PLAINTEXT: &str =
+        "ANXBEFEHLSHABERXDERXUNTERSEEBOOTEXUBOOTXMELDETXPOSITIONXQUADRATXAJXDREIXSIEBENXEINSXNEUN";
+
+    fn machine(rings: &str, positions: &str) -> EnigmaMachine {
+        let rotors = ["ii", "iv", "i"]
+            .iter()
+            .zip(rings.chars().zip(positions.chars()))
+            .filter_map(|(name, (ring, position))| rotors::from_name(name, position, ring))
+            .collect();
+        EnigmaMachine::new(rotors, reflectors::ukw_b(), Plugboard::new(vec![]).unwrap())
+    }
+
+    fn stack() -> Stack {
+        Stack::new(
+            &["ii", "iv", "i"]
+                .iter()
+                .filter_map(|name| rotors::from_name(name, 'A', 'A'))
+                .collect::<Vec<_>>(),
+            &reflectors::ukw_b(),
+        )
+    }
+
+    #[test]
+    fn test_stack_matches_machine() {
+        let ciphertext: Vec<u8> = PLAINTEXT.chars().map(index).collect();
+        let stack = stack();
+        for (rings, positions) in [("AAA", "AAA"), ("KDQ", "XRB"), ("ZZZ", "QEV")] {
+            let mut machine = machine(rings, positions);
+            let expected: Vec<u8> = PLAINTEXT
+                .chars()
+                .filter_map(|ch| machine.encrypt_char(ch))
+                .map(|ch| ch as u8)
+                .collect();
+            let mut plaintext = Vec::new();
+            let mut window: Vec<u8> = positions.chars().map(index).collect();
+            let rings: Vec<u8> = rings.chars().map(index).collect();
+            stack.decrypt(&ciphertext, &rings, &mut window, &mut plaintext);
+            assert_eq!(plaintext, expected, "rings {:?}", rings);
+        }
+    }
+
+    #[test]
+    fn test_run_totals_match_decryption() {
+        let ciphertext: Vec<u8> = PLAINTEXT.chars().map(index).collect();
+        let stack = stack();
+        let mut runs = Runs::default();
+        // From T the middle wheel IV reaches its notch K within the message; from A it does not
+        for (rings, positions) in [([0, 0, 0], [0, 0, 0]), ([3, 16, 11], [25, 19, 1])] {
+            stack.count_runs(&ciphertext, &rings, &positions, &mut runs);
+            let mut plaintext = Vec::new();
+            stack.decrypt(&ciphertext, &rings, &mut positions.clone(), &mut plaintext);
+            let mut expected = [0; LETTERS];
+            for letter in plaintext {
+                expected[(letter - b'A') as usize] += 1;
+            }
+            assert_eq!(stack.run_totals(&runs, positions[1], rings[1]), expected);
+        }
+    }
+
+    #[test]
+    fn test_presses_match_machine() {
+        let stack = stack();
+        let mut machine = machine("QZE", "DMV");
+        let mut presses = [[0; LETTERS]; 30];
+        stack.presses(&[16, 25, 4], &mut [3, 12, 21], &mut presses);
+        let mut walk = Walk::default();
+        stack.walk(&[16, 25, 4], &mut [3, 12, 21], presses.len(), &mut walk);
+        for (press, permutation) in presses.iter().enumerate() {
+            for (input, &output) in permutation.iter().enumerate() {
+                assert_eq!(stack.press(&walk, press, input as u8), output);
+            }
+        }
+        for permutation in presses {
+            let positions = machine.positions();
+            for (input, &output) in permutation.iter().enumerate() {
+                machine.set_positions(&positions).unwrap();
+                assert_eq!(
+                    machine.encrypt_char(letter(input as u8)),
+                    Some(letter(output))
+                );
+            }
+        }
+    }
+}
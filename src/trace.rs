@@ -0,0 +1,179 @@
+//! What a single key press does inside the machine.
+//!
+//! `EnigmaMachine::encrypt_traced` records which rotors a key press turned and why, then every
+//! stage the current takes from the key to the lamp. A `Trace` prints as a small table, one row
+//! per stage, and serialises to JSON for tools.
+
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+/// One key press, from the key to the lamp.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Trace {
+    /// The key pressed.
+    pub key: char,
+    /// Rotors the key press turned, rightmost rotor first.
+    pub stepped: Vec<Stepped>,
+    /// Window letters once the rotors have stepped, leftmost rotor first.
+    pub windows: String,
+    /// Through the plugboard on the way in.
+    pub plugboard_in: Wire,
+    /// Through each rotor towards the reflector, leftmost rotor first.
+    pub forward: Vec<RotorPass>,
+    /// Through the reflector.
+    pub reflector: Reflection,
+    /// Through each rotor back from the reflector, rightmost rotor first.
+    pub reverse: Vec<RotorPass>,
+    /// Through the plugboard on the way out.
+    pub plugboard_out: Wire,
+    /// The lamp that lights.
+    pub lamp: char,
+}
+
+/// A rotor that turned, and from which window letter to which.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Stepped {
+    pub rotor: String,
+    pub from: char,
+    pub to: char,
+    pub reason: StepReason,
+}
+
+/// Why a rotor turned.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+pub enum StepReason {
+    /// The rightmost rotor turns on every key press.
+    KeyPress,
+    /// The rotor to its right turned onto a notch.
+    Carry { rotor: String, notch: char },
+}
+
+/// A letter going into a fixed part of the machine and the letter coming out.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Wire {
+    pub input: char,
+    pub output: char,
+}
+
+/// A letter passing through one rotor, with the rotor's window letter and ring.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RotorPass {
+    pub rotor: String,
+    pub window: char,
+    pub ring: char,
+    pub input: char,
+    pub output: char,
+}
+
+/// A letter turned back by the reflector.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Reflection {
+    pub reflector: String,
+    pub input: char,
+    pub output: char,
+}
+
+impl fmt::Display for StepReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepReason::KeyPress => write!(f, "key press"),
+            StepReason::Carry { rotor, notch } => write!(f, "{} reached notch {}", rotor, notch),
+        }
+    }
+}
+
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "key {}  windows {}", self.key, self.windows)?;
+        for step in &self.stepped {
+            writeln!(
+                f,
+                "  {:<10} {:<10} {} -> {}  {}",
+                "step", step.rotor, step.from, step.to, step.reason
+            )?;
+        }
+        let wire = |f: &mut fmt::Formatter<'_>, stage: &str, part: &str, input, output| {
+            writeln!(f, "  {:<10} {:<10} {} -> {}", stage, part, input, output)
+        };
+        let rotor = |f: &mut fmt::Formatter<'_>, stage: &str, pass: &RotorPass| {
+            writeln!(
+                f,
+                "  {:<10} {:<10} {} -> {}  window {} ring {}",
+                stage, pass.rotor, pass.input, pass.output, pass.window, pass.ring
+            )
+        };
+        wire(
+            f,
+            "plugboard",
+            "",
+            self.plugboard_in.input,
+            self.plugboard_in.output,
+        )?;
+        for pass in &self.forward {
+            rotor(f, "forward", pass)?;
+        }
+        wire(
+            f,
+            "reflector",
+            &self.reflector.reflector,
+            self.reflector.input,
+            self.reflector.output,
+        )?;
+        for pass in &self.reverse {
+            rotor(f, "reverse", pass)?;
+        }
+        wire(
+            f,
+            "plugboard",
+            "",
+            self.plugboard_out.input,
+            self.plugboard_out.output,
+        )?;
+        write!(f, "  lamp {}", self.lamp)
+    }
+}
+
+/// How traces are printed: as tables for reading, or as one JSON object per key press.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    Table,
+    Json,
+}
+
+impl TraceFormat {
+    /// Renders one trace on its own, without a trailing newline.
+    pub fn render(&self, trace: &Trace) -> String {
+        match self {
+            TraceFormat::Table => trace.to_string(),
+            TraceFormat::Json => {
+                serde_json::to_string(trace).expect("Traces always serialise to JSON")
+            }
+        }
+    }
+}
+
+impl FromStr for TraceFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(TraceFormat::Table),
+            "json" => Ok(TraceFormat::Json),
+            _ => Err(format!(
+                "Unknown trace format: {} (expected table or json)",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for TraceFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TraceFormat::Table => write!(f, "table"),
+            TraceFormat::Json => write!(f, "json"),
+        }
+    }
+}
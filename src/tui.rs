@@ -0,0 +1,455 @@
+//! `enigma tui`: a full-screen lampboard on top of `EnigmaMachine`.
+//!
+//! The screen shows the rotor windows, the lampboard, the keyboard, the plugboard and a tape of
+//! everything typed so far. `Tab` cycles between typing and changing the window letters, rings
+//! and plugs, all of which go through the machine's own setters.
+
+use enigma_shark::{EnigmaMachine, Plugboard};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Alignment, Constraint, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::io;
+
+/// Keys and lamps in the order of the German keyboard.
+const ROWS: [&str; 3] = ["QWERTZUIO", "ASDFGHJK", "PYXCVBNML"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Type,
+    Positions,
+    Rings,
+    Plugs,
+}
+
+impl Mode {
+    fn next(self) -> Self {
+        match self {
+            Mode::Type => Mode::Positions,
+            Mode::Positions => Mode::Rings,
+            Mode::Rings => Mode::Plugs,
+            Mode::Plugs => Mode::Type,
+        }
+    }
+
+    fn help(self) -> &'static str {
+        match self {
+            Mode::Type => "A-Z type   Tab change settings   Esc quit",
+            Mode::Positions | Mode::Rings => {
+                "Left/Right pick rotor   Up/Down or A-Z set letter   Tab next   Esc quit"
+            }
+            Mode::Plugs => {
+                "A-Z then A-Z plug a pair, a plugged letter unplugs   Tab next   Esc quit"
+            }
+        }
+    }
+}
+
+/// Everything on screen: the machine, what is being changed, and the tape.
+pub(crate) struct Lampboard {
+    machine: EnigmaMachine,
+    mode: Mode,
+    /// Rotor picked for changing its window letter or ring, leftmost rotor first.
+    selected: usize,
+    /// First letter of a plug waiting for its partner.
+    pending: Option<char>,
+    /// Key and lamp of the last key press, while the lamp is lit.
+    lit: Option<(char, char)>,
+    input: String,
+    output: String,
+    status: String,
+    quit: bool,
+}
+
+impl Lampboard {
+    pub(crate) fn new(machine: EnigmaMachine) -> Self {
+        Lampboard {
+            machine,
+            mode: Mode::Type,
+            selected: 0,
+            pending: None,
+            lit: None,
+            input: String::new(),
+            output: String::new(),
+            status: String::new(),
+            quit: false,
+        }
+    }
+
+    pub(crate) fn handle(&mut self, key: KeyEvent) {
+        if key.kind != KeyEventKind::Press {
+            return;
+        }
+        let letter = match key.code {
+            KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                self.quit = true;
+                return;
+            }
+            KeyCode::Char(ch) if ch.is_ascii_alphabetic() => Some(ch.to_ascii_uppercase()),
+            _ => None,
+        };
+        self.lit = None;
+        match (self.mode, key.code, letter) {
+            (_, KeyCode::Esc, _) => self.quit = true,
+            (_, KeyCode::Tab, _) => {
+                self.mode = self.mode.next();
+                self.pending = None;
+                self.status.clear();
+            }
+            (Mode::Type, _, Some(letter)) => self.press(letter),
+            (Mode::Positions | Mode::Rings, KeyCode::Left, _) => {
+                self.selected = self.selected.saturating_sub(1)
+            }
+            (Mode::Positions | Mode::Rings, KeyCode::Right, _) => {
+                self.selected = (self.selected + 1).min(self.machine.rotors().len() - 1)
+            }
+            (Mode::Positions | Mode::Rings, KeyCode::Up, _) => self.turn(1),
+            (Mode::Positions | Mode::Rings, KeyCode::Down, _) => self.turn(25),
+            (Mode::Positions | Mode::Rings, _, Some(letter)) => self.set(letter),
+            (Mode::Plugs, _, Some(letter)) => self.plug(letter),
+            _ => {}
+        }
+    }
+
+    fn press(&mut self, key: char) {
+        if let Some(lamp) = self.machine.encrypt_char(key) {
+            self.input.push(key);
+            self.output.push(lamp);
+            self.lit = Some((key, lamp));
+        }
+    }
+
+    /// Moves the selected rotor's window letter or ring `by` letters on.
+    fn turn(&mut self, by: u8) {
+        let current = match self.mode {
+            Mode::Rings => self.machine.rings(),
+            _ => self.machine.positions(),
+        };
+        if let Some(letter) = current.chars().nth(self.selected) {
+            self.set((b'A' + (letter as u8 - b'A' + by) % 26) as char);
+        }
+    }
+
+    fn set(&mut self, letter: char) {
+        let setting = |current: String| -> String {
+            current
+                .chars()
+                .enumerate()
+                .map(|(index, ch)| if index == self.selected { letter } else { ch })
+                .collect()
+        };
+        let result = match self.mode {
+            Mode::Rings => self.machine.set_rings(&setting(self.machine.rings())),
+            _ => self
+                .machine
+                .set_positions(&setting(self.machine.positions())),
+        };
+        if let Err(err) = result {
+            self.status = err.to_string();
+        }
+    }
+
+    fn plug(&mut self, letter: char) {
+        let mut pairs = self.machine.plugboard().pairs().to_vec();
+        if let Some(index) = pairs.iter().position(|&(a, b)| a == letter || b == letter) {
+            let (a, b) = pairs.remove(index);
+            self.status = format!("Unplugged {}{}", a, b);
+        } else {
+            match self.pending.take() {
+                None => {
+                    self.pending = Some(letter);
+                    self.status = format!("Plugging {}, pick its partner", letter);
+                    return;
+                }
+                Some(first) if first == letter => {
+                    self.status.clear();
+                    return;
+                }
+                Some(first) => {
+                    pairs.push((first, letter));
+                    self.status = format!("Plugged {}{}", first, letter);
+                }
+            }
+        }
+        match Plugboard::new(pairs) {
+            Ok(plugboard) => self.machine.set_plugboard(plugboard),
+            Err(err) => self.status = err.to_string(),
+        }
+    }
+
+    pub(crate) fn render(&self, frame: &mut Frame) {
+        let [rotors, lamps, keys, plugs, tape, help] = Layout::vertical([
+            Constraint::Length(5),
+            Constraint::Length(5),
+            Constraint::Length(5),
+            Constraint::Length(4),
+            Constraint::Length(4),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+        self.render_rotors(frame, rotors);
+
+        let lamp = self.lit.map(|(_, lamp)| lamp);
+        let lit = Style::default()
+            .fg(Color::Black)
+            .bg(Color::Yellow)
+            .add_modifier(Modifier::BOLD);
+        frame.render_widget(
+            board("Lampboard", |ch| (Some(ch) == lamp).then_some(lit)),
+            lamps,
+        );
+        let key = self.lit.map(|(key, _)| key);
+        let pressed = Style::default().add_modifier(Modifier::REVERSED);
+        frame.render_widget(
+            board("Keyboard", |ch| (Some(ch) == key).then_some(pressed)),
+            keys,
+        );
+
+        self.render_plugs(frame, plugs);
+        self.render_tape(frame, tape);
+        let help_line = match self.status.is_empty() {
+            true => self.mode.help().to_string(),
+            false => format!("{}   {}", self.status, self.mode.help()),
+        };
+        frame.render_widget(Paragraph::new(help_line), help);
+    }
+
+    fn render_rotors(&self, frame: &mut Frame, area: Rect) {
+        let rotors = self.machine.rotors();
+        let mut constraints = vec![Constraint::Length(14)];
+        constraints.extend(rotors.iter().map(|_| Constraint::Length(14)));
+        constraints.push(Constraint::Min(0));
+        let slots = Layout::horizontal(constraints).split(area);
+
+        let reflector = Paragraph::new(vec![Line::from(""), Line::from("reflector")])
+            .alignment(Alignment::Center)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(self.machine.reflector().model().to_string()),
+            );
+        frame.render_widget(reflector, slots[0]);
+
+        let changing = matches!(self.mode, Mode::Positions | Mode::Rings);
+        for (index, (rotor, &slot)) in rotors.iter().zip(&slots[1..]).enumerate() {
+            let selected = changing && index == self.selected;
+            let highlight = |style: Style, on: bool| match on {
+                true => style.fg(Color::Yellow).add_modifier(Modifier::BOLD),
+                false => style,
+            };
+            let window = Span::styled(
+                format!("[ {} ]", rotor.position()),
+                highlight(
+                    Style::default().add_modifier(Modifier::BOLD),
+                    selected && self.mode == Mode::Positions,
+                ),
+            );
+            let ring = Span::styled(
+                format!("ring {}", rotor.ring()),
+                highlight(Style::default(), selected && self.mode == Mode::Rings),
+            );
+            let paragraph = Paragraph::new(vec![Line::from(window), Line::from(ring)])
+                .alignment(Alignment::Center)
+                .block(
+                    Block::default()
+                        .borders(Borders::ALL)
+                        .border_style(highlight(Style::default(), selected))
+                        .title(rotor.model().to_string()),
+                );
+            frame.render_widget(paragraph, slot);
+        }
+    }
+
+    fn render_plugs(&self, frame: &mut Frame, area: Rect) {
+        let plugboard = self.machine.plugboard();
+        let letters: Vec<Span> = ROWS
+            .concat()
+            .chars()
+            .flat_map(|ch| {
+                let plugged = plugboard.pass_through(ch) != ch;
+                let style = match (Some(ch) == self.pending, plugged) {
+                    (true, _) => Style::default().fg(Color::Black).bg(Color::Yellow),
+                    (false, true) => Style::default().fg(Color::Cyan),
+                    (false, false) => Style::default().fg(Color::DarkGray),
+                };
+                [Span::styled(ch.to_string(), style), Span::raw(" ")]
+            })
+            .collect();
+        let pairs: Vec<String> = plugboard
+            .pairs()
+            .iter()
+            .map(|&(a, b)| format!("{}{}", a, b))
+            .collect();
+        let pairs = match pairs.is_empty() {
+            true => "no plugs".to_string(),
+            false => pairs.join(" "),
+        };
+        let title = match self.mode {
+            Mode::Plugs => "Plugboard (changing)",
+            _ => "Plugboard",
+        };
+        let paragraph = Paragraph::new(vec![Line::from(letters), Line::from(pairs)])
+            .alignment(Alignment::Center)
+            .block(Block::default().borders(Borders::ALL).title(title));
+        frame.render_widget(paragraph, area);
+    }
+
+    fn render_tape(&self, frame: &mut Frame, area: Rect) {
+        // Five-letter groups, keeping the tail of the tape that fits on a line
+        let groups = (area.width.saturating_sub(7) as usize).div_ceil(6).max(1);
+        let tail = |text: &str| -> String {
+            let groups_typed = text.len().div_ceil(5);
+            let start = groups_typed.saturating_sub(groups) * 5;
+            text.as_bytes()[start..]
+                .chunks(5)
+                .map(|group| String::from_utf8_lossy(group).into_owned())
+                .collect::<Vec<_>>()
+                .join(" ")
+        };
+        let paragraph = Paragraph::new(vec![
+            Line::from(format!("in   {}", tail(&self.input))),
+            Line::from(format!("out  {}", tail(&self.output))),
+        ])
+        .block(Block::default().borders(Borders::ALL).title("Tape"));
+        frame.render_widget(paragraph, area);
+    }
+}
+
+/// The keys or lamps in three staggered rows, styled by `style`.
+fn board<'a>(title: &'a str, style: impl Fn(char) -> Option<Style>) -> Paragraph<'a> {
+    let rows: Vec<Line> = ROWS
+        .iter()
+        .map(|row| {
+            Line::from(
+                row.chars()
+                    .flat_map(|ch| {
+                        let look = style(ch).unwrap_or_default();
+                        [Span::styled(format!(" {} ", ch), look), Span::raw(" ")]
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect();
+    Paragraph::new(rows)
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).title(title))
+}
+
+/// Runs the lampboard on the terminal until the user quits, leaving the terminal as it was.
+pub(crate) fn run(machine: EnigmaMachine) -> io::Result<()> {
+    let mut terminal = ratatui::init();
+    let result = event_loop(&mut terminal, Lampboard::new(machine));
+    ratatui::restore();
+    result
+}
+
+fn event_loop(terminal: &mut DefaultTerminal, mut lampboard: Lampboard) -> io::Result<()> {
+    while !lampboard.quit {
+        terminal.draw(|frame| lampboard.render(frame))?;
+        if let Event::Key(key) = event::read()? {
+            lampboard.handle(key);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tui_tests {
+    use super::*;
+    use enigma_shark::{reflectors, rotors};
+    use ratatui::backend::TestBackend;
+    use ratatui::Terminal;
+
+    fn machine() -> EnigmaMachine {
+        let rotors = vec![
+            rotors::type_i('A', 'A'),
+            rotors::type_ii('A', 'A'),
+            rotors::type_iii('A', 'A'),
+        ];
+        EnigmaMachine::new(rotors, reflectors::ukw_b(), Plugboard::new(vec![]).unwrap())
+    }
+
+    fn keys(lampboard: &mut Lampboard, codes: &[KeyCode]) {
+        for &code in codes {
+            lampboard.handle(KeyEvent::from(code));
+        }
+    }
+
+    fn typed(lampboard: &mut Lampboard, text: &str) {
+        for ch in text.chars() {
+            lampboard.handle(KeyEvent::from(KeyCode::Char(ch)));
+        }
+    }
+
+    #[test]
+    fn test_settings_change_the_machine() {
+        let mut lampboard = Lampboard::new(machine());
+        typed(&mut lampboard, "hello");
+
+        // Window letters, then rings, then plugs
+        keys(&mut lampboard, &[KeyCode::Tab]);
+        typed(&mut lampboard, "b");
+        keys(&mut lampboard, &[KeyCode::Right]);
+        typed(&mut lampboard, "c");
+        keys(&mut lampboard, &[KeyCode::Up, KeyCode::Right]);
+        typed(&mut lampboard, "q");
+        assert_eq!(lampboard.machine.positions(), "BDQ");
+        keys(
+            &mut lampboard,
+            &[KeyCode::Tab, KeyCode::Left, KeyCode::Down],
+        );
+        assert_eq!(lampboard.machine.rings(), "AZA");
+        keys(&mut lampboard, &[KeyCode::Tab]);
+        typed(&mut lampboard, "aqezmk");
+        typed(&mut lampboard, "e");
+        assert_eq!(
+            lampboard.machine.plugboard().pairs(),
+            &[('A', 'Q'), ('M', 'K')]
+        );
+        keys(&mut lampboard, &[KeyCode::Tab]);
+        assert_eq!(lampboard.mode, Mode::Type);
+        typed(&mut lampboard, "world");
+
+        let mut expected = EnigmaMachine::new(
+            vec![
+                rotors::type_i('B', 'A'),
+                rotors::type_ii('D', 'Z'),
+                rotors::type_iii('Q', 'A'),
+            ],
+            reflectors::ukw_b(),
+            Plugboard::new(vec![('A', 'Q'), ('M', 'K')]).unwrap(),
+        );
+        let world = expected.encrypt_message("WORLD").unwrap();
+        assert_eq!(lampboard.input, "HELLOWORLD");
+        assert_eq!(&lampboard.output[5..], world);
+        assert_eq!(lampboard.lit, world.chars().last().map(|lamp| ('D', lamp)));
+
+        keys(&mut lampboard, &[KeyCode::Esc]);
+        assert!(lampboard.quit);
+    }
+
+    #[test]
+    fn test_render() {
+        let mut lampboard = Lampboard::new(machine());
+        typed(&mut lampboard, "enigma");
+        let mut terminal = Terminal::new(TestBackend::new(80, 24)).unwrap();
+        terminal.draw(|frame| lampboard.render(frame)).unwrap();
+        let screen: String = terminal
+            .backend()
+            .buffer()
+            .content()
+            .iter()
+            .map(|cell| cell.symbol())
+            .collect();
+        for text in ["UKW-B", "type III", "Lampboard", "no plugs", "out  "] {
+            assert!(screen.contains(text), "{} not on screen", text);
+        }
+        for window in lampboard.machine.positions().chars() {
+            assert!(screen.contains(&format!("[ {} ]", window)));
+        }
+        let output = &lampboard.output;
+        assert!(screen.contains(&format!("{} {}", &output[..5], &output[5..])));
+    }
+}
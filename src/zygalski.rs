@@ -0,0 +1,573 @@
+//! Zygalski sheets.
+//!
+//! From September 1938 the operator picked a Grundstellung for each message, sent it in the
+//! clear and keyed the doubled message key at it. Catalogues of a daily Grundstellung were
+//! useless, but *females* still gave the key away: an indicator whose first and fourth letters
+//! agree (or second and fifth, third and sixth) shows that the product of those two key presses
+//! has a fixed point at that message's Grundstellung, and only about two settings in five have
+//! one.
+//!
+//! A Zygalski sheet marks every setting of one wheel order where the product has a fixed
+//! point: one sheet per window letter of the left wheel, a hole in a grid of middle and right
+//! wheel positions, drawn twice over in each direction so sheets can be slid against each other.
+//! Here they come out as SVG, or as a PDF with a page per sheet, for printing and punching.
+//!
+//! The search stacks the same sheets in memory, but over ring settings: each female is a layer
+//! that lets light through at the rings where its Grundstellung gives a fixed point, and the
+//! rings that show light through every layer are the apertures. The rings go in as such rather
+//! than as a shift of the sheets because this machine adds them to the wiring offset, which
+//! leaves nothing to slide.
+//!
+//! The signal passes the slower wheels before the fast one, so unless a turnover falls inside
+//! the indicator they only conjugate the products, which keeps the fixed points. Sheets of this
+//! machine are therefore striped by the right wheel position, and each aperture comes with
+//! every ring setting of the slower wheels.
+
+use crate::error::EnigmaError;
+use crate::model::Model;
+use crate::procedure::Indicator;
+use crate::reflector::reflectors;
+use crate::rotor::{rotors, Rotor};
+use crate::tables::Stack;
+use rayon::prelude::*;
+use std::fmt;
+
+const LETTERS: usize = 26;
+
+/// Rows and columns of a sheet: the 26 positions and 25 of them again.
+const GRID: usize = 2 * LETTERS - 1;
+
+/// A repeated letter in a doubled indicator: key presses `pair + 1` and `pair + 1 + n` of an
+/// `n` letter key came out the same at `grundstellung`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Female {
+    pub grundstellung: String,
+    pub pair: usize,
+}
+
+impl fmt::Display for Female {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let key_len = self.grundstellung.len();
+        write!(
+            f,
+            "{} {}-{}",
+            self.grundstellung,
+            self.pair + 1,
+            self.pair + 1 + key_len
+        )
+    }
+}
+
+/// Every female among the doubled `indicators`.
+pub fn females(indicators: &[Indicator]) -> Result<Vec<Female>, EnigmaError> {
+    let mut females = Vec::new();
+    for indicator in indicators {
+        let key_len = indicator.grundstellung.len();
+        let letters = indicator.encrypted_key.as_bytes();
+        if letters.len() != 2 * key_len || key_len == 0 {
+            return Err(EnigmaError::InvalidIndicator(format!(
+                "{} is not a doubled key for Grundstellung {}",
+                indicator.encrypted_key, indicator.grundstellung
+            )));
+        }
+        for pair in 0..key_len {
+            if letters[pair] == letters[pair + key_len] {
+                females.push(Female {
+                    grundstellung: indicator.grundstellung.clone(),
+                    pair,
+                });
+            }
+        }
+    }
+    Ok(females)
+}
+
+/// Rings that let light through every layer of a wheel order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Aperture {
+    /// Rotor models, leftmost first.
+    pub wheels: Vec<String>,
+    pub reflector: String,
+    pub rings: String,
+}
+
+impl fmt::Display for Aperture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} rings {}",
+            self.wheels.join(" "),
+            self.reflector,
+            self.rings
+        )
+    }
+}
+
+/// Stacks the layers of `females` for every wheel order of `model`.
+pub fn search(
+    model: Model,
+    reflector: &str,
+    females: &[Female],
+) -> Result<Vec<Aperture>, EnigmaError> {
+    search_orders(&model.wheel_orders(), reflector, females)
+}
+
+/// Stacks the layers of `females` for the given wheel orders (names as for
+/// `rotors::from_name`) in parallel.
+pub fn search_orders(
+    orders: &[Vec<&str>],
+    reflector: &str,
+    females: &[Female],
+) -> Result<Vec<Aperture>, EnigmaError> {
+    let reflector = reflectors::by_name(reflector)
+        .ok_or_else(|| EnigmaError::InvalidSearch(format!("unknown reflector {}", reflector)))?;
+    let stacks = orders
+        .iter()
+        .map(|order| wheel_stack(order))
+        .collect::<Result<Vec<_>, _>>()?;
+    let starts = stacks
+        .first()
+        .map(|stack| {
+            females
+                .iter()
+                .map(|female| window(&female.grundstellung, stack.len()))
+                .collect::<Result<Vec<_>, _>>()
+        })
+        .transpose()?
+        .unwrap_or_default();
+
+    Ok(stacks
+        .par_iter()
+        .flat_map_iter(|stack| {
+            let tables = Stack::new(stack, &reflector);
+            let wheels = stack.len();
+            let mut lit = vec![true; LETTERS.pow(wheels as u32)];
+            for (female, start) in females.iter().zip(&starts) {
+                for (setting, light) in lit.iter_mut().enumerate() {
+                    if *light {
+                        *light =
+                            has_fixed_point(&tables, &digits(setting, wheels), start, female.pair);
+                    }
+                }
+            }
+            let wheel_names: Vec<String> = stack
+                .iter()
+                .map(|rotor| rotor.model().to_string())
+                .collect();
+            let reflector = reflector.model().to_string();
+            lit.into_iter()
+                .enumerate()
+                .filter(|&(_, light)| light)
+                .map(move |(setting, _)| Aperture {
+                    wheels: wheel_names.clone(),
+                    reflector: reflector.clone(),
+                    rings: letters(&digits(setting, wheels)),
+                })
+        })
+        .collect())
+}
+
+/// One Zygalski sheet: the fixed points of one product for one setting of every wheel but the
+/// last two, over the positions of those two.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sheet {
+    /// Rotor models, leftmost first.
+    pub wheels: Vec<String>,
+    pub rings: String,
+    /// Window letters of the wheels left of the last two.
+    pub prefix: String,
+    pub pair: usize,
+    /// By middle wheel position, then right wheel position.
+    holes: Vec<bool>,
+}
+
+/// Every sheet of a wheel order for the product of key presses `pair + 1` and `pair + 1 + n`:
+/// one per window letter of the wheels left of the last two.
+pub fn sheets(
+    order: &[&str],
+    rings: &str,
+    reflector: &str,
+    pair: usize,
+) -> Result<Vec<Sheet>, EnigmaError> {
+    let reflector = reflectors::by_name(reflector)
+        .ok_or_else(|| EnigmaError::InvalidSearch(format!("unknown reflector {}", reflector)))?;
+    let stack = wheel_stack(order)?;
+    let wheels = stack.len();
+    if wheels < 2 || pair >= wheels {
+        return Err(EnigmaError::InvalidSearch(format!(
+            "no sheets for presses {} and {} of {} wheels",
+            pair + 1,
+            pair + 1 + wheels,
+            wheels
+        )));
+    }
+    let ring_indices = window(rings, wheels)?;
+    let tables = Stack::new(&stack, &reflector);
+    let wheel_names: Vec<String> = stack
+        .iter()
+        .map(|rotor| rotor.model().to_string())
+        .collect();
+
+    Ok((0..LETTERS.pow(wheels as u32 - 2))
+        .map(|prefix| {
+            let prefix = digits(prefix, wheels - 2);
+            let holes = (0..LETTERS * LETTERS)
+                .map(|square| {
+                    let mut start = prefix.clone();
+                    start.extend([(square / LETTERS) as u8, (square % LETTERS) as u8]);
+                    has_fixed_point(&tables, &ring_indices, &start, pair)
+                })
+                .collect();
+            Sheet {
+                wheels: wheel_names.clone(),
+                rings: rings.to_string(),
+                prefix: letters(&prefix),
+                pair,
+                holes,
+            }
+        })
+        .collect())
+}
+
+impl Sheet {
+    /// Whether the sheet is punched at this middle and right wheel position.
+    pub fn is_hole(&self, middle: char, right: char) -> bool {
+        match (middle.is_ascii_uppercase(), right.is_ascii_uppercase()) {
+            (true, true) => {
+                self.holes[(middle as u8 - b'A') as usize * LETTERS + (right as u8 - b'A') as usize]
+            }
+            _ => false,
+        }
+    }
+
+    /// Number of holes among the 26 x 26 positions.
+    pub fn holes(&self) -> usize {
+        self.holes.iter().filter(|&&hole| hole).count()
+    }
+
+    fn title(&self) -> String {
+        let key_len = self.wheels.len();
+        format!(
+            "{} rings {} at {}, presses {} and {}",
+            self.wheels.join(" "),
+            self.rings,
+            if self.prefix.is_empty() {
+                "any setting".to_string()
+            } else {
+                self.prefix.clone()
+            },
+            self.pair + 1,
+            self.pair + 1 + key_len
+        )
+    }
+
+    /// Whether the grid square at `row`, `column` (0..51) is punched.
+    fn punched(&self, row: usize, column: usize) -> bool {
+        self.holes[(row % LETTERS) * LETTERS + column % LETTERS]
+    }
+
+    /// The sheet as an SVG drawing, 12 px to a square.
+    pub fn to_svg(&self) -> String {
+        const SQUARE: usize = 12;
+        const MARGIN: usize = 40;
+        let size = 2 * MARGIN + GRID * SQUARE;
+        let mut svg = format!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{size}\" height=\"{size}\" viewBox=\"0 0 {size} {size}\" font-family=\"Helvetica, sans-serif\" font-size=\"9\">\n"
+        );
+        svg.push_str(&format!(
+            "<title>{}</title>\n<rect x=\"{MARGIN}\" y=\"{MARGIN}\" width=\"{side}\" height=\"{side}\" fill=\"#c8c8c8\" stroke=\"#000\"/>\n",
+            self.title(),
+            side = GRID * SQUARE
+        ));
+        svg.push_str(&format!(
+            "<text x=\"{MARGIN}\" y=\"{}\" font-size=\"12\">{}</text>\n",
+            MARGIN / 2,
+            self.title()
+        ));
+        for line in 0..GRID {
+            let label = (b'A' + (line % LETTERS) as u8) as char;
+            let centre = MARGIN + line * SQUARE + SQUARE / 2;
+            svg.push_str(&format!(
+                "<text x=\"{centre}\" y=\"{}\" text-anchor=\"middle\">{label}</text>\n<text x=\"{}\" y=\"{}\" text-anchor=\"middle\">{label}</text>\n",
+                MARGIN - 4,
+                MARGIN - 10,
+                centre + 3
+            ));
+        }
+        for row in 0..GRID {
+            for column in (0..GRID).filter(|&column| self.punched(row, column)) {
+                svg.push_str(&format!(
+                    "<rect x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" fill=\"#fff\"/>\n",
+                    MARGIN + column * SQUARE + 2,
+                    MARGIN + row * SQUARE + 2,
+                    SQUARE - 4,
+                    SQUARE - 4
+                ));
+            }
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    /// The page content stream of the sheet on A4, 10 pt to a square.
+    fn pdf_content(&self) -> String {
+        const SQUARE: usize = 10;
+        const LEFT: usize = 42;
+        const TOP: usize = 780;
+        let bottom = TOP - GRID * SQUARE;
+        let mut content = format!(
+            "0 g BT /F1 12 Tf {LEFT} {} Td ({}) Tj ET\n0.78 g {LEFT} {bottom} {side} {side} re f\n",
+            TOP + 24,
+            pdf_escape(&self.title()),
+            side = GRID * SQUARE
+        );
+        content.push_str("0 g BT /F1 7 Tf\n");
+        for line in 0..GRID {
+            let label = (b'A' + (line % LETTERS) as u8) as char;
+            content.push_str(&format!(
+                "1 0 0 1 {} {} Tm ({label}) Tj 1 0 0 1 {} {} Tm ({label}) Tj\n",
+                LEFT + line * SQUARE + 3,
+                TOP + 4,
+                LEFT - 9,
+                TOP - line * SQUARE - 8
+            ));
+        }
+        content.push_str("ET\n1 g\n");
+        for row in 0..GRID {
+            for column in (0..GRID).filter(|&column| self.punched(row, column)) {
+                content.push_str(&format!(
+                    "{} {} {} {} re f\n",
+                    LEFT + column * SQUARE + 2,
+                    TOP - (row + 1) * SQUARE + 2,
+                    SQUARE - 4,
+                    SQUARE - 4
+                ));
+            }
+        }
+        content
+    }
+}
+
+/// A PDF with one A4 page per sheet.
+pub fn sheets_pdf(sheets: &[Sheet]) -> Vec<u8> {
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        String::new(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica >>".to_string(),
+    ];
+    let mut pages = Vec::new();
+    for sheet in sheets {
+        let content = sheet.pdf_content();
+        let page = objects.len() + 1;
+        pages.push(format!("{} 0 R", page));
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 595 842] /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+            page + 1
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}\nendstream",
+            content.len(),
+            content
+        ));
+    }
+    objects[1] = format!(
+        "<< /Type /Pages /Kids [{}] /Count {} >>",
+        pages.join(" "),
+        sheets.len()
+    );
+
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::new();
+    for (number, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", number + 1, object));
+    }
+    let xref = pdf.len();
+    pdf.push_str(&format!(
+        "xref\n0 {}\n0000000000 65535 f \n",
+        objects.len() + 1
+    ));
+    for offset in offsets {
+        pdf.push_str(&format!("{:010} 00000 n \n", offset));
+    }
+    pdf.push_str(&format!(
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref
+    ));
+    pdf.into_bytes()
+}
+
+/// Whether the product of key presses `pair + 1` and `pair + 1 + n` has a fixed point when the
+/// doubled key is keyed from window letters `start`.
+fn has_fixed_point(tables: &Stack, rings: &[u8], start: &[u8], pair: usize) -> bool {
+    let wheels = start.len();
+    let mut positions = start.to_vec();
+    let mut presses = vec![[0; LETTERS]; pair + 1 + wheels];
+    tables.presses(rings, &mut positions, &mut presses);
+    let (first, second) = (&presses[pair], &presses[pair + wheels]);
+    (0..LETTERS).any(|letter| second[first[letter] as usize] as usize == letter)
+}
+
+fn wheel_stack(order: &[&str]) -> Result<Vec<Rotor>, EnigmaError> {
+    order
+        .iter()
+        .map(|name| {
+            rotors::from_name(name, 'A', 'A')
+                .ok_or_else(|| EnigmaError::InvalidSearch(format!("unknown wheel {}", name)))
+        })
+        .collect()
+}
+
+/// Window letters (or rings) as indices, checked against the number of wheels.
+fn window(letters: &str, wheels: usize) -> Result<Vec<u8>, EnigmaError> {
+    let found = letters.chars().count();
+    if found != wheels {
+        return Err(EnigmaError::PositionCount {
+            expected: wheels,
+            found,
+        });
+    }
+    letters
+        .chars()
+        .map(|ch| {
+            ch.is_ascii_uppercase()
+                .then(|| ch as u8 - b'A')
+                .ok_or(EnigmaError::InvalidPosition(ch))
+        })
+        .collect()
+}
+
+/// Base-26 digits of `number`, most significant first.
+fn digits(mut number: usize, count: usize) -> Vec<u8> {
+    let mut digits = vec![0; count];
+    for digit in digits.iter_mut().rev() {
+        *digit = (number % LETTERS) as u8;
+        number /= LETTERS;
+    }
+    digits
+}
+
+fn letters(indices: &[u8]) -> String {
+    indices
+        .iter()
+        .map(|&index| (b'A' + index) as char)
+        .collect()
+}
+
+fn pdf_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('(', "\\(")
+        .replace(')', "\\)")
+}
+
+#[cfg(test)]
+mod zygalski_tests {
+    use super::*;
+    use crate::machine::EnigmaMachine;
+    use crate::plugboard::Plugboard;
+    use crate::procedure::{random_key, Procedure};
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    const RINGS: &str = "GMR";
+
+    /// A day of doubled indicators at random Grundstellungen, keyed through a plugboard.
+    fn indicators(count: usize) -> Vec<Indicator> {
+        let rotors = vec![
+            rotors::type_ii('A', 'G'),
+            rotors::type_v('A', 'M'),
+            rotors::type_iii('A', 'R'),
+        ];
+        let plugboard =
+            Plugboard::new(vec![('B', 'Q'), ('C', 'R'), ('D', 'I'), ('E', 'J')]).unwrap();
+        let mut machine = EnigmaMachine::new(rotors, reflectors::ukw_b(), plugboard);
+        let mut rng = StdRng::seed_from_u64(1938);
+        (0..count)
+            .map(|_| {
+                let grundstellung = random_key(&mut rng, 3);
+                let key = random_key(&mut rng, 3);
+                Procedure::DoubledIndicator
+                    .encrypt_indicator(&mut machine, &grundstellung, &key)
+                    .unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_females() {
+        let indicator = |grundstellung: &str, encrypted_key: &str| Indicator {
+            grundstellung: grundstellung.to_string(),
+            encrypted_key: encrypted_key.to_string(),
+        };
+        let found = females(&[
+            indicator("KWT", "PQRPSX"),
+            indicator("ABC", "XYZUVZ"),
+            indicator("DEF", "ABCDEF"),
+        ])
+        .unwrap();
+        assert_eq!(
+            found,
+            vec![
+                Female {
+                    grundstellung: "KWT".to_string(),
+                    pair: 0
+                },
+                Female {
+                    grundstellung: "ABC".to_string(),
+                    pair: 2
+                },
+            ]
+        );
+        assert_eq!(found[1].to_string(), "ABC 3-6");
+        assert!(females(&[indicator("KWT", "PQRP")]).is_err());
+    }
+
+    #[test]
+    fn test_search_finds_the_rings() {
+        let females = females(&indicators(300)).unwrap();
+        assert!(females.len() > 10);
+        let apertures = search_orders(&[vec!["ii", "v", "iii"]], "ukw_b", &females).unwrap();
+        assert!(apertures.iter().any(|aperture| aperture.rings == RINGS));
+        assert!(apertures.len() < 26 * 26 * 26 / 10);
+        assert_eq!(
+            apertures[0].to_string(),
+            format!("type II type V type III UKW-B rings {}", apertures[0].rings)
+        );
+
+        assert!(search_orders(&[vec!["ii", "x", "iii"]], "ukw_b", &females).is_err());
+    }
+
+    #[test]
+    fn test_sheets() {
+        let sheets = sheets(&["ii", "v", "iii"], "AAA", "ukw_b", 0).unwrap();
+        assert_eq!(sheets.len(), 26);
+        assert_eq!(sheets[3].prefix, "D");
+        let holes = sheets[3].holes();
+        assert!(holes > 0 && holes < 26 * 26);
+
+        let svg = sheets[3].to_svg();
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("type II type V type III rings AAA at D, presses 1 and 4"));
+        // The 26 x 26 holes drawn with 25 rows and columns repeated
+        let drawn = svg.matches("fill=\"#fff\"").count();
+        let repeated: usize = (0..GRID)
+            .flat_map(|row| (0..GRID).map(move |column| (row, column)))
+            .filter(|&(row, column)| sheets[3].punched(row, column))
+            .count();
+        assert_eq!(drawn, repeated);
+
+        let pdf = sheets_pdf(&sheets[..2]);
+        assert!(pdf.starts_with(b"%PDF-1.4"));
+        assert!(pdf.ends_with(b"%%EOF\n"));
+        assert_eq!(
+            String::from_utf8_lossy(&pdf)
+                .matches("/Type /Page ")
+                .count(),
+            2
+        );
+
+        assert!(super::sheets(&["ii", "v", "iii"], "AAA", "ukw_b", 3).is_err());
+        assert!(super::sheets(&["ii", "v", "iii"], "AA", "ukw_b", 0).is_err());
+    }
+}